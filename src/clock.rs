@@ -0,0 +1,103 @@
+use std::cmp::max;
+use std::fmt;
+use std::num::Int;
+use std::sync::{Arc, Mutex};
+use std::time::duration::Duration;
+use time::precise_time_ns;
+
+/// A point in time on the monotonic clock used by the event loop's timer.
+///
+/// Unlike wall clock time, the monotonic clock never steps when the system
+/// time is changed, so deadlines computed from an instant stay valid.
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MonotonicInstant {
+    ns: u64,
+}
+
+impl MonotonicInstant {
+    /// Returns the current time
+    pub fn now() -> MonotonicInstant {
+        MonotonicInstant { ns: precise_time_ns() }
+    }
+
+    /// Creates an instant from a number of nanoseconds on the monotonic
+    /// clock, as returned by `time::precise_time_ns`.
+    pub fn from_ns(ns: u64) -> MonotonicInstant {
+        MonotonicInstant { ns: ns }
+    }
+
+    pub fn as_ns(&self) -> u64 {
+        self.ns
+    }
+
+    /// Returns the instant `delay` after this one. Negative delays count as
+    /// zero.
+    pub fn after(&self, delay: Duration) -> MonotonicInstant {
+        let delay = max(0, delay.num_nanoseconds().unwrap_or(Int::max_value())) as u64;
+        MonotonicInstant { ns: self.ns + delay }
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant, zero if
+    /// `earlier` is later.
+    pub fn duration_since(&self, earlier: MonotonicInstant) -> Duration {
+        if earlier.ns >= self.ns {
+            return Duration::zero();
+        }
+
+        Duration::nanoseconds((self.ns - earlier.ns) as i64)
+    }
+}
+
+impl fmt::Show for MonotonicInstant {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "MonotonicInstant({}ns)", self.ns)
+    }
+}
+
+/// Source of the current time for an event loop's timer, see
+/// `EventLoop::with_clock`. Clocks are `Send`, so that the event loop can be
+/// moved to another thread.
+pub trait Clock : Send {
+    /// Current time in nanoseconds. Must never go backwards.
+    fn now_ns(&self) -> u64;
+}
+
+/// The system's monotonic clock, used by default.
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now_ns(&self) -> u64 {
+        precise_time_ns()
+    }
+}
+
+/// A clock that only moves when told to, for testing timeout handling
+/// deterministically. Clones share the same time, so one copy can be handed
+/// to the event loop while the test keeps another to advance it.
+#[deriving(Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<u64>>,
+}
+
+impl MockClock {
+    /// Creates a clock starting at the given time in nanoseconds
+    pub fn new(start_ns: u64) -> MockClock {
+        MockClock { now: Arc::new(Mutex::new(start_ns)) }
+    }
+
+    /// Moves the clock forward. Negative durations are ignored.
+    pub fn advance(&self, delta: Duration) {
+        let delta = max(0, delta.num_nanoseconds().unwrap_or(Int::max_value())) as u64;
+        *self.now.lock() += delta;
+    }
+
+    pub fn advance_ms(&self, delta: u64) {
+        *self.now.lock() += delta * 1_000_000;
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ns(&self) -> u64 {
+        *self.now.lock()
+    }
+}
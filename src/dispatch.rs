@@ -0,0 +1,321 @@
+use error::MioError;
+use event_loop::EventLoop;
+use handler::Handler;
+use os::token::Token;
+use os::event;
+use util::Slab;
+
+/// Handles the IO events of a single registration when used with a
+/// `Dispatcher`.
+///
+/// The callbacks get access to the dispatcher's `TokenHandlers`, so a handler
+/// can install handlers for new registrations (e.g. a listener for the
+/// connections it accepts) or remove itself.
+#[allow(unused_variables)]
+pub trait TokenHandler<T, M: Send> {
+    fn readable(&mut self, event_loop: &mut EventLoop<T, M>, handlers: &mut TokenHandlers<T, M>, token: Token, hint: event::ReadHint) {
+    }
+
+    fn writable(&mut self, event_loop: &mut EventLoop<T, M>, handlers: &mut TokenHandlers<T, M>, token: Token) {
+    }
+}
+
+/// The set of per-token handlers of a `Dispatcher`. Inserting a handler
+/// allocates the token the IO handle is to be registered with.
+pub struct TokenHandlers<T, M> {
+    // A slot is temporarily empty while its handler is being dispatched to
+    slab: Slab<Option<Box<TokenHandler<T, M> + 'static>>>,
+}
+
+impl<T, M: Send> TokenHandlers<T, M> {
+    fn new(offset: Token, capacity: uint) -> TokenHandlers<T, M> {
+        TokenHandlers { slab: Slab::new_starting_at(offset, capacity) }
+    }
+
+    /// Stores the handler, returning the token it is dispatched for. Fails
+    /// with the handler when the capacity is exhausted.
+    pub fn insert(&mut self, handler: Box<TokenHandler<T, M> + 'static>) -> Result<Token, Box<TokenHandler<T, M> + 'static>> {
+        match self.slab.insert(Some(handler)) {
+            Ok(token) => Ok(token),
+            Err(handler) => Err(handler.unwrap()),
+        }
+    }
+
+    /// Removes the handler of the given token. Returns `None` when the token
+    /// has no handler, or when a handler removes itself during dispatch.
+    pub fn remove(&mut self, token: Token) -> Option<Box<TokenHandler<T, M> + 'static>> {
+        match self.slab.remove(token) {
+            Some(handler) => handler,
+            None => None,
+        }
+    }
+
+    pub fn contains(&self, token: Token) -> bool {
+        self.slab.contains(token)
+    }
+
+    pub fn len(&self) -> uint {
+        self.slab.count()
+    }
+
+    fn take(&mut self, token: Token) -> Option<Box<TokenHandler<T, M> + 'static>> {
+        match self.slab.get_mut(token) {
+            Some(slot) => slot.take(),
+            None => None,
+        }
+    }
+
+    fn restore(&mut self, token: Token, handler: Box<TokenHandler<T, M> + 'static>) {
+        // The slot is gone, or was reused, when the handler removed itself
+        match self.slab.get_mut(token) {
+            Some(slot) if slot.is_none() => *slot = Some(handler),
+            _ => {}
+        }
+    }
+}
+
+/// A `Handler` routing the IO events of each token to the `TokenHandler`
+/// stored for it, instead of matching on the token in a single handler.
+///
+/// IO events for tokens without a `TokenHandler`, as well as all other
+/// callbacks, are passed on to the wrapped handler.
+pub struct Dispatcher<H, T, M> {
+    handlers: TokenHandlers<T, M>,
+    inner: H,
+}
+
+impl<T, M: Send, H: Handler<T, M>> Dispatcher<H, T, M> {
+    /// Creates a dispatcher storing at most `capacity` token handlers, with
+    /// tokens allocated from `Token(0)`.
+    pub fn new(inner: H, capacity: uint) -> Dispatcher<H, T, M> {
+        Dispatcher::new_starting_at(inner, Token(0), capacity)
+    }
+
+    /// Creates a dispatcher allocating tokens from `offset`, leaving the
+    /// tokens below it to the wrapped handler.
+    pub fn new_starting_at(inner: H, offset: Token, capacity: uint) -> Dispatcher<H, T, M> {
+        Dispatcher {
+            handlers: TokenHandlers::new(offset, capacity),
+            inner: inner,
+        }
+    }
+
+    pub fn handlers(&mut self) -> &mut TokenHandlers<T, M> {
+        &mut self.handlers
+    }
+
+    pub fn inner(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    pub fn unwrap(self) -> H {
+        self.inner
+    }
+}
+
+impl<T, M: Send, H: Handler<T, M>> Handler<T, M> for Dispatcher<H, T, M> {
+    fn ready(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, events: event::IoEvent) {
+        if !self.handlers.contains(token) {
+            return self.inner.ready(event_loop, token, events);
+        }
+
+        if events.is_readable() {
+            self.readable(event_loop, token, events.read_hint());
+        }
+
+        if events.is_writable() && !event_loop.is_deregistered(token) {
+            self.writable(event_loop, token);
+        }
+    }
+
+    fn readable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, hint: event::ReadHint) {
+        match self.handlers.take(token) {
+            Some(mut handler) => {
+                handler.readable(event_loop, &mut self.handlers, token, hint);
+                self.handlers.restore(token, handler);
+            }
+            None => self.inner.readable(event_loop, token, hint),
+        }
+    }
+
+    fn writable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+        match self.handlers.take(token) {
+            Some(mut handler) => {
+                handler.writable(event_loop, &mut self.handlers, token);
+                self.handlers.restore(token, handler);
+            }
+            None => self.inner.writable(event_loop, token),
+        }
+    }
+
+    fn notify(&mut self, event_loop: &mut EventLoop<T, M>, msg: M) {
+        self.inner.notify(event_loop, msg)
+    }
+
+    fn timeout(&mut self, event_loop: &mut EventLoop<T, M>, timeout: T) {
+        self.inner.timeout(event_loop, timeout)
+    }
+
+    fn error(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, error: MioError) {
+        self.inner.error(event_loop, token, error)
+    }
+
+    fn error_queue(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+        self.inner.error_queue(event_loop, token)
+    }
+
+    fn tick(&mut self, event_loop: &mut EventLoop<T, M>) {
+        self.inner.tick(event_loop)
+    }
+
+    fn deferred(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+        self.inner.deferred(event_loop, token)
+    }
+
+    fn idle(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+        self.inner.idle(event_loop, token)
+    }
+
+    fn signaled(&mut self, event_loop: &mut EventLoop<T, M>) {
+        self.inner.signaled(event_loop)
+    }
+
+    fn shutdown(&mut self, event_loop: &mut EventLoop<T, M>) {
+        self.inner.shutdown(event_loop)
+    }
+
+    fn notify_dropped(&mut self, event_loop: &mut EventLoop<T, M>, msg: M) {
+        self.inner.notify_dropped(event_loop, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::{Dispatcher, TokenHandler, TokenHandlers};
+    use event_loop::EventLoop;
+    use handler::Handler;
+    use io::{pipe, IoWriter, PipeReader};
+    use os::token::Token;
+    use os::event;
+    use buf;
+
+    type TestEventLoop = EventLoop<uint, ()>;
+    type Log = Rc<RefCell<Vec<(&'static str, Token)>>>;
+
+    struct Inner {
+        log: Log
+    }
+
+    impl Handler<uint, ()> for Inner {
+        fn readable(&mut self, _event_loop: &mut TestEventLoop, token: Token, _hint: event::ReadHint) {
+            self.log.borrow_mut().push(("inner", token));
+        }
+    }
+
+    // Removes itself on its first event
+    struct Once {
+        log: Log
+    }
+
+    impl TokenHandler<uint, ()> for Once {
+        fn readable(&mut self, _event_loop: &mut TestEventLoop, handlers: &mut TokenHandlers<uint, ()>, token: Token, _hint: event::ReadHint) {
+            self.log.borrow_mut().push(("once", token));
+            assert!(handlers.remove(token).is_none());
+        }
+    }
+
+    // Installs a handler for another pipe on its first event
+    struct Spawner {
+        reader: Option<PipeReader>,
+        spawned: Option<(Token, PipeReader)>,
+        log: Log
+    }
+
+    impl TokenHandler<uint, ()> for Spawner {
+        fn readable(&mut self, event_loop: &mut TestEventLoop, handlers: &mut TokenHandlers<uint, ()>, token: Token, _hint: event::ReadHint) {
+            self.log.borrow_mut().push(("spawner", token));
+
+            match self.reader.take() {
+                Some(reader) => {
+                    let spawned = handlers.insert(box Recorder { log: self.log.clone() })
+                        .ok().expect("capacity exhausted");
+
+                    event_loop.register(&reader, spawned).unwrap();
+                    self.spawned = Some((spawned, reader));
+                }
+                None => {}
+            }
+        }
+    }
+
+    struct Recorder {
+        log: Log
+    }
+
+    impl TokenHandler<uint, ()> for Recorder {
+        fn readable(&mut self, _event_loop: &mut TestEventLoop, _handlers: &mut TokenHandlers<uint, ()>, token: Token, _hint: event::ReadHint) {
+            self.log.borrow_mut().push(("recorder", token));
+        }
+    }
+
+    #[test]
+    fn test_routing() {
+        let mut event_loop: TestEventLoop = EventLoop::new().unwrap();
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let mut dispatcher = Dispatcher::new_starting_at(Inner { log: log.clone() }, Token(10), 16);
+
+        let (r1, mut w1) = pipe().unwrap();
+        let (r2, mut w2) = pipe().unwrap();
+        let (r3, mut w3) = pipe().unwrap();
+
+        let once = dispatcher.handlers().insert(box Once { log: log.clone() })
+            .ok().expect("capacity exhausted");
+        let spawner = dispatcher.handlers().insert(box Spawner { reader: Some(r3), spawned: None, log: log.clone() })
+            .ok().expect("capacity exhausted");
+
+        // Tokens below the offset go to the wrapped handler
+        let (r0, mut w0) = pipe().unwrap();
+        event_loop.register(&r0, Token(1)).unwrap();
+        w0.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.run_once(&mut dispatcher).unwrap();
+        assert_eq!(log.borrow().as_slice(), [("inner", Token(1))].as_slice());
+        event_loop.deregister(&r0).unwrap();
+        log.borrow_mut().clear();
+
+        // The handler is dispatched to and removes itself, further events for
+        // its token go to the wrapped handler
+        event_loop.register(&r1, once).unwrap();
+        w1.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.run_once(&mut dispatcher).unwrap();
+        assert!(!dispatcher.handlers().contains(once));
+        assert_eq!(dispatcher.handlers().len(), 1);
+
+        event_loop.run_once(&mut dispatcher).unwrap();
+        assert_eq!(log.borrow().as_slice(), [("once", once), ("inner", once)].as_slice());
+        event_loop.deregister(&r1).unwrap();
+        log.borrow_mut().clear();
+
+        // The handler installs another one from within its callback, which
+        // receives the events of its own registration
+        event_loop.register(&r2, spawner).unwrap();
+        w2.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        w3.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.run_once(&mut dispatcher).unwrap();
+        assert!(dispatcher.handlers().contains(spawner));
+        assert_eq!(dispatcher.handlers().len(), 2);
+        event_loop.deregister(&r2).unwrap();
+        log.borrow_mut().clear();
+
+        event_loop.run_once(&mut dispatcher).unwrap();
+        let (name, spawned) = {
+            let log = log.borrow();
+            assert_eq!(log.len(), 1);
+            log[0]
+        };
+        assert_eq!(name, "recorder");
+        assert!(spawned != spawner);
+        assert!(dispatcher.handlers().contains(spawned));
+    }
+}
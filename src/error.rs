@@ -1,5 +1,7 @@
 use std::io;
-use nix::errno::{SysError, EAGAIN, EADDRINUSE};
+use std::num::FromPrimitive;
+use nix::c_int;
+use nix::errno::{SysError, EAGAIN, EADDRINUSE, UnknownErrno};
 
 use self::MioErrorKind::{
     Eof,
@@ -8,6 +10,7 @@ use self::MioErrorKind::{
     WouldBlock,
     AddrInUse,
     EventLoopTerminated,
+    NotRegistered,
     OtherError
 };
 
@@ -27,6 +30,7 @@ pub enum MioErrorKind {
     BufUnderflow,           // Buf does not contain enough data to perform read op
     BufOverflow,            // Buf does not contain enough capacity to perform write op
     EventLoopTerminated,    // The event loop is not running anymore
+    NotRegistered,          // The IO handle is not registered with the event loop
     OtherError,             // System error not covered by other kinds
 }
 
@@ -52,6 +56,20 @@ impl MioError {
         }
     }
 
+    pub fn not_registered() -> MioError {
+        MioError {
+            kind: NotRegistered,
+            sys: None
+        }
+    }
+
+    /// Creates an error from a raw errno value, e.g. as returned by the
+    /// `SO_ERROR` socket option.
+    pub fn from_errno(errno: c_int) -> MioError {
+        let kind = FromPrimitive::from_i64(errno as i64).unwrap_or(UnknownErrno);
+        MioError::from_sys_error(SysError { kind: kind })
+    }
+
     pub fn from_sys_error(err: SysError) -> MioError {
         let kind = match err.kind {
             EAGAIN => WouldBlock,
@@ -104,7 +122,8 @@ impl MioError {
                 Some(err) => io::IoError::from_errno(err.kind as uint, false),
                 None => io::standard_error(io::OtherIoError)
             },
-            EventLoopTerminated => io::standard_error(OtherIoError)
+            EventLoopTerminated => io::standard_error(OtherIoError),
+            NotRegistered => io::standard_error(io::InvalidInput)
         }
     }
 }
@@ -1,13 +1,25 @@
+use std::mem;
+use std::cmp::max;
+use std::num::Int;
 use std::default::Default;
 use std::time::duration::Duration;
 use std::uint;
+use std::any::Any;
+use std::sync::{Arc, Future, Mutex};
+use std::sync::atomic::{AtomicBool, SeqCst};
+use std::task::TaskBuilder;
+use clock::{Clock, MonotonicClock, MonotonicInstant};
 use error::{MioResult, MioError};
 use handler::Handler;
 use io::IoHandle;
-use notify::Notify;
+use notify::{BatchError, Notify, NotifyError, NotifyStats, Signal, SignalWaker, Waker};
+use os;
 use os::event;
-use poll::{Poll};
+use poll::{Events, Poll};
 use timer::{Timer, Timeout, TimerResult};
+use nix::errno::{EINVAL, SysError};
+use nix::fcntl::Fd;
+use time::precise_time_ns;
 use os::token::Token;
 
 /// A lightweight event loop.
@@ -15,29 +27,61 @@ use os::token::Token;
 /// TODO:
 /// - Enforce private tokens
 
+/// Tunes the event loop, pass to `EventLoop::configured`. Start from
+/// `Default::default()` and override the fields that need changing.
 #[deriving(Clone, Show)]
 pub struct EventLoopConfig {
+    // == IO ==
+    // Maximum time to block waiting for IO events
     pub io_poll_timeout_ms: uint,
+    // Maximum number of IO events dispatched per tick
+    pub io_events_per_tick: uint,
+    // Fill in `IoEvent::available` for readable events on selectors that do
+    // not report it (epoll), at the cost of a syscall or two per event. Not
+    // filled in for tokens shared by several handles.
+    pub read_available: bool,
 
     // == Notifications ==
     pub notify_capacity: uint,
+    // When set, `notify_capacity` is ignored and the queue grows as needed
+    pub notify_unbounded: bool,
     pub messages_per_tick: uint,
 
     // == Timer ==
+    // Timer granularity
     pub timer_tick_ms: u64,
+    // Number of slots in the timer wheel, rounded up to a power of two. Best
+    // sized to cover the typical timeout duration in ticks.
     pub timer_wheel_size: uint,
+    // Maximum number of pending timeouts, rounded up to a power of two
     pub timer_capacity: uint,
+    // Wake up for timeouts with a timerfd (an `EVFILT_TIMER` kqueue on OS X)
+    // armed at the exact time of the next tick, or of the next precise
+    // timeout, instead of the millisecond poll timeout. Useful with small
+    // `timer_tick_ms` values, as the poll timeout is rounded and recomputed
+    // after each dispatch, and required for sub-millisecond precise
+    // timeouts.
+    pub timer_fd: bool,
+    // Window, in ms, within which timer wake-ups are coalesced by delaying
+    // them to the next multiple of the window. Saves wake-ups at the cost of
+    // firing timeouts up to `timer_slack_ms` late. Zero disables coalescing.
+    pub timer_slack_ms: u64,
 }
 
 impl Default for EventLoopConfig {
     fn default() -> EventLoopConfig {
         EventLoopConfig {
             io_poll_timeout_ms: 1_000,
+            io_events_per_tick: 1_024,
+            read_available: false,
             notify_capacity: 1_024,
+            notify_unbounded: false,
             messages_per_tick: 64,
             timer_tick_ms: 100,
             timer_wheel_size: 1_024,
             timer_capacity: 65_536,
+            timer_fd: false,
+            timer_slack_ms: 0,
         }
     }
 }
@@ -45,14 +89,47 @@ impl Default for EventLoopConfig {
 pub struct EventLoop<T, M: Send> {
     run: bool,
     poll: Poll,
+    // Filled in place by each poll, never reallocated
+    events: Events,
     timer: Timer<T>,
+    timer_fd: Option<os::TimerFd>,
+    // Deadline the timer fd is currently armed for, 0 when it is not
+    timer_armed: u64,
     notify: Notify<M>,
+    signal: Signal,
+    // Set by `ShutdownHandle` from other threads
+    shutdown: Arc<AtomicBool>,
+    // Registration changes queued by `Registry` handles
+    registry: Arc<RegistryInner>,
+    // Tokens passed to `Handler::idle` when the loop is about to sleep
+    idle: Vec<Token>,
+    // Number of IO events dispatched during the previous tick
+    prev_events: uint,
+    // Tokens passed to `Handler::deferred` at the start of the next tick
+    deferred: Vec<Token>,
+    // Spare buffer swapped with `deferred` while dispatching
+    deferred_spare: Vec<Token>,
+    stats: EventLoopStats,
+    // True while IO events are being dispatched to the handler
+    dispatching: bool,
+    // Descriptors deregistered during dispatch and the token they were
+    // registered with, removed from the selector once dispatch completes.
+    // Remaining events for a token are suppressed once all of its
+    // descriptors are in here. The descriptor is None when it was removed
+    // early because it got registered again.
+    dead_fds: Vec<(Option<Fd>, Token)>,
     config: EventLoopConfig,
 }
 
 // Token used to represent notifications
 const NOTIFY: Token = Token(uint::MAX);
 
+// Token used to represent signal wake-ups
+const SIGNAL: Token = Token(uint::MAX - 1);
+
+// Token used to represent timer fd expirations
+const TIMER: Token = Token(uint::MAX - 2);
+
 impl<T, M: Send> EventLoop<T, M> {
     /// Initializes a new event loop. The event loop will not be running yet.
     pub fn new() -> MioResult<EventLoop<T, M>> {
@@ -60,20 +137,55 @@ impl<T, M: Send> EventLoop<T, M> {
     }
 
     pub fn configured(config: EventLoopConfig) -> MioResult<EventLoop<T, M>> {
+        EventLoop::build(config, box MonotonicClock)
+    }
+
+    /// Creates an event loop whose timer reads the time from the given
+    /// clock. With a `MockClock`, tests can advance time manually and drive
+    /// the loop with `run_pending` to check which timeouts fire.
+    ///
+    /// Fails with `EINVAL` when `EventLoopConfig::timer_fd` is set, as the
+    /// timer fd is armed with deadlines on the system's monotonic clock.
+    pub fn with_clock(config: EventLoopConfig, clock: Box<Clock + Send>) -> MioResult<EventLoop<T, M>> {
+        if config.timer_fd {
+            return Err(MioError::from_sys_error(SysError { kind: EINVAL }));
+        }
+
+        EventLoop::build(config, clock)
+    }
+
+    fn build(config: EventLoopConfig, clock: Box<Clock + Send>) -> MioResult<EventLoop<T, M>> {
         // Create the IO poller
         let mut poll = try!(Poll::new());
+        let events = Events::with_capacity(config.io_events_per_tick);
 
         // Create the timer
-        let mut timer = Timer::new(
+        let mut timer = Timer::with_clock(
             config.timer_tick_ms,
             config.timer_wheel_size,
-            config.timer_capacity);
+            config.timer_capacity,
+            clock);
+
+        timer.set_slack_ms(config.timer_slack_ms);
 
         // Create cross thread notification queue
-        let notify = try!(Notify::with_capacity(config.notify_capacity));
+        let notify = if config.notify_unbounded {
+            try!(Notify::unbounded())
+        } else {
+            try!(Notify::with_capacity(config.notify_capacity))
+        };
+
+        let signal = try!(Signal::new());
+
+        let timer_fd = if config.timer_fd {
+            Some(try!(os::TimerFd::new()))
+        } else {
+            None
+        };
 
-        // Register the notification wakeup FD with the IO poller
-        try!(poll.register(&notify, NOTIFY, event::READABLE | event::WRITABLE, event::EDGE));
+        // Register the notification, signal and timer wake-up FDs with the
+        // IO poller
+        try!(register_internal(&mut poll, &notify, &signal, &timer_fd));
 
         // Set the timer's starting time reference point
         timer.setup();
@@ -81,8 +193,21 @@ impl<T, M: Send> EventLoop<T, M> {
         Ok(EventLoop {
             run: true,
             poll: poll,
+            events: events,
             timer: timer,
+            timer_fd: timer_fd,
+            timer_armed: 0,
             notify: notify,
+            signal: signal,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            registry: Arc::new(RegistryInner::new()),
+            idle: Vec::new(),
+            prev_events: 0,
+            deferred: Vec::new(),
+            deferred_spare: Vec::new(),
+            stats: Default::default(),
+            dispatching: false,
+            dead_fds: Vec::new(),
             config: config,
         })
     }
@@ -93,92 +218,461 @@ impl<T, M: Send> EventLoop<T, M> {
         EventLoopSender::new(self.notify.clone())
     }
 
+    /// Returns the wake-up statistics of the event loop's notify queue.
+    pub fn notify_stats(&self) -> NotifyStats {
+        self.notify.stats()
+    }
+
+    /// Returns the event loop's runtime counters.
+    pub fn stats(&self) -> &EventLoopStats {
+        &self.stats
+    }
+
+    /// Returns a handle that can wake up the event loop from another thread
+    /// without sending a message.
+    pub fn waker(&self) -> Waker {
+        self.notify.waker()
+    }
+
+    /// Returns a handle that can wake up the event loop from a Unix signal
+    /// handler, see `SignalWaker`.
+    pub fn signal_waker(&self) -> SignalWaker {
+        self.signal.waker()
+    }
+
     /// After the requested time interval, the handler's `timeout` function
     /// will be called with the supplied token.
     pub fn timeout(&mut self, token: T, delay: Duration) -> TimerResult<Timeout> {
         self.timer.timeout(token, delay)
     }
 
+    /// Same as `timeout`, with the delay given in milliseconds.
+    pub fn timeout_ms(&mut self, token: T, delay: u64) -> TimerResult<Timeout> {
+        self.timer.timeout_ms(token, delay)
+    }
+
+    /// Calls the handler's `timeout` function with the supplied token once
+    /// the monotonic clock reaches `at`. Deadlines in the past trigger on the
+    /// next timer tick.
+    pub fn timeout_at(&mut self, token: T, at: MonotonicInstant) -> TimerResult<Timeout> {
+        self.timer.timeout_at(token, at)
+    }
+
+    /// Calls the handler's `timeout` function with the supplied token once
+    /// `delay` has elapsed, with nanosecond resolution instead of on the next
+    /// timer tick. Precise timeouts are kept sorted outside of the timer
+    /// wheel, so they are meant for a few deadlines that must be met
+    /// accurately, not for per connection timeouts.
+    ///
+    /// The event loop only wakes up at the exact deadline when
+    /// `EventLoopConfig::timer_fd` is set, otherwise the poll timeout rounds
+    /// it up to the next millisecond. Slack does not apply.
+    pub fn timeout_precise(&mut self, token: T, delay: Duration) -> TimerResult<Timeout> {
+        let delay = max(0, delay.num_nanoseconds().unwrap_or(Int::max_value())) as u64;
+        self.timer.timeout_ns(token, delay)
+    }
+
+    /// Same as `timeout_precise`, triggering once the monotonic clock
+    /// reaches `at`. Deadlines in the past trigger on the next iteration.
+    pub fn timeout_at_precise(&mut self, token: T, at: MonotonicInstant) -> TimerResult<Timeout> {
+        self.timer.timeout_at_ns(token, at.as_ns())
+    }
+
     /// If the supplied timeout has not been triggered, cancel it such that it
     /// will not be triggered in the future.
     pub fn clear_timeout(&mut self, timeout: Timeout) -> bool {
         self.timer.clear(timeout)
     }
 
+    /// If the supplied timeout has not been triggered, reschedule it to
+    /// trigger after the requested time interval instead. Returns the handle
+    /// to use for the rescheduled timeout, or `None` if the timeout already
+    /// triggered or was cleared.
+    ///
+    /// The timeout keeps its slot in the timer, so resetting is as cheap as
+    /// setting a new timeout.
+    pub fn reset_timeout(&mut self, timeout: Timeout, delay: Duration) -> Option<Timeout> {
+        self.timer.reset(timeout, delay)
+    }
+
+    /// Same as `reset_timeout`, with the delay given in milliseconds.
+    pub fn reset_timeout_ms(&mut self, timeout: Timeout, delay: u64) -> Option<Timeout> {
+        self.timer.reset_ms(timeout, delay)
+    }
+
     /// Tells the event loop to exit after it is done handling all events in the
     /// current iteration.
     pub fn shutdown(&mut self) {
         self.run = false;
     }
 
+    /// Returns a handle that can stop the event loop from any thread. It may be
+    /// obtained before the event loop starts running.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shutdown: self.shutdown.clone(),
+            waker: self.notify.waker()
+        }
+    }
+
+    /// Sets the signal mask in effect while the event loop blocks waiting for
+    /// IO (epoll_pwait). Signals blocked in the thread's mask but not in this
+    /// one are only delivered while the event loop sleeps, so their handlers
+    /// never interrupt a callback. `None` keeps the thread's mask. A signal
+    /// interrupting the wait does not stop the loop, it looks like a wake-up
+    /// without events.
+    ///
+    /// On kqueue platforms the mask is only swapped in around `kevent`, which
+    /// is not race-free: a signal arriving just before the event loop blocks
+    /// does not wake it up. Have the signal handler call `SignalWaker::wakeup`
+    /// to make sure the event loop notices the signal.
+    pub fn set_poll_sigmask(&mut self, sigmask: Option<os::SigSet>) {
+        self.poll.set_sigmask(sigmask);
+    }
+
+    /// Returns a handle that can register IO with the event loop from other
+    /// threads, see `Registry`.
+    pub fn registry(&self) -> Registry {
+        Registry {
+            inner: self.registry.clone(),
+            waker: self.notify.waker()
+        }
+    }
+
     /// Registers an IO handle with the event loop.
     pub fn register<H: IoHandle>(&mut self, io: &H, token: Token) -> MioResult<()> {
-        self.poll.register(io, token, event::READABLE, event::LEVEL)
+        self.register_opt(io, token, event::READABLE, event::LEVEL)
     }
 
     /// Registers an IO handle with the event loop.
     pub fn register_opt<H: IoHandle>(&mut self, io: &H, token: Token, interest: event::Interest, opt: event::PollOpt) -> MioResult<()> {
+        self.release_guards();
+        self.flush_deregistration(io.desc().fd);
         self.poll.register(io, token, interest, opt)
     }
 
-    /// Re-Registers an IO handle with the event loop.
+    /// Registers an IO handle with the event loop, returning a guard that
+    /// deregisters it when dropped, see `RegistrationGuard`.
+    pub fn register_guarded<H: IoHandle>(&mut self, io: &H, token: Token, interest: event::Interest, opt: event::PollOpt) -> MioResult<RegistrationGuard> {
+        try!(self.register_opt(io, token, interest, opt));
+
+        Ok(RegistrationGuard {
+            inner: self.registry.clone(),
+            fd: io.desc().fd,
+            token: token
+        })
+    }
+
+    /// Schedules a call to the handler's `deferred` function with the supplied
+    /// token at the start of the next event loop iteration, before polling
+    /// for IO.
+    pub fn defer(&mut self, token: Token) {
+        self.deferred.push(token);
+    }
+
+    /// Registers an idle callback. The handler's `idle` function will be
+    /// called with the supplied token whenever the event loop is about to
+    /// block waiting for IO. Idle callbacks are skipped while the event loop
+    /// is busy, i.e. when messages are pending or the previous iteration
+    /// dispatched IO events.
+    pub fn register_idle(&mut self, token: Token) {
+        if !self.idle.contains(&token) {
+            self.idle.push(token);
+        }
+    }
+
+    /// Removes an idle callback, returns false if the token was not
+    /// registered.
+    pub fn deregister_idle(&mut self, token: Token) -> bool {
+        match self.idle.iter().position(|t| *t == token) {
+            Some(idx) => {
+                self.idle.remove(idx);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Re-Registers an IO handle with the event loop, replacing the token,
+    /// interest and options of its registration in place (`EPOLL_CTL_MOD`,
+    /// or a batched kevent change). Cheap enough to toggle write interest
+    /// whenever an output buffer fills up or drains, and required to re-arm
+    /// oneshot registrations.
     pub fn reregister<H: IoHandle>(&mut self, io: &H, token: Token, interest: event::Interest, opt: event::PollOpt) -> MioResult<()> {
+        self.release_guards();
+        self.flush_deregistration(io.desc().fd);
         self.poll.reregister(io, token, interest, opt)
     }
 
     /// Keep spinning the event loop indefinitely, and notify the handler whenever
     /// any of the registered handles are ready.
+    ///
+    /// Once shutdown is requested, either with `shutdown` or through a
+    /// `ShutdownHandle`, the current iteration completes, the handler's
+    /// `shutdown` hook is invoked, pending messages are handed to
+    /// `notify_dropped` and all IO handles are deregistered.
     pub fn run<H: Handler<T, M>>(&mut self, mut handler: H) -> EventLoopResult<H> {
         self.run = true;
 
-        while self.run {
+        while self.is_running() {
             // Execute ticks as long as the event loop is running
-            match self.tick(&mut handler) {
+            match self.tick(&mut handler, true) {
                 Err(e) => return Err(EventLoopError::new(handler, e)),
                 _ => {}
             }
         }
 
+        handler.shutdown(self);
+
+        // Hand any undelivered messages back to the handler
+        self.drain(&mut handler);
+
+        match self.deregister_all() {
+            Err(e) => return Err(EventLoopError::new(handler, e)),
+            _ => {}
+        }
+
+        self.shutdown.store(false, SeqCst);
+
         Ok(handler)
     }
 
+    /// Returns false once shutdown has been requested.
+    pub fn is_running(&self) -> bool {
+        self.run && !self.shutdown.load(SeqCst)
+    }
+
     /// Deregisters an IO handle with the event loop.
+    ///
+    /// When called while IO events are being dispatched, any remaining
+    /// events for the handle in the current iteration are discarded and the
+    /// handle is removed from the selector once dispatching completes.
     pub fn deregister<H: IoHandle>(&mut self, io: &H) -> MioResult<()> {
+        if self.dispatching {
+            self.deregister_later(io.desc().fd);
+            return Ok(());
+        }
+
         self.poll.deregister(io)
     }
 
-    /// Spin the event loop once, with a timeout of one second, and notify the
-    /// handler if any of the registered handles become ready during that
-    /// time.
-    pub fn run_once<H: Handler<T, M>>(&mut self, mut handler: H) -> EventLoopResult<H> {
+    /// Deregisters an IO handle and packages it with its current interest,
+    /// so that it can be sent to another event loop, for example through its
+    /// notify channel, and registered there with `import`.
+    pub fn deregister_and_export<H: IoHandle>(&mut self, io: H) -> MioResult<ExportedIo<H>> {
+        let reg = match self.poll.registration(io.desc().fd) {
+            Some(reg) => reg,
+            None => return Err(MioError::not_registered())
+        };
+
+        try!(self.deregister(&io));
+
+        Ok(ExportedIo {
+            io: io,
+            interest: reg.interest,
+            opts: reg.opts
+        })
+    }
+
+    /// Registers an IO handle exported from another event loop with the given
+    /// token, re-arming the interest it had there. Returns the handle so the
+    /// caller can take ownership of it.
+    pub fn import<H: IoHandle>(&mut self, exported: ExportedIo<H>, token: Token) -> MioResult<H> {
+        let ExportedIo { io, interest, opts } = exported;
+        try!(self.register_opt(&io, token, interest, opts));
+        Ok(io)
+    }
+
+    fn deregister_later(&mut self, fd: Fd) {
+        if self.is_dead_fd(fd) {
+            return;
+        }
+
+        match self.poll.token_for(fd) {
+            Some(token) => self.dead_fds.push((Some(fd), token)),
+            None => debug!("deregistering unknown fd while dispatching; fd={}", fd)
+        }
+    }
+
+    fn is_dead_fd(&self, fd: Fd) -> bool {
+        self.dead_fds.iter().any(|&(f, _)| f == Some(fd))
+    }
+
+    /// Returns true if the token was deregistered while the IO events of the
+    /// current iteration are being dispatched. Its remaining events are
+    /// discarded, a handler overriding `ready` should stop handling the
+    /// current one as well. When several handles share the token, it only
+    /// counts as deregistered once all of them are.
+    pub fn is_deregistered(&self, token: Token) -> bool {
+        if !self.dispatching {
+            return false;
+        }
+
+        let fds = self.poll.fds_for(token);
+
+        // A token without descriptors is unknown to the poller, unless its
+        // descriptor was removed early to be registered again
+        if fds.is_empty() {
+            return self.dead_fds.iter().any(|&(_, t)| t == token);
+        }
+
+        fds.iter().all(|fd| self.is_dead_fd(*fd))
+    }
+
+    // If the descriptor has a pending deregistration, perform it now, so that
+    // it does not clobber a new registration.
+    fn flush_deregistration(&mut self, fd: Fd) {
+        match self.dead_fds.iter().position(|&(f, _)| f == Some(fd)) {
+            Some(idx) => {
+                // Keep the token, its remaining events are still discarded
+                let (_, token) = self.dead_fds[idx];
+                self.dead_fds[idx] = (None, token);
+
+                // The descriptor may have been closed and reused by the
+                // handle being registered, closing removed it from the
+                // selector already.
+                match self.poll.deregister_fd(fd) {
+                    Err(e) => debug!("deferred deregistration failed; fd={}; err={}", fd, e),
+                    _ => {}
+                }
+            }
+            None => {}
+        }
+    }
+
+    // Applies the deregistrations of dropped `RegistrationGuard`s. Runs
+    // before any registration, because the guarded descriptor may have been
+    // closed and reused since, and at the end of each dispatch.
+    fn release_guards(&mut self) {
+        if !self.registry.released.load(SeqCst) {
+            return;
+        }
+
+        let releases = {
+            let mut releases = self.registry.releases.lock();
+            self.registry.released.store(false, SeqCst);
+            mem::replace(&mut *releases, Vec::new())
+        };
+
+        for &(fd, token) in releases.iter() {
+            // Skip descriptors registered with a different token since
+            if self.poll.token_for(fd) != Some(token) {
+                continue;
+            }
+
+            if self.dispatching {
+                self.deregister_later(fd);
+            } else {
+                let _ = self.poll.deregister_fd(fd);
+            }
+        }
+    }
+
+    fn flush_deregistrations(&mut self) {
+        loop {
+            let fd = match self.dead_fds.pop() {
+                Some((Some(fd), _)) => fd,
+                // Already removed when the descriptor was registered again
+                Some((None, _)) => continue,
+                None => break
+            };
+
+            // The descriptor may have been closed already, in which case the
+            // OS has removed it from the selector.
+            match self.poll.deregister_fd(fd) {
+                Err(e) => debug!("deferred deregistration failed; fd={}; err={}", fd, e),
+                _ => {}
+            }
+        }
+    }
+
+    /// Spin the event loop once and notify the handler if any of the
+    /// registered handles become ready, any messages are pending or any
+    /// timeouts have fired. Blocks for at most `io_poll_timeout_ms`, or less
+    /// if a timeout is due sooner.
+    ///
+    /// The handler is borrowed, so the event loop can be driven from an outer
+    /// loop that retains ownership of it.
+    pub fn run_once<H: Handler<T, M>>(&mut self, handler: &mut H) -> MioResult<()> {
         // Execute a single tick
-        match self.tick(&mut handler) {
-            Err(e) => return Err(EventLoopError::new(handler, e)),
-            _ => {}
+        self.tick(handler, true)
+    }
+
+    /// Dispatches any pending IO events, messages and timeouts without
+    /// blocking. Intended for event loops embedded in a foreign main loop:
+    /// register the event loop itself (it implements `IoHandle`) and call
+    /// `run_pending` whenever it becomes readable.
+    ///
+    /// On kqueue platforms, registration changes are only submitted to the
+    /// OS while polling, so call `run_pending` after registering new IO.
+    pub fn run_pending<H: Handler<T, M>>(&mut self, handler: &mut H) -> MioResult<()> {
+        self.tick(handler, false)
+    }
+
+    // Removes every IO handle from the poller, except for the internal wake-up
+    // handles. The selector itself is kept, along with its signal mask and
+    // its descriptor, which may be registered with a foreign event loop.
+    fn deregister_all(&mut self) -> MioResult<()> {
+        for fd in self.poll.registered_fds().into_iter() {
+            match self.poll.token_for(fd) {
+                Some(tok) if tok == NOTIFY || tok == SIGNAL || tok == TIMER => continue,
+                _ => {}
+            }
+
+            // Handles that were closed without being deregistered are
+            // already gone from the selector, only the tracking is cleared
+            let _ = self.poll.deregister_fd(fd);
         }
 
-        Ok(handler)
+        Ok(())
     }
 
-    // Executes a single run of the event loop loop
-    fn tick<H: Handler<T, M>>(&mut self, handler: &mut H) -> MioResult<()> {
+    // Executes a single run of the event loop loop. When `block` is false,
+    // polling for IO never blocks.
+    fn tick<H: Handler<T, M>>(&mut self, handler: &mut H, block: bool) -> MioResult<()> {
         let mut messages;
         let mut pending;
 
         debug!("event loop tick");
 
+        let tick_start = precise_time_ns();
+
+        self.release_guards();
+        self.registry_process(handler);
+        self.deferred_process(handler);
+
         // Check the notify channel for any pending messages. If there are any,
         // avoid blocking when polling for IO events. Messages will be
         // processed after IO events.
-        messages = self.notify.check(self.config.messages_per_tick, true);
+        messages = self.notify.check(self.config.messages_per_tick, block);
         pending = messages > 0;
 
+        // The event loop is about to sleep, run the idle callbacks. Any
+        // message sent in the meantime will wake up the poll.
+        if block && !pending && self.prev_events == 0 && self.deferred.is_empty() && !self.idle.is_empty() {
+            self.idle_process(handler);
+        }
+
         // Check the registered IO handles for any new events. Each poll
         // is for one second, so a shutdown request can last as long as
         // one second before it takes effect.
-        let events = try!(self.io_poll(pending));
+        let immediate = !block || pending || !self.deferred.is_empty() || !self.is_running();
+        let start = precise_time_ns();
+        let events = try!(self.io_poll(immediate));
+
+        // Time spent blocked in the selector, a non-blocking poll counts as
+        // dispatch time
+        let slept = if immediate { 0 } else { precise_time_ns() - start };
 
-        if !pending {
+        self.prev_events = events;
+        self.stats.iterations += 1;
+        self.stats.sleep_ns += slept;
+
+        if !immediate {
+            self.stats.wakeups += 1;
+        }
+
+        if block && !pending {
             // Indicate that the sleep period is over, also grab any additional
             // messages
             let remaining = self.config.messages_per_tick - messages;
@@ -189,21 +683,47 @@ impl<T, M: Send> EventLoop<T, M> {
         self.notify(handler, messages);
         self.timer_process(handler);
 
+        handler.tick(self);
+
+        self.stats.dispatch_ns += precise_time_ns() - tick_start - slept;
+
         Ok(())
     }
 
     #[inline]
     fn io_poll(&mut self, immediate: bool) -> MioResult<uint> {
         if immediate {
-            self.poll.poll(0)
+            self.poll.poll(&mut self.events, 0)
         } else {
-            let mut sleep = self.timer.next_tick_in_ms() as uint;
-
-            if sleep > self.config.io_poll_timeout_ms {
-                sleep = self.config.io_poll_timeout_ms;
+            let mut sleep = self.config.io_poll_timeout_ms;
+
+            match self.timer_fd {
+                Some(ref timer_fd) => {
+                    // Only wake up when there are timeouts to fire, a timer
+                    // left armed for cleared timeouts would wake the loop for
+                    // nothing
+                    let at = self.timer.next_wakeup_at_ns();
+
+                    if at != self.timer_armed {
+                        try!(timer_fd.set_at_ns(at));
+                        self.timer_armed = at;
+                    }
+                }
+                None => {
+                    let mut next = self.timer.next_tick_in_ms();
+
+                    match self.timer.next_precise_in_ms() {
+                        Some(precise) if precise < next => next = precise,
+                        _ => {}
+                    }
+
+                    if (next as uint) < sleep {
+                        sleep = next as uint;
+                    }
+                }
             }
 
-            self.poll.poll(sleep)
+            self.poll.poll(&mut self.events, sleep)
         }
     }
 
@@ -215,58 +735,475 @@ impl<T, M: Send> EventLoop<T, M> {
         // it was registered with (which usually represents, at least, the
         // handle that the event is about) as well as information about
         // what kind of event occurred (readable, writable, signal, etc.)
+        self.dispatching = true;
+
         while i < cnt {
-            let evt = self.poll.event(i);
+            let evt = self.events.get(i);
 
             debug!("event={}", evt);
 
             match evt.token() {
                 NOTIFY => self.notify.cleanup(),
+                SIGNAL => {
+                    self.signal.cleanup();
+                    handler.signaled(self);
+                }
+                TIMER => {
+                    match self.timer_fd {
+                        Some(ref timer_fd) => timer_fd.cleanup(),
+                        None => {}
+                    }
+
+                    // The timer fd gets armed for the next tick on the next
+                    // poll
+                    self.timer_armed = 0;
+                }
+                tok if self.is_deregistered(tok) => {
+                    debug!("discarding event for deregistered token; token={}", tok);
+                }
                 _      => self.io_event(handler, evt)
             }
 
             i += 1;
         }
+
+        self.release_guards();
+        self.dispatching = false;
+        self.flush_deregistrations();
     }
 
     fn io_event<H: Handler<T, M>>(&mut self, handler: &mut H, evt: event::IoEvent) {
         let tok = evt.token();
 
-        if evt.is_readable() {
-            handler.readable(self, tok, evt.read_hint());
+        self.stats.io_events += 1;
+
+        if evt.is_error() {
+            // Reading SO_ERROR clears it, so it is only fetched when the
+            // event is known to be about a single descriptor
+            let err = match self.single_fd(tok) {
+                Some(fd) => os::take_socket_error_fd(fd),
+                None => Err(MioError::not_registered())
+            };
+
+            match err {
+                Ok(Some(err)) => {
+                    debug!("socket error; token={}; err={}", tok, err);
+                    handler.error(self, tok, err);
+                    return;
+                }
+                Ok(None) => {
+                    debug!("socket error queue; token={}", tok);
+                    handler.error_queue(self, tok);
+
+                    // Nothing else to report for a pure error event
+                    if !(evt.is_readable() || evt.is_writable() || evt.is_priority()) {
+                        return;
+                    }
+
+                    if self.is_deregistered(tok) {
+                        return;
+                    }
+                }
+                // Not a socket, or a token shared by several descriptors
+                Err(_) => {}
+            }
         }
 
-        if evt.is_writable() {
-            handler.writable(self, tok);
+        let evt = if self.config.read_available && evt.is_readable() && evt.available().is_none() {
+            match self.single_fd(tok).and_then(os::read_available) {
+                Some(available) => evt.with_hint(available),
+                None => evt
+            }
+        } else {
+            evt
+        };
+
+        handler.ready(self, tok, evt);
+    }
+
+    // Returns the descriptor registered with the token, unless several
+    // descriptors share it
+    fn single_fd(&self, token: Token) -> Option<Fd> {
+        let fds = self.poll.fds_for(token);
+
+        if fds.len() == 1 {
+            Some(fds[0])
+        } else {
+            None
         }
+    }
 
-        if evt.is_error() {
-            println!(" + ERROR");
+    // Applies the registration changes requested through `Registry` handles
+    fn registry_process<H: Handler<T, M>>(&mut self, handler: &mut H) {
+        if !self.registry.pending.load(SeqCst) {
+            return;
+        }
+
+        let ops = {
+            let mut ops = self.registry.ops.lock();
+            self.registry.pending.store(false, SeqCst);
+            mem::replace(&mut *ops, Vec::new())
+        };
+
+        for op in ops.into_iter() {
+            let (token, res) = match op {
+                RegistryOp::Register(fd, token, interest, opts) => {
+                    (Some(token), self.register_fd(fd, token, interest, opts))
+                }
+                RegistryOp::Reregister(fd, token, interest, opts) => {
+                    (Some(token), self.reregister_fd(fd, token, interest, opts))
+                }
+                RegistryOp::Deregister(fd) => {
+                    (self.poll.token_for(fd), self.poll.deregister_fd(fd))
+                }
+            };
+
+            match (token, res) {
+                (Some(token), Err(err)) => handler.error(self, token, err),
+                _ => {}
+            }
+        }
+    }
+
+    fn register_fd(&mut self, fd: Fd, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.release_guards();
+        self.flush_deregistration(fd);
+        self.poll.register_fd(fd, token, interest, opts)
+    }
+
+    fn reregister_fd(&mut self, fd: Fd, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.release_guards();
+        self.flush_deregistration(fd);
+        self.poll.reregister_fd(fd, token, interest, opts)
+    }
+
+    fn deferred_process<H: Handler<T, M>>(&mut self, handler: &mut H) {
+        if self.deferred.is_empty() {
+            return;
+        }
+
+        // Tokens deferred while dispatching are queued for the next tick
+        let mut tokens = mem::replace(&mut self.deferred_spare, Vec::new());
+        mem::swap(&mut tokens, &mut self.deferred);
+
+        for &token in tokens.iter() {
+            handler.deferred(self, token);
+        }
+
+        // Keep the allocation around for the next tick
+        tokens.clear();
+        self.deferred_spare = tokens;
+    }
+
+    fn idle_process<H: Handler<T, M>>(&mut self, handler: &mut H) {
+        let mut i = 0u;
+
+        // Callbacks may deregister themselves, so re-check the length
+        while i < self.idle.len() {
+            let token = self.idle[i];
+            handler.idle(self, token);
+            i += 1;
         }
     }
 
     fn notify<H: Handler<T, M>>(&mut self, handler: &mut H, mut cnt: uint) {
         while cnt > 0 {
-            let msg = self.notify.poll()
-                .expect("[BUG] at this point there should always be a message");
-
+            let msg = match self.notify.poll() {
+                Some(msg) => msg,
+                None => {
+                    // A sender is in the middle of a push, retry on the next
+                    // tick instead of waiting for it
+                    self.notify.unclaim(cnt);
+                    return;
+                }
+            };
+
+            self.stats.messages += 1;
             handler.notify(self, msg);
             cnt -= 1;
         }
     }
 
+    fn drain<H: Handler<T, M>>(&mut self, handler: &mut H) {
+        // Clone the handle, the handler needs a mutable borrow of the event loop
+        let notify = self.notify.clone();
+
+        for msg in notify.drain() {
+            handler.notify_dropped(self, msg);
+        }
+    }
+
     fn timer_process<H: Handler<T, M>>(&mut self, handler: &mut H) {
         let now = self.timer.now();
 
         loop {
             match self.timer.tick_to(now) {
-                Some(t) => handler.timeout(self, t),
+                Some(t) => {
+                    self.stats.timeouts += 1;
+                    handler.timeout(self, t);
+                }
+                _ => break
+            }
+        }
+
+        let now = self.timer.now_ns();
+
+        loop {
+            match self.timer.precise_to(now) {
+                Some(t) => {
+                    self.stats.timeouts += 1;
+                    handler.timeout(self, t);
+                }
                 _ => return
             }
         }
     }
 }
 
+impl<T: Clone, M: Send> EventLoop<T, M> {
+    /// Calls the handler's `timeout` function with a clone of the supplied
+    /// token every `period` milliseconds, until the returned timeout is
+    /// cleared. Each deadline is computed from the previous deadline rather
+    /// than from when the timeout was handled, so the interval does not
+    /// drift. A `period` of zero is rejected with `TimerInvalidPeriod`.
+    pub fn interval_ms(&mut self, token: T, period: u64) -> TimerResult<Timeout> {
+        self.timer.interval_ms(token, period)
+    }
+}
+
+impl<T: Send, M: Send> EventLoop<T, M> {
+    /// Creates an event loop on a new named thread and runs it with the given
+    /// handler. The returned handle can send messages to the event loop, shut
+    /// it down and wait for the thread to finish.
+    pub fn spawn_thread<H: Handler<T, M> + Send>(name: String, config: EventLoopConfig, handler: H) -> MioResult<EventLoopThread<H, M>> {
+        let (tx, rx) = channel();
+
+        let future = TaskBuilder::new().named(name).try_future(proc() {
+            let mut event_loop: EventLoop<T, M> = match EventLoop::configured(config) {
+                Ok(event_loop) => event_loop,
+                Err(e) => {
+                    tx.send(Err(e.clone()));
+                    return Err(EventLoopError::new(handler, e));
+                }
+            };
+
+            tx.send(Ok((event_loop.channel(), event_loop.shutdown_handle())));
+            event_loop.run(handler)
+        });
+
+        // Wait for the event loop to be created, so that setup errors are
+        // reported to the caller.
+        let (sender, shutdown) = try!(rx.recv());
+
+        Ok(EventLoopThread {
+            sender: sender,
+            shutdown: shutdown,
+            future: future
+        })
+    }
+}
+
+/// Handle to an event loop running on its own thread, see
+/// `EventLoop::spawn_thread`.
+pub struct EventLoopThread<H, M: Send> {
+    sender: EventLoopSender<M>,
+    shutdown: ShutdownHandle,
+    future: Future<Result<EventLoopResult<H>, Box<Any + Send>>>
+}
+
+impl<H: Send, M: Send> EventLoopThread<H, M> {
+    /// Returns a sender for the event loop's message queue.
+    pub fn channel(&self) -> EventLoopSender<M> {
+        self.sender.clone()
+    }
+
+    /// Requests the event loop to shut down, see `ShutdownHandle`.
+    pub fn shutdown(&self) -> MioResult<()> {
+        self.shutdown.shutdown()
+    }
+
+    /// Returns a handle that can shut the event loop down.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Blocks until the event loop thread exits, returning the handler. The
+    /// outer `Err` holds the panic payload if the thread panicked.
+    pub fn join(self) -> Result<EventLoopResult<H>, Box<Any + Send>> {
+        self.future.unwrap()
+    }
+}
+
+impl<T, M: Send> IoHandle for EventLoop<T, M> {
+    fn desc(&self) -> &os::IoDesc {
+        self.poll.desc()
+    }
+}
+
+fn register_internal<M: Send>(poll: &mut Poll, notify: &Notify<M>, signal: &Signal, timer_fd: &Option<os::TimerFd>) -> MioResult<()> {
+    try!(poll.register(notify, NOTIFY, event::READABLE | event::WRITABLE, event::EDGE));
+    try!(poll.register(signal, SIGNAL, event::READABLE, event::EDGE));
+
+    match *timer_fd {
+        Some(ref timer_fd) => try!(poll.register(timer_fd, TIMER, event::READABLE, event::EDGE)),
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Counters describing the work performed by an event loop since it was
+/// created.
+#[deriving(Clone, Show, Default)]
+pub struct EventLoopStats {
+    /// Number of event loop iterations
+    pub iterations: u64,
+    /// Number of IO events dispatched to the handler
+    pub io_events: u64,
+    /// Number of notify messages dispatched to the handler
+    pub messages: u64,
+    /// Number of timeouts fired
+    pub timeouts: u64,
+    /// Number of times the event loop blocked in the selector
+    pub wakeups: u64,
+    /// Time spent waiting in the selector, in nanoseconds
+    pub sleep_ns: u64,
+    /// Time spent dispatching events to the handler, in nanoseconds
+    pub dispatch_ns: u64,
+}
+
+/// An IO handle removed from one event loop, on its way to another. See
+/// `EventLoop::deregister_and_export`.
+pub struct ExportedIo<H> {
+    io: H,
+    interest: event::Interest,
+    opts: event::PollOpt,
+}
+
+impl<H> ExportedIo<H> {
+    pub fn interest(&self) -> event::Interest {
+        self.interest
+    }
+
+    pub fn opts(&self) -> event::PollOpt {
+        self.opts
+    }
+
+    /// Returns the IO handle without registering it anywhere.
+    pub fn unwrap(self) -> H {
+        self.io
+    }
+}
+
+/// Registers IO with an event loop from any thread, see
+/// `EventLoop::registry`.
+///
+/// Requests are queued and applied by the event loop at the start of its
+/// next iteration, waking it up if needed. Failures are reported to the
+/// handler's `error` function. The IO handles must stay open until they have
+/// been deregistered.
+#[deriving(Clone)]
+pub struct Registry {
+    inner: Arc<RegistryInner>,
+    waker: Waker
+}
+
+struct RegistryInner {
+    ops: Mutex<Vec<RegistryOp>>,
+    // Set when `ops` is not empty, checked without locking on every tick
+    pending: AtomicBool,
+    // Dropped `RegistrationGuard`s
+    releases: Mutex<Vec<(Fd, Token)>>,
+    released: AtomicBool,
+}
+
+impl RegistryInner {
+    fn new() -> RegistryInner {
+        RegistryInner {
+            ops: Mutex::new(Vec::new()),
+            pending: AtomicBool::new(false),
+            releases: Mutex::new(Vec::new()),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, op: RegistryOp) {
+        let mut ops = self.ops.lock();
+        ops.push(op);
+        self.pending.store(true, SeqCst);
+    }
+}
+
+enum RegistryOp {
+    Register(Fd, Token, event::Interest, event::PollOpt),
+    Reregister(Fd, Token, event::Interest, event::PollOpt),
+    Deregister(Fd),
+}
+
+impl Registry {
+    pub fn register<H: IoHandle>(&self, io: &H, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.push(RegistryOp::Register(io.desc().fd, token, interest, opts))
+    }
+
+    pub fn reregister<H: IoHandle>(&self, io: &H, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.push(RegistryOp::Reregister(io.desc().fd, token, interest, opts))
+    }
+
+    pub fn deregister<H: IoHandle>(&self, io: &H) -> MioResult<()> {
+        self.push(RegistryOp::Deregister(io.desc().fd))
+    }
+
+    fn push(&self, op: RegistryOp) -> MioResult<()> {
+        self.inner.push(op);
+        self.waker.wakeup()
+    }
+}
+
+/// Deregisters an IO handle from its event loop when dropped, see
+/// `EventLoop::register_guarded`.
+///
+/// The deregistration is queued and applied before the event loop
+/// registers any other handle, so a guard dropped together with its handle
+/// never deregisters a new handle that reuses the descriptor. Otherwise it
+/// is applied at the end of the current dispatch, events already polled for
+/// the handle are still dispatched until then, or at the start of the next
+/// iteration. It is skipped if the handle has been registered with a
+/// different token in the meantime.
+pub struct RegistrationGuard {
+    inner: Arc<RegistryInner>,
+    fd: Fd,
+    token: Token
+}
+
+impl RegistrationGuard {
+    pub fn token(&self) -> Token {
+        self.token
+    }
+}
+
+impl Drop for RegistrationGuard {
+    fn drop(&mut self) {
+        let mut releases = self.inner.releases.lock();
+        releases.push((self.fd, self.token));
+        self.inner.released.store(true, SeqCst);
+    }
+}
+
+/// Stops a running event loop from any thread, see `EventLoop::run`.
+#[deriving(Clone)]
+pub struct ShutdownHandle {
+    shutdown: Arc<AtomicBool>,
+    waker: Waker
+}
+
+impl ShutdownHandle {
+    /// Requests the event loop to shut down, waking it up if it is sleeping.
+    pub fn shutdown(&self) -> MioResult<()> {
+        self.shutdown.store(true, SeqCst);
+        self.waker.wakeup()
+    }
+}
+
 #[deriving(Clone)]
 pub struct EventLoopSender<M: Send> {
     notify: Notify<M>
@@ -277,9 +1214,55 @@ impl<M: Send> EventLoopSender<M> {
         EventLoopSender { notify: notify }
     }
 
-    pub fn send(&self, msg: M) -> Result<(), M> {
+    pub fn send(&self, msg: M) -> Result<(), NotifyError<M>> {
         self.notify.notify(msg)
     }
+
+    /// Returns the wake-up statistics of the event loop's notify queue.
+    pub fn stats(&self) -> NotifyStats {
+        self.notify.stats()
+    }
+
+    /// Number of messages waiting to be processed by the event loop.
+    pub fn len(&self) -> uint {
+        self.notify.len()
+    }
+
+    /// Capacity of the event loop's message queue, `None` if unbounded.
+    pub fn capacity(&self) -> Option<uint> {
+        self.notify.capacity()
+    }
+
+    /// Returns true if `send` would currently fail because the queue is full.
+    pub fn is_full(&self) -> bool {
+        self.notify.is_full()
+    }
+
+    /// Sends all messages from the iterator, waking up the event loop at most
+    /// once. Returns the number of messages sent. If the queue fills up, the
+    /// error holds the number sent and the message that did not fit, and the
+    /// iterator still holds the rest.
+    pub fn send_batch<I: Iterator<M>>(&self, msgs: &mut I) -> Result<uint, BatchError<M>> {
+        self.notify.notify_batch(msgs)
+    }
+
+    /// Sends a message that will be delivered ahead of any messages sent with
+    /// `send` that are still pending.
+    pub fn send_urgent(&self, msg: M) -> Result<(), NotifyError<M>> {
+        self.notify.notify_urgent(msg)
+    }
+
+    /// Sends a message, blocking the current thread until there is room in the
+    /// event loop's queue.
+    pub fn send_wait(&self, msg: M) -> Result<(), NotifyError<M>> {
+        self.notify.notify_wait(msg)
+    }
+
+    /// Sends a message, blocking the current thread until there is room in the
+    /// event loop's queue or the timeout elapses.
+    pub fn send_timeout(&self, msg: M, timeout: Duration) -> Result<(), NotifyError<M>> {
+        self.notify.notify_timeout(msg, timeout)
+    }
 }
 
 pub type EventLoopResult<H> = Result<H, EventLoopError<H>>;
@@ -303,9 +1286,11 @@ mod tests {
     use std::str;
     use std::sync::Arc;
     use std::sync::atomic::{AtomicInt, SeqCst};
+    use std::default::Default;
+    use std::time::duration::Duration;
     use super::EventLoop;
-    use io::{IoWriter, IoReader};
-    use {io, buf, Buf, Handler, Token};
+    use io::{IoHandle, IoWriter, IoReader};
+    use {io, buf, Buf, Handler, MioError, MioResult, MockClock, Token};
     use os::event;
 
     type TestEventLoop = EventLoop<uint, ()>;
@@ -339,12 +1324,12 @@ mod tests {
 
         let rcount = Arc::new(AtomicInt::new(0));
         let wcount = Arc::new(AtomicInt::new(0));
-        let handler = Funtimes::new(rcount.clone(), wcount.clone());
+        let mut handler = Funtimes::new(rcount.clone(), wcount.clone());
 
         writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
         event_loop.register(&reader, Token(10)).unwrap();
 
-        let _ = event_loop.run_once(handler);
+        event_loop.run_once(&mut handler).unwrap();
         let mut b = buf::ByteBuf::new(16);
 
         assert_eq!((*rcount).load(SeqCst), 1);
@@ -354,4 +1339,396 @@ mod tests {
 
         assert_eq!(str::from_utf8(b.bytes()).unwrap(), "hello");
     }
+
+    struct Available {
+        available: Option<uint>
+    }
+
+    impl Handler<uint, ()> for Available {
+        fn ready(&mut self, _event_loop: &mut TestEventLoop, _token: Token, events: event::IoEvent) {
+            self.available = events.available();
+        }
+    }
+
+    #[test]
+    fn test_read_available() {
+        let config = super::EventLoopConfig { read_available: true, .. Default::default() };
+        let mut event_loop: TestEventLoop = EventLoop::configured(config).unwrap();
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        let mut handler = Available { available: None };
+
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.register(&reader, Token(10)).unwrap();
+
+        event_loop.run_once(&mut handler).unwrap();
+        assert_eq!(handler.available, Some(5));
+    }
+
+    struct ShutdownOnRead;
+
+    impl Handler<uint, ()> for ShutdownOnRead {
+        fn readable(&mut self, event_loop: &mut TestEventLoop, _token: Token, _hint: event::ReadHint) {
+            event_loop.shutdown();
+        }
+    }
+
+    #[test]
+    fn test_shutdown_deregisters_handles() {
+        let mut event_loop: TestEventLoop = EventLoop::new().unwrap();
+        let selector = event_loop.poll.desc().fd;
+
+        let (reader, mut writer) = io::pipe().unwrap();
+
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.register(&reader, Token(10)).unwrap();
+
+        assert!(event_loop.run(ShutdownOnRead).is_ok());
+
+        // The handle is gone, the selector and the internal handles remain
+        assert!(event_loop.poll.fd_for(Token(10)).is_none());
+        assert!(event_loop.poll.fd_for(super::NOTIFY).is_some());
+        assert_eq!(event_loop.poll.desc().fd, selector);
+
+        // The loop can be run again
+        writer.write(&mut buf::wrap("again".as_bytes())).unwrap();
+        event_loop.register(&reader, Token(10)).unwrap();
+        assert!(event_loop.run(ShutdownOnRead).is_ok());
+    }
+
+    #[test]
+    fn test_guard_skips_reused_fd() {
+        let mut event_loop: TestEventLoop = EventLoop::new().unwrap();
+
+        let (reader, _writer) = io::pipe().unwrap();
+        let fd = reader.desc().fd;
+        let guard = event_loop.register_guarded(&reader, Token(10), event::READABLE, event::LEVEL).unwrap();
+
+        // Drop the guard along with its handle, the next pipe reuses the
+        // descriptor and registers it with the same token
+        drop(guard);
+        drop(reader);
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        assert_eq!(reader.desc().fd, fd);
+
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.register(&reader, Token(10)).unwrap();
+        event_loop.run_once(&mut ShutdownOnRead).unwrap();
+
+        assert_eq!(event_loop.poll.token_for(fd), Some(Token(10)));
+    }
+
+    #[test]
+    fn test_signal_waker_outlives_event_loop() {
+        let event_loop: TestEventLoop = EventLoop::new().unwrap();
+        let waker = event_loop.signal_waker();
+        drop(event_loop);
+
+        // The waker keeps its descriptor open, a new pipe does not reuse it
+        let (mut reader, _writer) = io::pipe().unwrap();
+        waker.wakeup();
+
+        let mut b = buf::ByteBuf::new(16);
+        assert!(reader.read(&mut b).unwrap().would_block());
+    }
+
+    struct SharedToken {
+        first: Option<io::PipeReader>,
+        gone: Vec<io::PipeReader>,
+        read: uint
+    }
+
+    impl Handler<uint, ()> for SharedToken {
+        fn readable(&mut self, event_loop: &mut TestEventLoop, token: Token, _hint: event::ReadHint) {
+            self.read += 1;
+
+            match self.first.take() {
+                Some(reader) => {
+                    event_loop.deregister(&reader).unwrap();
+                    self.gone.push(reader);
+                }
+                None => {}
+            }
+
+            // The other handle sharing the token is still registered
+            assert!(!event_loop.is_deregistered(token));
+        }
+    }
+
+    #[test]
+    fn test_deregister_shared_token_while_dispatching() {
+        let mut event_loop: TestEventLoop = EventLoop::new().unwrap();
+
+        let (r1, mut w1) = io::pipe().unwrap();
+        let (r2, mut w2) = io::pipe().unwrap();
+
+        w1.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        w2.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.register(&r1, Token(5)).unwrap();
+        event_loop.register(&r2, Token(5)).unwrap();
+
+        let mut handler = SharedToken { first: Some(r1), gone: vec![], read: 0 };
+
+        // Deregistering the first pipe does not suppress the event of the
+        // second one
+        event_loop.run_once(&mut handler).unwrap();
+        assert_eq!(handler.read, 2);
+        assert_eq!(event_loop.poll.fds_for(Token(5)), [r2.desc().fd].as_slice());
+    }
+
+    type ImportEventLoop = EventLoop<uint, super::ExportedIo<io::PipeReader>>;
+
+    struct Exporter {
+        reader: Option<io::PipeReader>,
+        target: super::EventLoopSender<super::ExportedIo<io::PipeReader>>
+    }
+
+    impl Handler<uint, ()> for Exporter {
+        fn readable(&mut self, event_loop: &mut TestEventLoop, token: Token, _hint: event::ReadHint) {
+            assert_eq!(token, Token(1));
+
+            let reader = self.reader.take().unwrap();
+            let exported = event_loop.deregister_and_export(reader).unwrap();
+
+            assert!(exported.interest() == event::READABLE);
+            assert!(exported.opts() == event::PollOpt::edge());
+            assert!(event_loop.is_deregistered(token));
+
+            if self.target.send(exported).is_err() {
+                panic!("could not send the exported handle");
+            }
+        }
+    }
+
+    struct Importer {
+        reader: Option<io::PipeReader>,
+        read: Vec<Token>
+    }
+
+    impl Handler<uint, super::ExportedIo<io::PipeReader>> for Importer {
+        fn readable(&mut self, _event_loop: &mut ImportEventLoop, token: Token, _hint: event::ReadHint) {
+            self.read.push(token);
+        }
+
+        fn notify(&mut self, event_loop: &mut ImportEventLoop, exported: super::ExportedIo<io::PipeReader>) {
+            self.reader = Some(event_loop.import(exported, Token(7)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_export_import() {
+        let mut source: TestEventLoop = EventLoop::new().unwrap();
+        let mut target: ImportEventLoop = EventLoop::new().unwrap();
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        let fd = reader.desc().fd;
+
+        source.register_opt(&reader, Token(1), event::READABLE, event::PollOpt::edge()).unwrap();
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+
+        // The handle is exported while its event is dispatched
+        let mut exporter = Exporter { reader: Some(reader), target: target.channel() };
+        source.run_once(&mut exporter).unwrap();
+        assert!(exporter.reader.is_none());
+        assert!(source.poll.registration(fd).is_none());
+
+        // The target loop imports it under a new token, with the same
+        // interest and options
+        let mut importer = Importer { reader: None, read: vec![] };
+        target.run_pending(&mut importer).unwrap();
+        assert!(importer.reader.is_some());
+
+        let reg = target.poll.registration(fd).unwrap();
+        assert_eq!(reg.token, Token(7));
+        assert!(reg.interest == event::READABLE);
+        assert!(reg.opts == event::PollOpt::edge());
+
+        // The registration is armed, and being oneshot, fires once
+        writer.write(&mut buf::wrap("again".as_bytes())).unwrap();
+        target.run_pending(&mut importer).unwrap();
+        target.run_pending(&mut importer).unwrap();
+        assert_eq!(importer.read, vec![Token(7)]);
+    }
+
+    struct Registered {
+        read: Vec<Token>,
+        errors: Vec<Token>
+    }
+
+    impl Handler<uint, ()> for Registered {
+        fn readable(&mut self, _event_loop: &mut TestEventLoop, token: Token, _hint: event::ReadHint) {
+            self.read.push(token);
+        }
+
+        fn error(&mut self, _event_loop: &mut TestEventLoop, token: Token, _error: MioError) {
+            self.errors.push(token);
+        }
+    }
+
+    #[test]
+    fn test_registry_across_threads() {
+        let mut event_loop: TestEventLoop = EventLoop::new().unwrap();
+        let mut handler = Registered { read: vec![], errors: vec![] };
+
+        let registry = event_loop.registry();
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+
+        let (tx, rx) = channel();
+
+        spawn(proc() {
+            registry.register(&reader, Token(3), event::READABLE, event::LEVEL).unwrap();
+
+            // epoll refuses to register the same handle twice, the failure
+            // is reported to the handler with the token
+            if cfg!(target_os = "linux") {
+                registry.register(&reader, Token(4), event::READABLE, event::LEVEL).unwrap();
+            }
+
+            tx.send(reader);
+        });
+
+        // Keep the handle open while it is registered
+        let reader = rx.recv();
+
+        event_loop.run_once(&mut handler).unwrap();
+        assert_eq!(handler.read, vec![Token(3)]);
+        assert_eq!(event_loop.poll.token_for(reader.desc().fd), Some(Token(3)));
+
+        if cfg!(target_os = "linux") {
+            assert_eq!(handler.errors, vec![Token(4)]);
+        }
+    }
+
+    struct Timeouts {
+        fired: Vec<uint>
+    }
+
+    impl Handler<uint, ()> for Timeouts {
+        fn timeout(&mut self, _event_loop: &mut TestEventLoop, timeout: uint) {
+            self.fired.push(timeout);
+        }
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(1_000_000_000);
+        let mut event_loop: TestEventLoop =
+            EventLoop::with_clock(Default::default(), box clock.clone()).unwrap();
+        let mut handler = Timeouts { fired: vec![] };
+
+        event_loop.timeout_ms(1, 250).unwrap();
+        event_loop.timeout_ms(2, 1_000).unwrap();
+
+        clock.advance_ms(200);
+        event_loop.run_pending(&mut handler).unwrap();
+        assert!(handler.fired.is_empty());
+
+        clock.advance_ms(100);
+        event_loop.run_pending(&mut handler).unwrap();
+        assert_eq!(handler.fired, vec![1]);
+
+        clock.advance_ms(700);
+        event_loop.run_pending(&mut handler).unwrap();
+        assert_eq!(handler.fired, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let clock = MockClock::new(1_000_000_000);
+        let mut event_loop: TestEventLoop =
+            EventLoop::with_clock(Default::default(), box clock.clone()).unwrap();
+        let mut handler = Timeouts { fired: vec![] };
+
+        // One IO event, one message and one timeout
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write(&mut buf::wrap("hello".as_bytes())).unwrap();
+        event_loop.register(&reader, Token(10)).unwrap();
+        event_loop.channel().send(()).unwrap();
+        event_loop.timeout_ms(1, 50).unwrap();
+        clock.advance_ms(200);
+
+        event_loop.run_pending(&mut handler).unwrap();
+        assert_eq!(handler.fired, vec![1]);
+
+        {
+            let stats = event_loop.stats();
+            assert_eq!(stats.iterations, 1);
+            assert_eq!(stats.io_events, 1);
+            assert_eq!(stats.messages, 1);
+            assert_eq!(stats.timeouts, 1);
+
+            // Polling without blocking is not sleeping
+            assert_eq!(stats.wakeups, 0);
+            assert_eq!(stats.sleep_ns, 0);
+            assert!(stats.dispatch_ns > 0);
+        }
+
+        // The pipe is still readable, the blocking poll returns right away
+        event_loop.run_once(&mut handler).unwrap();
+
+        let stats = event_loop.stats();
+        assert_eq!(stats.iterations, 2);
+        assert_eq!(stats.io_events, 2);
+        assert_eq!(stats.messages, 1);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.wakeups, 1);
+    }
+
+    #[test]
+    fn test_precise_timeout() {
+        let clock = MockClock::new(1_000_000_000);
+        let mut event_loop: TestEventLoop =
+            EventLoop::with_clock(Default::default(), box clock.clone()).unwrap();
+        let mut handler = Timeouts { fired: vec![] };
+
+        event_loop.timeout_precise(1, Duration::microseconds(500)).unwrap();
+        event_loop.timeout_ms(2, 50).unwrap();
+
+        // Fires at the deadline, well before the next 100ms tick
+        clock.advance(Duration::microseconds(300));
+        event_loop.run_pending(&mut handler).unwrap();
+        assert!(handler.fired.is_empty());
+
+        clock.advance(Duration::microseconds(200));
+        event_loop.run_pending(&mut handler).unwrap();
+        assert_eq!(handler.fired, vec![1]);
+
+        clock.advance_ms(100);
+        event_loop.run_pending(&mut handler).unwrap();
+        assert_eq!(handler.fired, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_mock_clock_rejects_timer_fd() {
+        let config = super::EventLoopConfig { timer_fd: true, .. Default::default() };
+        let res: MioResult<TestEventLoop> = EventLoop::with_clock(config, box MockClock::new(0));
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_mock_clock_across_threads() {
+        let clock = MockClock::new(1_000_000_000);
+        let mut event_loop: TestEventLoop =
+            EventLoop::with_clock(Default::default(), box clock.clone()).unwrap();
+
+        event_loop.timeout_ms(1, 250).unwrap();
+        clock.advance_ms(300);
+
+        // The event loop, along with its clock, can be moved to another
+        // thread
+        let (tx, rx) = channel();
+
+        spawn(proc() {
+            let mut event_loop = event_loop;
+            let mut handler = Timeouts { fired: vec![] };
+
+            event_loop.run_pending(&mut handler).unwrap();
+            tx.send(handler.fired);
+        });
+
+        assert_eq!(rx.recv(), vec![1]);
+    }
 }
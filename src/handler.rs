@@ -1,18 +1,182 @@
+use error::MioError;
 use event_loop::EventLoop;
 use os::token::Token;
 use os::event;
 
+/// Receives the events dispatched by an `EventLoop`.
+///
+/// Every callback is passed the event loop, so IO handles can be registered,
+/// reregistered or deregistered from within any callback, for example
+/// registering a socket right after accepting it in `readable`. Registrations
+/// take effect for the next poll; a deregistration made while IO events are
+/// dispatched also discards the remaining events for the handle in the
+/// current iteration.
+///
+/// `T` is the type of the values passed to `EventLoop::timeout` and handed
+/// back to `timeout`, independent of the `Token`s identifying IO handles, so
+/// a timeout can carry e.g. a connection id along with the kind of timeout.
+/// `M` is the type of the messages sent through `EventLoopSender`.
 #[allow(unused_variables)]
 pub trait Handler<T, M: Send> {
+    /// Invoked once per IO event with all the readiness reported for the
+    /// token, for handlers that need to see simultaneous readiness at once.
+    /// The default implementation calls `readable` and then `writable`,
+    /// according to the event's kind. `events.available()` tells how much
+    /// can be read or accepted, to size buffers or drain a listener in one
+    /// pass.
+    fn ready(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, events: event::IoEvent) {
+        if events.is_readable() {
+            self.readable(event_loop, token, events.read_hint());
+        }
+
+        // `readable` may have deregistered the token
+        if events.is_writable() && !event_loop.is_deregistered(token) {
+            self.writable(event_loop, token);
+        }
+    }
+
+    fn readable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, hint: event::ReadHint) {
+    }
+
+    fn writable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+    }
+
+    fn notify(&mut self, event_loop: &mut EventLoop<T, M>, msg: M) {
+    }
+
+    fn timeout(&mut self, event_loop: &mut EventLoop<T, M>, timeout: T) {
+    }
+
+    /// Invoked instead of `ready` when the selector reports an error for a
+    /// socket, with the error retrieved from `SO_ERROR`. This is how a failed
+    /// non-blocking connect is reported. Errors on handles that are not
+    /// sockets, or on tokens shared by several handles, are dispatched to
+    /// `ready`, the handler can then fetch the error of each handle with
+    /// `take_socket_error`.
+    fn error(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, error: MioError) {
+    }
+
+    /// Invoked when the selector reports an error for a socket without a
+    /// pending `SO_ERROR`, meaning messages are queued on the socket's error
+    /// queue, such as the completions of `TcpSocket::send_zerocopy`. They
+    /// must be read, e.g. with `zerocopy_completion`, or a level-triggered
+    /// registration keeps reporting the event. `ready` is still invoked when
+    /// the event also carries other readiness.
+    fn error_queue(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+    }
+
+    /// Invoked once per event loop iteration, after all IO events, messages
+    /// and timeouts have been dispatched.
+    fn tick(&mut self, event_loop: &mut EventLoop<T, M>) {
+    }
+
+    /// Invoked at the start of an event loop iteration for each token passed
+    /// to `EventLoop::defer` during the previous iteration.
+    fn deferred(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+    }
+
+    /// Invoked for each token registered with `EventLoop::register_idle` when
+    /// the event loop is about to block waiting for IO.
+    fn idle(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+    }
+
+    /// Invoked when the event loop was woken up through a `SignalWaker`.
+    /// Multiple wake-ups may be collapsed into a single call.
+    fn signaled(&mut self, event_loop: &mut EventLoop<T, M>) {
+    }
+
+    /// Invoked once the event loop stops running, before the remaining
+    /// messages are dropped and the IO handles are deregistered.
+    fn shutdown(&mut self, event_loop: &mut EventLoop<T, M>) {
+    }
+
+    /// Invoked for each message still in the notify queue when the event loop
+    /// stops running. The messages are dropped afterwards.
+    fn notify_dropped(&mut self, event_loop: &mut EventLoop<T, M>, msg: M) {
+    }
+}
+
+/// A `Handler` assembled from closures, see `handler_fn`.
+///
+/// Events without a closure are ignored.
+pub struct FnHandler<'a, T, M> {
+    readable: Option<Box<FnMut(&mut EventLoop<T, M>, Token, event::ReadHint) + 'a>>,
+    writable: Option<Box<FnMut(&mut EventLoop<T, M>, Token) + 'a>>,
+    notify: Option<Box<FnMut(&mut EventLoop<T, M>, M) + 'a>>,
+    timeout: Option<Box<FnMut(&mut EventLoop<T, M>, T) + 'a>>,
+}
+
+/// Returns an empty `FnHandler`, to be completed with the `on_*` builder
+/// functions:
+///
+/// ```ignore
+/// let handler = mio::handler_fn()
+///     .on_notify(|event_loop: &mut EventLoop<(), String>, msg| {
+///         println!("got {}", msg);
+///         event_loop.shutdown();
+///     });
+/// ```
+pub fn handler_fn<'a, T, M: Send>() -> FnHandler<'a, T, M> {
+    FnHandler {
+        readable: None,
+        writable: None,
+        notify: None,
+        timeout: None,
+    }
+}
+
+impl<'a, T, M: Send> FnHandler<'a, T, M> {
+    pub fn on_readable<F>(mut self, f: F) -> FnHandler<'a, T, M>
+            where F: FnMut(&mut EventLoop<T, M>, Token, event::ReadHint) + 'a {
+        self.readable = Some(box f as Box<FnMut(&mut EventLoop<T, M>, Token, event::ReadHint) + 'a>);
+        self
+    }
+
+    pub fn on_writable<F>(mut self, f: F) -> FnHandler<'a, T, M>
+            where F: FnMut(&mut EventLoop<T, M>, Token) + 'a {
+        self.writable = Some(box f as Box<FnMut(&mut EventLoop<T, M>, Token) + 'a>);
+        self
+    }
+
+    pub fn on_notify<F>(mut self, f: F) -> FnHandler<'a, T, M>
+            where F: FnMut(&mut EventLoop<T, M>, M) + 'a {
+        self.notify = Some(box f as Box<FnMut(&mut EventLoop<T, M>, M) + 'a>);
+        self
+    }
+
+    pub fn on_timeout<F>(mut self, f: F) -> FnHandler<'a, T, M>
+            where F: FnMut(&mut EventLoop<T, M>, T) + 'a {
+        self.timeout = Some(box f as Box<FnMut(&mut EventLoop<T, M>, T) + 'a>);
+        self
+    }
+}
+
+impl<'a, T, M: Send> Handler<T, M> for FnHandler<'a, T, M> {
     fn readable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token, hint: event::ReadHint) {
+        match self.readable {
+            Some(ref mut f) => (**f)(event_loop, token, hint),
+            None => {}
+        }
     }
 
     fn writable(&mut self, event_loop: &mut EventLoop<T, M>, token: Token) {
+        match self.writable {
+            Some(ref mut f) => (**f)(event_loop, token),
+            None => {}
+        }
     }
 
     fn notify(&mut self, event_loop: &mut EventLoop<T, M>, msg: M) {
+        match self.notify {
+            Some(ref mut f) => (**f)(event_loop, msg),
+            None => {}
+        }
     }
 
     fn timeout(&mut self, event_loop: &mut EventLoop<T, M>, timeout: T) {
+        match self.timeout {
+            Some(ref mut f) => (**f)(event_loop, timeout),
+            None => {}
+        }
     }
 }
@@ -1,6 +1,6 @@
 use buf::{Buf, MutBuf};
 use os;
-use error::MioResult;
+use error::{MioResult, MioError};
 use self::NonBlock::{Ready, WouldBlock};
 use error::MioErrorKind as mek;
 
@@ -30,6 +30,12 @@ pub trait IoHandle {
     fn desc(&self) -> &os::IoDesc;
 }
 
+/// Returns the number of bytes that can be read from the handle without
+/// blocking (`FIONREAD`), e.g. to size a read buffer.
+pub fn bytes_available<H: IoHandle>(io: &H) -> MioResult<uint> {
+    os::bytes_available(io.desc())
+}
+
 pub trait IoReader {
     fn read(&mut self, buf: &mut MutBuf) -> MioResult<NonBlock<(uint)>>;
 }
@@ -102,6 +108,111 @@ pub fn read<I: IoHandle>(io: &mut I, buf: &mut MutBuf) -> MioResult<NonBlock<uin
     }
 }
 
+/// Relays data from one handle to another, e.g. between two sockets of a
+/// proxy, with `splice`, so the data is never copied to user space. The
+/// data passes through a pipe owned by the `Splice`, which holds on to
+/// whatever the destination could not take yet.
+#[cfg(target_os = "linux")]
+pub struct Splice {
+    reader: os::IoDesc,
+    writer: os::IoDesc,
+    // Bytes moved into the pipe, but not yet to the destination
+    buffered: uint,
+}
+
+#[cfg(target_os = "linux")]
+impl Splice {
+    pub fn new() -> MioResult<Splice> {
+        let (rd, wr) = try!(os::pipe());
+
+        Ok(Splice {
+            reader: rd,
+            writer: wr,
+            buffered: 0
+        })
+    }
+
+    /// The number of bytes read from the source that are still waiting to be
+    /// written to the destination. When a transfer returns `WouldBlock` with
+    /// bytes buffered, wait for the destination to be writable, otherwise for
+    /// the source to be readable.
+    pub fn buffered(&self) -> uint {
+        self.buffered
+    }
+
+    /// Moves up to `len` bytes from `src` to `dst`, returning the number of
+    /// bytes written to `dst`. Buffered bytes from a previous call are written
+    /// first. Returns `WouldBlock` when no progress could be made, and an EOF
+    /// error once `src` is exhausted and nothing is buffered.
+    pub fn transfer<S: IoHandle, D: IoHandle>(&mut self, src: &S, dst: &D, len: uint) -> MioResult<NonBlock<uint>> {
+        let mut written = 0;
+
+        loop {
+            while self.buffered > 0 {
+                match os::splice(&self.reader, dst.desc(), self.buffered) {
+                    Ok(cnt) => {
+                        self.buffered -= cnt;
+                        written += cnt;
+                    }
+                    Err(e) => {
+                        if e.is_would_block() {
+                            return Ok(progress(written));
+                        }
+
+                        return Err(e);
+                    }
+                }
+            }
+
+            if written >= len {
+                return Ok(Ready(written));
+            }
+
+            match os::splice(src.desc(), &self.writer, len - written) {
+                Ok(0) => {
+                    if written > 0 {
+                        return Ok(Ready(written));
+                    }
+
+                    return Err(MioError::eof());
+                }
+                Ok(cnt) => self.buffered += cnt,
+                Err(e) => {
+                    if e.is_would_block() {
+                        return Ok(progress(written));
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn progress(written: uint) -> NonBlock<uint> {
+    if written > 0 {
+        Ready(written)
+    } else {
+        WouldBlock
+    }
+}
+
+/// Copies up to `len` bytes from one pipe to another with `tee`, without
+/// consuming them from `src`, e.g. to mirror spliced traffic.
+#[cfg(target_os = "linux")]
+pub fn tee(src: &PipeReader, dst: &PipeWriter, len: uint) -> MioResult<NonBlock<uint>> {
+    match os::tee(src.desc(), dst.desc(), len) {
+        Ok(cnt) => Ok(Ready(cnt)),
+        Err(e) => {
+            match e.kind {
+                mek::WouldBlock => Ok(WouldBlock),
+                _ => Err(e)
+            }
+        }
+    }
+}
+
 ///writes the length of the slice supplied by Buf.bytes into the socket
 #[inline]
 pub fn write<O: IoHandle>(io: &mut O, buf: &mut Buf) -> MioResult<NonBlock<uint>> {
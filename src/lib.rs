@@ -1,11 +1,13 @@
 #![crate_name = "mio"]
 #![feature(globs)]
 #![feature(phase)]
+#![feature(unboxed_closures)]
 #![feature(unsafe_destructor)]
 // While in active dev
 #![allow(dead_code)]
 
 extern crate alloc;
+extern crate libc;
 extern crate nix;
 extern crate time;
 
@@ -21,10 +23,24 @@ pub use error::{
     MioError,
     MioErrorKind
 };
+pub use clock::{
+    Clock,
+    MockClock,
+    MonotonicClock,
+    MonotonicInstant,
+};
+pub use dispatch::{
+    Dispatcher,
+    TokenHandler,
+    TokenHandlers,
+};
 pub use handler::{
+    FnHandler,
     Handler,
+    handler_fn,
 };
 pub use io::{
+    bytes_available,
     pipe,
     NonBlock,
     IoReader,
@@ -33,14 +49,33 @@ pub use io::{
     PipeReader,
     PipeWriter,
 };
+#[cfg(target_os = "linux")]
+pub use io::{
+    Splice,
+    tee,
+};
+pub use notify::{
+    BatchError,
+    NotifyError,
+    NotifyStats,
+    SignalWaker,
+    Waker,
+};
 pub use poll::{
-    Poll
+    Events,
+    Poll,
 };
 pub use event_loop::{
     EventLoop,
     EventLoopConfig,
     EventLoopResult,
     EventLoopSender,
+    EventLoopStats,
+    EventLoopThread,
+    ExportedIo,
+    RegistrationGuard,
+    Registry,
+    ShutdownHandle,
 };
 pub use timer::{
     Timeout,
@@ -51,6 +86,7 @@ pub use timer::{
 pub use os::token::{
     Token,
 };
+pub use os::SigSet;
 
 pub use os::event;
 
@@ -58,6 +94,8 @@ pub mod buf;
 pub mod net;
 pub mod util;
 
+mod clock;
+mod dispatch;
 mod error;
 mod event_loop;
 mod handler;
@@ -1,8 +1,10 @@
+use std::cmp::max;
 use std::fmt;
+use std::time::duration::Duration;
 use std::str::FromStr;
 use std::io::net::ip::SocketAddr as StdSocketAddr;
 use io::{IoHandle, NonBlock};
-use error::MioResult;
+use error::{MioResult, MioError};
 use buf::{Buf, MutBuf};
 use os;
 
@@ -10,43 +12,321 @@ pub use std::io::net::ip::{IpAddr, Port};
 pub use std::io::net::ip::Ipv4Addr as IPv4Addr;
 pub use std::io::net::ip::Ipv6Addr as IPv6Addr;
 
+pub use self::tcp::shard_listeners;
+
 use self::SockAddr::{InetAddr,UnixAddr};
 use self::AddressFamily::{Unix,Inet,Inet6};
 
 pub trait Socket : IoHandle {
-    fn linger(&self) -> MioResult<uint> {
-        os::linger(self.desc())
+    /// Returns the address the socket is bound to, e.g. to find out which
+    /// port the kernel picked when binding to port 0.
+    fn local_addr(&self) -> MioResult<SockAddr> {
+        os::local_addr(self.desc())
+    }
+
+    /// Returns the linger time set with `set_linger`, `None` when lingering
+    /// is disabled.
+    fn linger(&self) -> MioResult<Option<Duration>> {
+        let secs = try!(os::linger(self.desc()));
+        Ok(secs.map(|s| Duration::seconds(s as i64)))
     }
 
-    fn set_linger(&self, dur_s: uint) -> MioResult<()> {
-        os::set_linger(self.desc(), dur_s)
+    /// Controls how closing the socket treats unsent data. `None`, the
+    /// default, closes gracefully in the background. A duration makes close
+    /// wait for unsent data to be sent for at most that long, rounded up to
+    /// whole seconds, and a zero duration aborts the connection with a RST,
+    /// skipping TIME_WAIT.
+    fn set_linger(&self, dur: Option<Duration>) -> MioResult<()> {
+        os::set_linger(self.desc(), dur.map(linger_secs))
     }
 
+    /// Allows binding an address that still has connections in TIME_WAIT.
+    /// Unlike `set_reuseport`, this does not allow two sockets to be bound
+    /// to the same address concurrently.
     fn set_reuseaddr(&self, val: bool) -> MioResult<()> {
         os::set_reuseaddr(self.desc(), val)
     }
 
+    /// Allows multiple sockets, possibly in different processes, to bind
+    /// the same address and port. Must be set on each socket before `bind`.
+    /// The kernel then load balances incoming connections or datagrams
+    /// across all of them, see `shard_listeners`.
     fn set_reuseport(&self, val: bool) -> MioResult<()> {
         os::set_reuseport(self.desc(), val)
     }
+
+    /// Sets the size of the kernel's receive buffer for the socket. Linux
+    /// doubles the value to leave room for bookkeeping, and caps it to
+    /// `net.core.rmem_max`, so `recv_buffer_size` may differ from what was
+    /// set. For TCP, set it before `connect` or `listen` for it to affect the
+    /// window scale.
+    fn set_recv_buffer_size(&self, size: uint) -> MioResult<()> {
+        os::set_recv_buffer_size(self.desc(), size)
+    }
+
+    fn recv_buffer_size(&self) -> MioResult<uint> {
+        os::recv_buffer_size(self.desc())
+    }
+
+    /// Sets the size of the kernel's send buffer for the socket, capped to
+    /// `net.core.wmem_max` on Linux. A small send buffer makes writes
+    /// complete partially sooner.
+    fn set_send_buffer_size(&self, size: uint) -> MioResult<()> {
+        os::set_send_buffer_size(self.desc(), size)
+    }
+
+    fn send_buffer_size(&self) -> MioResult<uint> {
+        os::send_buffer_size(self.desc())
+    }
+
+    /// Attaches a classic BPF program that filters the packets the socket
+    /// receives (`SO_ATTACH_FILTER`), replacing any previous filter. The
+    /// program returns the number of bytes of each packet to keep, zero
+    /// drops it in the kernel before it wakes up the event loop. Linux only.
+    #[cfg(target_os = "linux")]
+    fn set_filter(&self, prog: &[SockFilter]) -> MioResult<()> {
+        os::attach_filter(self.desc(), prog)
+    }
+
+    /// Removes the filter set with `set_filter`. Linux only.
+    #[cfg(target_os = "linux")]
+    fn clear_filter(&self) -> MioResult<()> {
+        os::detach_filter(self.desc())
+    }
+
+    /// Returns the CPU that handled the most recent packet received by the
+    /// socket (`SO_INCOMING_CPU`). Linux only.
+    #[cfg(target_os = "linux")]
+    fn incoming_cpu(&self) -> MioResult<uint> {
+        os::incoming_cpu(self.desc())
+    }
+
+    /// Makes a listener in a `SO_REUSEPORT` group prefer connections that
+    /// arrive on the given CPU. Linux only.
+    #[cfg(target_os = "linux")]
+    fn set_incoming_cpu(&self, cpu: uint) -> MioResult<()> {
+        os::set_incoming_cpu(self.desc(), cpu)
+    }
+
+    /// Attaches a classic BPF program to the `SO_REUSEPORT` group of the
+    /// socket, which returns the index of the socket, in bind order, that
+    /// receives each new connection or datagram. An out of range index falls
+    /// back to the default hashing. See `tcp::reuseport_cpu_filter`. Linux
+    /// only.
+    #[cfg(target_os = "linux")]
+    fn attach_reuseport_cbpf(&self, prog: &[SockFilter]) -> MioResult<()> {
+        os::attach_reuseport_cbpf(self.desc(), prog)
+    }
+
+    /// Restricts the socket to sending and receiving through the named
+    /// interface, e.g. "eth1", regardless of the routing table. Uses
+    /// `SO_BINDTODEVICE` on Linux, which may require CAP_NET_RAW, and
+    /// `IP_BOUND_IF` on OS X, which only applies to IPv4 sockets.
+    fn bind_to_device(&self, name: &str) -> MioResult<()> {
+        os::bind_to_device(self.desc(), name)
+    }
+
+    /// Returns the number of bytes that can be read without blocking, for a
+    /// datagram socket the size of the next datagram on Linux, and of all
+    /// queued data on OS X. See `mio::bytes_available`.
+    fn bytes_available(&self) -> MioResult<uint> {
+        os::bytes_available(self.desc())
+    }
+
+    /// Retrieves and clears the socket's pending error (`SO_ERROR`), e.g.
+    /// the reason a non-blocking connect failed.
+    fn take_socket_error(&self) -> MioResult<Option<MioError>> {
+        os::take_socket_error(self.desc())
+    }
+}
+
+/// Options of the IP layer, only available on TCP and UDP sockets.
+pub trait InetSocket : Socket {
+    /// Controls whether an IPv6 socket is restricted to IPv6, must be set
+    /// before `bind`. When disabled, a socket bound to `::` also accepts IPv4
+    /// traffic, with peers showing up as v4-mapped `::ffff:a.b.c.d`
+    /// addresses. The default differs between platforms, Linux is dual-stack
+    /// unless `net.ipv6.bindv6only` is set.
+    fn set_v6only(&self, val: bool) -> MioResult<()> {
+        os::set_ipv6_v6only(self.desc(), val)
+    }
+
+    fn v6only(&self) -> MioResult<bool> {
+        os::ipv6_v6only(self.desc())
+    }
+
+    /// Sets the time to live of outgoing IPv4 unicast packets (`IP_TTL`),
+    /// the number of hops after which routers drop them.
+    fn set_ttl(&self, ttl: u8) -> MioResult<()> {
+        os::set_ip_ttl(self.desc(), ttl)
+    }
+
+    fn ttl(&self) -> MioResult<u8> {
+        os::ip_ttl(self.desc())
+    }
+
+    /// Sets the hop limit of outgoing IPv6 unicast packets
+    /// (`IPV6_UNICAST_HOPS`), the IPv6 counterpart of `set_ttl`.
+    fn set_unicast_hops(&self, hops: u8) -> MioResult<()> {
+        os::set_ipv6_unicast_hops(self.desc(), hops)
+    }
+
+    fn unicast_hops(&self) -> MioResult<u8> {
+        os::ipv6_unicast_hops(self.desc())
+    }
+
+    /// Sets the type of service byte of outgoing IPv4 packets (`IP_TOS`).
+    /// The upper six bits are the DSCP, e.g. `46 << 2` marks packets for
+    /// expedited forwarding, the lower two bits are used for ECN.
+    fn set_tos(&self, tos: u8) -> MioResult<()> {
+        os::set_ip_tos(self.desc(), tos)
+    }
+
+    fn tos(&self) -> MioResult<u8> {
+        os::ip_tos(self.desc())
+    }
+
+    /// Sets the traffic class of outgoing IPv6 packets (`IPV6_TCLASS`), the
+    /// IPv6 counterpart of `set_tos`.
+    fn set_tclass(&self, tclass: u8) -> MioResult<()> {
+        os::set_ipv6_tclass(self.desc(), tclass)
+    }
+
+    fn tclass(&self) -> MioResult<u8> {
+        os::ipv6_tclass(self.desc())
+    }
+
+    /// Allows the socket to bind to a non-local address, must be set before
+    /// `bind`. Transparent proxies use it together with TPROXY firewall
+    /// rules to intercept connections while keeping the original
+    /// destination. Requires CAP_NET_ADMIN, Linux only.
+    #[cfg(target_os = "linux")]
+    fn set_ip_transparent(&self, val: bool) -> MioResult<()> {
+        os::set_ip_transparent(self.desc(), val)
+    }
+
+    /// Allows the socket to bind to an address that is not assigned to any
+    /// interface yet (`IP_FREEBIND`), must be set before `bind`. A service
+    /// can then listen on a failover address before it moves to the host.
+    /// Unlike `set_ip_transparent`, no privileges are needed. Linux only,
+    /// applies to IPv6 sockets as well.
+    #[cfg(target_os = "linux")]
+    fn set_freebind(&self, val: bool) -> MioResult<()> {
+        os::set_ip_freebind(self.desc(), val)
+    }
+}
+
+// Rounds a linger time up to whole seconds, so that only a zero (or
+// negative) duration turns into the abortive close
+fn linger_secs(dur: Duration) -> uint {
+    let nanos = match dur.num_nanoseconds() {
+        Some(nanos) => nanos,
+        None => return max(0, dur.num_seconds()) as uint
+    };
+
+    if nanos <= 0 {
+        return 0;
+    }
+
+    ((nanos - 1) / 1_000_000_000 + 1) as uint
+}
+
+/// An instruction of a classic BPF program, laid out like the kernel's
+/// `struct sock_filter`.
+#[repr(C)]
+#[deriving(Show, Clone, PartialEq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl SockFilter {
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> SockFilter {
+        SockFilter { code: code, jt: jt, jf: jf, k: k }
+    }
 }
 
 pub trait MulticastSocket : Socket {
+    /// `join_multicast_v4` taking the interface as an `Option`, `None` lets
+    /// the kernel pick one.
     fn join_multicast_group(&self, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
         os::join_multicast_group(self.desc(), addr, interface)
     }
 
+    /// `leave_multicast_v4` taking the interface as an `Option`.
     fn leave_multicast_group(&self, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
         os::leave_multicast_group(self.desc(), addr, interface)
     }
 
+    /// Joins the IPv4 multicast group `addr` on the interface with the
+    /// address `interface`, `0.0.0.0` lets the kernel pick one. Fails with
+    /// EINVAL for IPv6 addresses.
+    fn join_multicast_v4(&self, addr: &IpAddr, interface: &IpAddr) -> MioResult<()> {
+        os::join_multicast_group(self.desc(), addr, &Some(*interface))
+    }
+
+    /// Leaves the IPv4 multicast group `addr` joined on the interface with
+    /// the address `interface` with `join_multicast_v4`.
+    fn leave_multicast_v4(&self, addr: &IpAddr, interface: &IpAddr) -> MioResult<()> {
+        os::leave_multicast_group(self.desc(), addr, &Some(*interface))
+    }
+
+    /// Joins the IPv6 multicast group `addr` on the interface with the
+    /// index `interface`, 0 lets the kernel pick one. IPv6 groups are joined
+    /// by interface index, as link local addresses are not unique across
+    /// interfaces. Fails with EINVAL for IPv4 addresses.
+    fn join_multicast_v6(&self, addr: &IpAddr, interface: uint) -> MioResult<()> {
+        os::join_multicast_group_v6(self.desc(), addr, interface)
+    }
+
+    /// Leaves the IPv6 multicast group `addr` joined on the interface with
+    /// the index `interface` with `join_multicast_v6`.
+    fn leave_multicast_v6(&self, addr: &IpAddr, interface: uint) -> MioResult<()> {
+        os::leave_multicast_group_v6(self.desc(), addr, interface)
+    }
+
+    /// Sets the time to live of outgoing IPv4 multicast datagrams, which
+    /// limits how far they travel. Defaults to 1, the local network.
     fn set_multicast_ttl(&self, val: u8) -> MioResult<()> {
         os::set_multicast_ttl(self.desc(), val)
     }
+
+    /// Controls whether outgoing IPv4 multicast datagrams are delivered to
+    /// sockets on this host that joined the group, including the sending
+    /// socket itself. Enabled by default.
+    fn set_multicast_loop(&self, val: bool) -> MioResult<()> {
+        os::set_multicast_loop(self.desc(), val)
+    }
+
+    /// Sends IPv4 multicast datagrams through the interface with the address
+    /// `interface` rather than the one picked by the routing table.
+    fn set_multicast_if(&self, interface: &IpAddr) -> MioResult<()> {
+        os::set_multicast_if(self.desc(), interface)
+    }
+
+    /// The IPv6 counterpart of `set_multicast_ttl`.
+    fn set_multicast_hops_v6(&self, val: u8) -> MioResult<()> {
+        os::set_multicast_hops_v6(self.desc(), val)
+    }
+
+    /// The IPv6 counterpart of `set_multicast_loop`.
+    fn set_multicast_loop_v6(&self, val: bool) -> MioResult<()> {
+        os::set_multicast_loop_v6(self.desc(), val)
+    }
+
+    /// Sends IPv6 multicast datagrams through the interface with the index
+    /// `interface`, 0 restores the default.
+    fn set_multicast_if_v6(&self, interface: uint) -> MioResult<()> {
+        os::set_multicast_if_v6(self.desc(), interface)
+    }
 }
 
 pub trait UnconnectedSocket {
     fn send_to(&mut self, buf: &mut Buf, tgt: &SockAddr) -> MioResult<NonBlock<()>>;
+    /// Receives a datagram and returns its source. The part of a datagram
+    /// that does not fit into the buffer is discarded.
     fn recv_from(&mut self, buf: &mut MutBuf) -> MioResult<NonBlock<SockAddr>>;
 }
 
@@ -129,16 +409,34 @@ impl fmt::Show for SockAddr {
 pub enum SocketType {
     Dgram,
     Stream,
+    /// Connection oriented like `Stream`, but preserving message boundaries.
+    /// Only supported for Unix domain sockets.
+    SeqPacket,
+}
+
+/// Which halves of a connection to close with `TcpSocket::shutdown`
+#[deriving(Show, PartialEq)]
+pub enum Shutdown {
+    /// Further reads return EOF
+    Read,
+    /// Sends a FIN to the peer once buffered data has been sent, further
+    /// writes fail
+    Write,
+    Both,
 }
 
 pub mod tcp {
+    use std::io::fs::File;
+    use std::os::unix::AsRawFd;
+    use std::cmp::max;
+    use std::time::duration::Duration;
     use os;
     use error::MioResult;
     use buf::{Buf, MutBuf};
     use io;
     use io::{IoHandle, IoAcceptor, IoReader, IoWriter, NonBlock};
     use io::NonBlock::{Ready, WouldBlock};
-    use net::{AddressFamily, Socket, SockAddr};
+    use net::{AddressFamily, InetSocket, Socket, SockAddr, Shutdown, SockFilter};
     use net::SocketType::Stream;
     use net::AddressFamily::{Inet, Inet6};
 
@@ -147,6 +445,34 @@ pub mod tcp {
         desc: os::IoDesc
     }
 
+    /// Reports that the zero copy sends numbered `first` to `last`, inclusive,
+    /// completed, see `TcpSocket::send_zerocopy`.
+    #[deriving(Show, Clone, PartialEq)]
+    pub struct ZeroCopyCompletion {
+        pub first: u32,
+        pub last: u32,
+        /// The kernel copied the data after all, e.g. because the route
+        /// does not support zero copy. Zero copy is not worth it for such
+        /// a connection.
+        pub copied: bool,
+    }
+
+    /// Connection statistics reported by the kernel, see `TcpSocket::info`.
+    #[deriving(Show, Clone)]
+    pub struct TcpInfo {
+        /// Smoothed round trip time
+        pub rtt: Duration,
+        /// Variance of the round trip time
+        pub rtt_var: Duration,
+        /// Total number of retransmitted segments
+        pub retransmits: u32,
+        /// Congestion window in bytes
+        pub cwnd_bytes: u64,
+        /// Recent delivery rate in bytes per second. Only reported on Linux
+        /// 4.9 and newer.
+        pub delivery_rate: Option<u64>,
+    }
+
     impl TcpSocket {
         pub fn v4() -> MioResult<TcpSocket> {
             TcpSocket::new(Inet)
@@ -160,25 +486,266 @@ pub mod tcp {
             Ok(TcpSocket { desc: try!(os::socket(family, Stream)) })
         }
 
-        /// Connects the socket to the specified address. When the operation
-        /// completes, the handler will be notified with the supplied token.
+        /// Creates a Multipath TCP socket, which can spread the connection
+        /// over several paths, e.g. wifi and cellular, if the peer supports
+        /// it. Falls back to a plain TCP socket when the kernel lacks MPTCP
+        /// support. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn mptcp_v4() -> MioResult<TcpSocket> {
+            Ok(TcpSocket { desc: try!(os::mptcp_socket(Inet)) })
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn mptcp_v6() -> MioResult<TcpSocket> {
+            Ok(TcpSocket { desc: try!(os::mptcp_socket(Inet6)) })
+        }
+
+        /// Creates a new handle to the same socket with `dup`, e.g. to share
+        /// it with another event loop or hand it to a child process. The new
+        /// descriptor is non-blocking and close-on-exec, like the original.
+        pub fn try_clone(&self) -> MioResult<TcpSocket> {
+            Ok(TcpSocket { desc: try!(os::dup(&self.desc)) })
+        }
+
+        /// Starts connecting the socket to the specified address, without
+        /// blocking. Returns `Ready` if the connection was established
+        /// immediately, and `WouldBlock` while it is in progress.
         ///
-        /// The goal of this method is to ensure that the event loop will always
-        /// notify about the connection, even if the connection happens
-        /// immediately. Otherwise, every consumer of the event loop would have
-        /// to worry about possibly-immediate connection.
-        pub fn connect(&self, addr: &SockAddr) -> MioResult<()> {
+        /// Either way, the socket becomes writable once the attempt
+        /// completes, so a handler registered for writable readiness is always
+        /// notified, even about a connection that happened immediately. A
+        /// failed attempt is reported through `Handler::error`, or can be
+        /// checked from `writable` with `take_socket_error`, which returns
+        /// e.g. the ECONNREFUSED or ETIMEDOUT error.
+        pub fn connect(&self, addr: &SockAddr) -> MioResult<NonBlock<()>> {
             debug!("socket connect; addr={}", addr);
 
             // Attempt establishing the context. This may not complete immediately.
             if try!(os::connect(&self.desc, addr)) {
-                // On some OSs, connecting to localhost succeeds immediately. In
-                // this case, queue the writable callback for execution during the
-                // next event loop tick.
+                // On some OSs, connecting to localhost succeeds immediately
                 debug!("socket connected immediately; addr={}", addr);
+                return Ok(Ready(()));
             }
 
-            Ok(())
+            Ok(WouldBlock)
+        }
+
+        /// Returns the address of the connected peer. Fails with ENOTCONN
+        /// while a non-blocking connect is still in progress.
+        pub fn peer_addr(&self) -> MioResult<SockAddr> {
+            os::peer_addr(&self.desc)
+        }
+
+        /// Returns statistics about the connection, from `TCP_INFO` on Linux
+        /// and `TCP_CONNECTION_INFO` on OS X, which reports a subset.
+        pub fn info(&self) -> MioResult<TcpInfo> {
+            os::tcp_info(&self.desc)
+        }
+
+        /// Returns the destination the peer originally connected to, for a
+        /// connection redirected to this socket by a netfilter REDIRECT or
+        /// DNAT rule (`SO_ORIGINAL_DST`). Fails with ENOENT for connections
+        /// that were not redirected. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn original_dst(&self) -> MioResult<SockAddr> {
+            os::original_dst(&self.desc)
+        }
+
+        /// Whether the connection uses MPTCP, i.e. the socket was created
+        /// with `mptcp_v4` or `mptcp_v6` and the peer negotiated it. Linux
+        /// only.
+        #[cfg(target_os = "linux")]
+        pub fn is_mptcp(&self) -> MioResult<bool> {
+            os::mptcp_negotiated(&self.desc)
+        }
+
+        /// Disables Nagle's algorithm when set, so small writes are sent
+        /// right away instead of being coalesced.
+        pub fn set_nodelay(&self, val: bool) -> MioResult<()> {
+            os::set_tcp_nodelay(&self.desc, val)
+        }
+
+        pub fn nodelay(&self) -> MioResult<bool> {
+            os::tcp_nodelay(&self.desc)
+        }
+
+        /// Bounds how long sent data may remain unacknowledged before the
+        /// connection is aborted with ETIMEDOUT (`TCP_USER_TIMEOUT`), instead
+        /// of retransmitting for up to about 15 minutes. Unlike keepalive,
+        /// this also detects peers that vanish while data is in flight.
+        /// `Duration::zero()` restores the system default.
+        #[cfg(target_os = "linux")]
+        pub fn set_user_timeout(&self, timeout: Duration) -> MioResult<()> {
+            os::set_tcp_user_timeout(&self.desc, max(0, timeout.num_milliseconds()) as uint)
+        }
+
+        /// Enables sending TCP keepalive probes on an idle connection, to
+        /// detect dead peers.
+        pub fn set_keepalive(&self, val: bool) -> MioResult<()> {
+            os::set_keepalive(&self.desc, val)
+        }
+
+        /// Sets how long the connection must be idle before keepalive probes
+        /// are sent, instead of the system default of usually two hours.
+        pub fn set_keepalive_idle(&self, secs: uint) -> MioResult<()> {
+            os::set_keepalive_idle(&self.desc, secs)
+        }
+
+        /// Sets the time between unacknowledged keepalive probes.
+        pub fn set_keepalive_interval(&self, secs: uint) -> MioResult<()> {
+            os::set_keepalive_interval(&self.desc, secs)
+        }
+
+        /// Sets the number of unacknowledged keepalive probes after which the
+        /// connection is considered dead.
+        pub fn set_keepalive_count(&self, count: uint) -> MioResult<()> {
+            os::set_keepalive_count(&self.desc, count)
+        }
+
+        /// Reads from the socket without removing the data from the receive
+        /// buffer, so the next read returns the same bytes. Useful to sniff
+        /// the protocol of a connection, e.g. TLS vs plaintext.
+        pub fn peek(&self, buf: &mut MutBuf) -> MioResult<NonBlock<uint>> {
+            match os::peek(&self.desc, buf.mut_bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Opts into client side TCP Fast Open, must be set before `connect`.
+        /// The connect then completes immediately and the SYN is only sent
+        /// with the first write, carrying its data when the kernel has a Fast
+        /// Open cookie for the server. Otherwise a regular handshake is done.
+        #[cfg(target_os = "linux")]
+        pub fn set_fastopen_connect(&self, val: bool) -> MioResult<()> {
+            os::set_tcp_fastopen_connect(&self.desc, val)
+        }
+
+        /// Sends up to `len` bytes of `file`, starting at `offset`, without
+        /// copying them through user space (`sendfile`). Returns the number
+        /// of bytes sent, which may be less than `len`, or `WouldBlock` when
+        /// the send buffer is full, in which case the transfer is resumed
+        /// once the socket is writable. `Ready(0)` means `offset` is at the end
+        /// of the file.
+        pub fn send_file(&self, file: &File, offset: u64, len: uint) -> MioResult<NonBlock<uint>> {
+            match os::sendfile(&self.desc, file.as_raw_fd(), offset, len) {
+                Ok(cnt) => Ok(Ready(cnt)),
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Allows sending with `send_zerocopy` (`SO_ZEROCOPY`). Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn set_zerocopy(&self, val: bool) -> MioResult<()> {
+            os::set_zerocopy(&self.desc, val)
+        }
+
+        /// Sends the buffer's bytes without copying them into the kernel
+        /// (`MSG_ZEROCOPY`), which pays off for writes in the order of 10KB
+        /// and more. The kernel reads the memory after the call returns, so
+        /// it must not be modified or freed before the send completed.
+        ///
+        /// Every call returning `Ready` is numbered, counting up from 0 for
+        /// the socket. Completions are queued on the socket's error queue,
+        /// which makes the socket report an error event dispatched to
+        /// `Handler::error_queue`, and are read with `zerocopy_completion`.
+        /// Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn send_zerocopy(&self, buf: &mut Buf) -> MioResult<NonBlock<uint>> {
+            match os::send_zerocopy(&self.desc, buf.bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Reads the next completion notification of `send_zerocopy` calls,
+        /// `WouldBlock` when there is none. Call it until it returns
+        /// `WouldBlock` when the socket reports an error event. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn zerocopy_completion(&self) -> MioResult<NonBlock<ZeroCopyCompletion>> {
+            loop {
+                match os::recv_zerocopy_completion(&self.desc) {
+                    Ok(Some((first, last, copied))) => {
+                        return Ok(Ready(ZeroCopyCompletion {
+                            first: first,
+                            last: last,
+                            copied: copied
+                        }));
+                    }
+                    // Skip other messages on the error queue
+                    Ok(None) => {}
+                    Err(e) => {
+                        if e.is_would_block() {
+                            return Ok(WouldBlock);
+                        }
+
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        /// Sends a byte of TCP urgent data (`MSG_OOB`), which the peer can
+        /// read ahead of the regular data with `recv_oob`.
+        pub fn send_oob(&self, byte: u8) -> MioResult<NonBlock<()>> {
+            match os::send_oob(&self.desc, byte) {
+                Ok(()) => Ok(Ready(())),
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Reads the pending byte of urgent data. Register the socket with
+        /// the `PRIORITY` interest to be notified when urgent data arrives.
+        /// Only the most recent urgent byte is kept, and reading fails with
+        /// EINVAL once it has been read.
+        pub fn recv_oob(&self) -> MioResult<NonBlock<u8>> {
+            match os::recv_oob(&self.desc) {
+                Ok(byte) => Ok(Ready(byte)),
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Closes the read half, the write half or both halves of the
+        /// connection. Shutting down only the write half lets a proxy forward
+        /// a client's FIN while it keeps relaying the response.
+        pub fn shutdown(&self, how: Shutdown) -> MioResult<()> {
+            os::shutdown(&self.desc, how)
         }
 
         pub fn bind(self, addr: &SockAddr) -> MioResult<TcpListener> {
@@ -208,12 +775,103 @@ pub mod tcp {
     impl Socket for TcpSocket {
     }
 
+    impl InetSocket for TcpSocket {
+    }
+
+    /// TLS protocol version of a kernel TLS connection
+    #[cfg(target_os = "linux")]
+    #[deriving(Show, Clone, PartialEq)]
+    pub enum TlsVersion {
+        Tls12,
+        Tls13,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub struct AesGcm128Keys {
+        pub key: [u8, ..16],
+        pub iv: [u8, ..8],
+        pub salt: [u8, ..4],
+        pub rec_seq: [u8, ..8],
+    }
+
+    #[cfg(target_os = "linux")]
+    pub struct AesGcm256Keys {
+        pub key: [u8, ..32],
+        pub iv: [u8, ..8],
+        pub salt: [u8, ..4],
+        pub rec_seq: [u8, ..8],
+    }
+
+    /// The cipher and keys negotiated by a TLS handshake for one direction
+    /// of the connection. `rec_seq` is the sequence number of the next
+    /// record.
+    #[cfg(target_os = "linux")]
+    pub enum TlsCryptoInfo {
+        AesGcm128(TlsVersion, AesGcm128Keys),
+        AesGcm256(TlsVersion, AesGcm256Keys),
+    }
+
+    /// Hands record encryption and decryption of a TLS connection to the
+    /// kernel (kTLS), after the handshake was done in user space. Once the
+    /// keys are installed, reads and writes on the socket are plaintext, and
+    /// `TcpSocket::send_file` sends encrypted file data without copying.
+    ///
+    /// Call `enable_ktls` once the connection is established, then
+    /// `set_ktls_tx` and `set_ktls_rx`. With receive offload, reading a
+    /// record other than application data, e.g. an alert, fails with EIO.
+    /// Linux only.
+    #[cfg(target_os = "linux")]
+    pub trait KernelTls : IoHandle {
+        fn enable_ktls(&self) -> MioResult<()> {
+            os::set_tcp_ulp_tls(self.desc())
+        }
+
+        fn set_ktls_tx(&self, info: &TlsCryptoInfo) -> MioResult<()> {
+            os::set_tls_crypto_info(self.desc(), true, info)
+        }
+
+        fn set_ktls_rx(&self, info: &TlsCryptoInfo) -> MioResult<()> {
+            os::set_tls_crypto_info(self.desc(), false, info)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl KernelTls for TcpSocket {
+    }
+
     #[deriving(Show)]
     pub struct TcpListener {
         desc: os::IoDesc,
     }
 
     impl TcpListener {
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<TcpListener> {
+            Ok(TcpListener { desc: try!(os::dup(&self.desc)) })
+        }
+
+        /// Opts into accepting TCP Fast Open connections, which deliver data
+        /// in the SYN, must be set before `listen`. `qlen` limits the number
+        /// of such connections that have not completed the handshake yet.
+        /// On OS X, `qlen` is only checked against zero.
+        pub fn set_fastopen(&self, qlen: uint) -> MioResult<()> {
+            os::set_tcp_fastopen(&self.desc, qlen)
+        }
+
+        /// Defers readiness of the listener until a connection has received
+        /// data (`TCP_DEFER_ACCEPT`), saving a round trip through the event
+        /// loop for protocols where the client speaks first, like HTTP.
+        /// Connections that stay silent for `secs` are accepted anyway. Not
+        /// available on OS X, which lacks accept filters.
+        #[cfg(target_os = "linux")]
+        pub fn set_defer_accept(&self, secs: uint) -> MioResult<()> {
+            os::set_tcp_defer_accept(&self.desc, secs)
+        }
+
+        /// Starts listening for connections. `backlog` is the length of the
+        /// queue of connections waiting to be accepted, further connection
+        /// attempts are dropped or refused while it is full. The kernel
+        /// silently caps it, e.g. to `net.core.somaxconn` on Linux.
         pub fn listen(self, backlog: uint) -> MioResult<TcpAcceptor> {
             try!(os::listen(self.desc(), backlog));
             Ok(TcpAcceptor { desc: self.desc })
@@ -226,17 +884,30 @@ pub mod tcp {
         }
     }
 
+    impl Socket for TcpListener {
+    }
+
+    impl InetSocket for TcpListener {
+    }
+
     #[deriving(Show)]
     pub struct TcpAcceptor {
         desc: os::IoDesc,
     }
 
     impl TcpAcceptor {
+        /// Binds and listens in one step, see `TcpListener::listen` for the
+        /// meaning of `backlog`.
         pub fn new(addr: &SockAddr, backlog: uint) -> MioResult<TcpAcceptor> {
             let sock = try!(TcpSocket::new(addr.family()));
             let listener = try!(sock.bind(addr));
             listener.listen(backlog)
         }
+
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<TcpAcceptor> {
+            Ok(TcpAcceptor { desc: try!(os::dup(&self.desc)) })
+        }
     }
 
     impl IoHandle for TcpAcceptor {
@@ -245,13 +916,52 @@ pub mod tcp {
         }
     }
 
+    /// Creates `n` listening sockets bound to the same address with
+    /// `SO_REUSEPORT`, one for each event loop of a multi-reactor server. The
+    /// kernel distributes incoming connections across the listeners, so no
+    /// shared acceptor or hand-off queue is needed.
+    pub fn shard_listeners(addr: &SockAddr, n: uint, backlog: uint) -> MioResult<Vec<TcpAcceptor>> {
+        let mut listeners = Vec::with_capacity(n);
+
+        for _ in range(0, n) {
+            let sock = try!(TcpSocket::new(addr.family()));
+
+            try!(sock.set_reuseaddr(true));
+            try!(sock.set_reuseport(true));
+
+            let listener = try!(sock.bind(addr));
+            listeners.push(try!(listener.listen(backlog)));
+        }
+
+        Ok(listeners)
+    }
+
     impl Socket for TcpAcceptor {
     }
 
-    impl IoAcceptor<TcpSocket> for TcpAcceptor {
-        fn accept(&mut self) -> MioResult<NonBlock<TcpSocket>> {
+    impl InetSocket for TcpAcceptor {
+    }
+
+    /// A reuseport program that steers each connection to the listener with
+    /// the index of the CPU handling it. Combined with `shard_listeners` and
+    /// one event loop pinned to each CPU, a connection is processed on the
+    /// same CPU from the network card to the handler. Attach it to one of
+    /// the listeners with `Socket::attach_reuseport_cbpf`. Linux only.
+    #[cfg(target_os = "linux")]
+    pub fn reuseport_cpu_filter() -> [SockFilter, ..2] {
+        // BPF_LD | BPF_W | BPF_ABS of the SKF_AD_CPU ancillary field
+        let ld_cpu = SockFilter::new(0x20, 0, 0, (-0x1000i32 + 36) as u32);
+        // BPF_RET | BPF_A
+        let ret_a = SockFilter::new(0x16, 0, 0, 0);
+
+        [ld_cpu, ret_a]
+    }
+
+    /// Accepted connections come with the address of the peer.
+    impl IoAcceptor<(TcpSocket, SockAddr)> for TcpAcceptor {
+        fn accept(&mut self) -> MioResult<NonBlock<(TcpSocket, SockAddr)>> {
             match os::accept(self.desc()) {
-                Ok(sock) => Ok(Ready(TcpSocket { desc: sock })),
+                Ok((sock, addr)) => Ok(Ready((TcpSocket { desc: sock }, addr))),
                 Err(e) => {
                     if e.is_would_block() {
                         return Ok(WouldBlock);
@@ -266,13 +976,13 @@ pub mod tcp {
 
 pub mod udp {
     use os;
-    use error::MioResult;
+    use error::{MioResult, MioError};
     use buf::{Buf, MutBuf};
     use io::{IoHandle, IoReader, IoWriter, NonBlock};
     use io::NonBlock::{Ready, WouldBlock};
-    use net::{AddressFamily, Socket, MulticastSocket, SockAddr};
+    use net::{AddressFamily, InetSocket, IpAddr, Socket, MulticastSocket, SockAddr};
     use net::SocketType::Dgram;
-    use net::AddressFamily::Inet;
+    use net::AddressFamily::{Inet, Inet6};
     use super::UnconnectedSocket;
 
     #[deriving(Show)]
@@ -280,28 +990,473 @@ pub mod udp {
         desc: os::IoDesc
     }
 
-    impl UdpSocket {
-        pub fn v4() -> MioResult<UdpSocket> {
-            UdpSocket::new(Inet)
-        }
-
-        fn new(family: AddressFamily) -> MioResult<UdpSocket> {
-            Ok(UdpSocket { desc: try!(os::socket(family, Dgram)) })
-        }
-
-        pub fn bind(&self, addr: &SockAddr) -> MioResult<()> {
-            try!(os::bind(&self.desc, addr))
-            Ok(())
-        }
+    /// The Explicit Congestion Notification codepoint of a datagram, the
+    /// lowest two bits of the TOS byte or IPv6 traffic class
+    #[deriving(Show, Clone, PartialEq)]
+    pub enum Ecn {
+        /// Not ECN capable transport
+        NotEct,
+        /// ECN capable transport, ECT(1)
+        Ect1,
+        /// ECN capable transport, ECT(0)
+        Ect0,
+        /// Congestion experienced
+        Ce,
+    }
 
-        pub fn connect(&self, addr: &SockAddr) -> MioResult<bool> {
-            os::connect(&self.desc, addr)
+    impl Ecn {
+        /// Extracts the codepoint from a TOS byte or traffic class
+        pub fn from_bits(tos: u8) -> Ecn {
+            match tos & 0x3 {
+                0 => Ecn::NotEct,
+                1 => Ecn::Ect1,
+                2 => Ecn::Ect0,
+                _ => Ecn::Ce
+            }
         }
 
-        pub fn bound(addr: &SockAddr) -> MioResult<UdpSocket> {
-            let sock = try!(UdpSocket::new(addr.family()));
-            try!(sock.bind(addr));
-            Ok(sock)
+        pub fn bits(&self) -> u8 {
+            match *self {
+                Ecn::NotEct => 0,
+                Ecn::Ect1 => 1,
+                Ecn::Ect0 => 2,
+                Ecn::Ce => 3
+            }
+        }
+    }
+
+    /// Describes a datagram received with `UdpSocket::recv_meta`
+    #[deriving(Show)]
+    pub struct RecvMeta {
+        /// The source of the datagram
+        pub addr: SockAddr,
+        /// The destination address of the datagram, which tells a socket
+        /// bound to a wildcard address which local address to reply
+        /// from with `send_to_from`. Only reported after enabling
+        /// `set_recv_pktinfo`.
+        pub dst_addr: Option<IpAddr>,
+        /// The index of the interface the datagram arrived on. Only reported
+        /// after enabling `set_recv_pktinfo`.
+        pub interface: Option<uint>,
+        /// The size of the datagrams the kernel coalesced into the buffer,
+        /// all but the last one of which have this size. Only reported
+        /// after enabling `set_gro`.
+        pub segment_size: Option<uint>,
+        /// The ECN codepoint of the datagram. Only reported after enabling
+        /// `set_recv_ecn` or `set_recv_ecn_v6`.
+        pub ecn: Option<Ecn>,
+        /// The datagram did not fit into the buffer, the rest of it was
+        /// discarded
+        pub truncated: bool,
+        /// The full size of the datagram, including any truncated part.
+        /// Only reported on Linux.
+        pub datagram_len: Option<uint>,
+    }
+
+    /// Where an error read with `UdpSocket::recv_err` originated
+    #[cfg(target_os = "linux")]
+    #[deriving(Show, PartialEq)]
+    pub enum ErrorOrigin {
+        /// The local network stack, e.g. a message too large for the path MTU
+        Local,
+        /// An ICMP message from a router or the destination
+        Icmp,
+        /// An ICMPv6 message from a router or the destination
+        Icmp6,
+        /// Another `SO_EE_ORIGIN_*` value
+        Other(u8),
+    }
+
+    /// An error read from the socket's error queue, see
+    /// `UdpSocket::set_recv_err`
+    #[cfg(target_os = "linux")]
+    #[deriving(Show)]
+    pub struct ExtendedError {
+        /// The error, e.g. ECONNREFUSED for an ICMP port unreachable
+        pub error: MioError,
+        pub origin: ErrorOrigin,
+        /// The ICMP type, for ICMP errors
+        pub kind: u8,
+        /// The ICMP code, for ICMP errors
+        pub code: u8,
+        /// Extra information, e.g. the MTU for EMSGSIZE
+        pub info: u32,
+        /// The destination of the datagram that caused the error, if the
+        /// kernel reported it
+        pub addr: Option<SockAddr>,
+        /// The node that reported the error, e.g. the router sending the
+        /// ICMP message, if known
+        pub offender: Option<SockAddr>,
+    }
+
+    /// A datagram buffer for `UdpSocket::recv_multi` and `send_multi`
+    pub struct MsgBuf {
+        buf: Vec<u8>,
+        len: uint,
+        addr: Option<SockAddr>,
+    }
+
+    impl MsgBuf {
+        /// A buffer to receive a datagram of up to `capacity` bytes into
+        pub fn with_capacity(capacity: uint) -> MsgBuf {
+            MsgBuf {
+                buf: Vec::from_elem(capacity, 0u8),
+                len: 0,
+                addr: None
+            }
+        }
+
+        /// A datagram to send to `addr`, or to the connected address for
+        /// `None`
+        pub fn from_bytes(bytes: &[u8], addr: Option<SockAddr>) -> MsgBuf {
+            MsgBuf {
+                buf: bytes.to_vec(),
+                len: bytes.len(),
+                addr: addr
+            }
+        }
+
+        /// The datagram's bytes
+        pub fn bytes(&self) -> &[u8] {
+            self.buf.slice_to(self.len)
+        }
+
+        /// The source of a received datagram, or the destination of one to
+        /// send
+        pub fn addr(&self) -> Option<&SockAddr> {
+            self.addr.as_ref()
+        }
+
+        /// Replaces the datagram to send, reusing the allocation
+        pub fn set_bytes(&mut self, bytes: &[u8], addr: Option<SockAddr>) {
+            self.buf.clear();
+            self.buf.push_all(bytes);
+            self.len = bytes.len();
+            self.addr = addr;
+        }
+    }
+
+    impl UdpSocket {
+        pub fn v4() -> MioResult<UdpSocket> {
+            UdpSocket::new(Inet)
+        }
+
+        pub fn v6() -> MioResult<UdpSocket> {
+            UdpSocket::new(Inet6)
+        }
+
+        fn new(family: AddressFamily) -> MioResult<UdpSocket> {
+            Ok(UdpSocket { desc: try!(os::socket(family, Dgram)) })
+        }
+
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<UdpSocket> {
+            Ok(UdpSocket { desc: try!(os::dup(&self.desc)) })
+        }
+
+        pub fn bind(&self, addr: &SockAddr) -> MioResult<()> {
+            try!(os::bind(&self.desc, addr))
+            Ok(())
+        }
+
+        /// Allows sending datagrams to broadcast addresses, such as
+        /// 255.255.255.255, which fail with EACCES otherwise.
+        pub fn set_broadcast(&self, val: bool) -> MioResult<()> {
+            os::set_broadcast(&self.desc, val)
+        }
+
+        pub fn broadcast(&self) -> MioResult<bool> {
+            os::broadcast(&self.desc)
+        }
+
+        /// Sets the default destination of the socket, used by `send`, and
+        /// only accepts datagrams from that address. The kernel looks up the
+        /// route once, rather than for every `send_to`, and ICMP errors for
+        /// the peer, e.g. port unreachable, are reported as socket errors
+        /// (ECONNREFUSED) through `Handler::error` and the next `recv`.
+        /// Always completes immediately.
+        pub fn connect(&self, addr: &SockAddr) -> MioResult<bool> {
+            os::connect(&self.desc, addr)
+        }
+
+        /// Sends the buffer's bytes as a single datagram to the connected
+        /// address.
+        pub fn send(&self, buf: &mut Buf) -> MioResult<NonBlock<uint>> {
+            match os::send(&self.desc, buf.bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Receives a datagram from the connected address. A datagram larger
+        /// than the buffer is truncated. Unlike `read`, an empty datagram is
+        /// returned as `Ready(0)` rather than an EOF error.
+        pub fn recv(&self, buf: &mut MutBuf) -> MioResult<NonBlock<uint>> {
+            match os::recv(&self.desc, buf.mut_bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Reads the next datagram and its source address, leaving the
+        /// datagram queued for the next `recv_from`.
+        pub fn peek_from(&self, buf: &mut MutBuf) -> MioResult<NonBlock<SockAddr>> {
+            match os::peek_from(&self.desc, buf.mut_bytes()) {
+                Ok((cnt, saddr)) => {
+                    buf.advance(cnt);
+                    Ok(Ready(saddr))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Returns the address the socket is connected to with `connect`.
+        pub fn peer_addr(&self) -> MioResult<SockAddr> {
+            os::peer_addr(&self.desc)
+        }
+
+        /// Receives a datagram into each of the buffers, in as few system
+        /// calls as possible (`recvmmsg`), returning the number of buffers
+        /// filled. The buffers after that are left untouched. Other
+        /// platforms receive one datagram at a time.
+        pub fn recv_multi(&self, msgs: &mut [MsgBuf]) -> MioResult<NonBlock<uint>> {
+            let res = {
+                let mut bufs: Vec<&mut [u8]> = msgs.iter_mut()
+                    .map(|msg| msg.buf.as_mut_slice())
+                    .collect();
+
+                os::recv_multi(&self.desc, bufs.as_mut_slice())
+            };
+
+            match res {
+                Ok(received) => {
+                    let cnt = received.len();
+
+                    for (msg, (len, addr)) in msgs.iter_mut().zip(received.into_iter()) {
+                        msg.len = len;
+                        msg.addr = Some(addr);
+                    }
+
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Sends each buffer as a datagram, in as few system calls as
+        /// possible (`sendmmsg`), returning the number of datagrams sent,
+        /// which may be less than the number of buffers. Other platforms
+        /// send one datagram at a time.
+        pub fn send_multi(&self, msgs: &[MsgBuf]) -> MioResult<NonBlock<uint>> {
+            let datagrams: Vec<(&[u8], Option<&SockAddr>)> = msgs.iter()
+                .map(|msg| (msg.bytes(), msg.addr()))
+                .collect();
+
+            match os::send_multi(&self.desc, datagrams.as_slice()) {
+                Ok(cnt) => Ok(Ready(cnt)),
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Reports the destination address and arrival interface of
+        /// received IPv4 datagrams in `recv_meta` (`IP_PKTINFO`).
+        pub fn set_recv_pktinfo(&self, val: bool) -> MioResult<()> {
+            os::set_recv_pktinfo(&self.desc, val)
+        }
+
+        /// The IPv6 counterpart of `set_recv_pktinfo`
+        /// (`IPV6_RECVPKTINFO`).
+        pub fn set_recv_pktinfo_v6(&self, val: bool) -> MioResult<()> {
+            os::set_recv_pktinfo_v6(&self.desc, val)
+        }
+
+        /// Enables UDP generic segmentation offload (`UDP_SEGMENT`). A single
+        /// send of up to 64KB is split into datagrams of `size` bytes, the
+        /// last one possibly shorter, by the kernel or the network card,
+        /// which is much cheaper than sending them one by one. 0 disables
+        /// it. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn set_segment_size(&self, size: u16) -> MioResult<()> {
+            os::set_udp_segment(&self.desc, size)
+        }
+
+        /// Enables UDP generic receive offload (`UDP_GRO`). Consecutive
+        /// datagrams of a flow are then received coalesced into one buffer,
+        /// and `recv_meta` reports the size to split them at in
+        /// `RecvMeta::segment_size`. Use `recv_meta` exclusively once this
+        /// is enabled, other receive calls do not report the segment size.
+        /// Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn set_gro(&self, val: bool) -> MioResult<()> {
+            os::set_udp_gro(&self.desc, val)
+        }
+
+        /// Queues errors caused by sent datagrams, e.g. ICMP destination
+        /// unreachable or time exceeded messages, on the socket's error queue
+        /// (`IP_RECVERR`), instead of only reporting the most recent error
+        /// through `take_socket_error`. A non-empty error queue makes the
+        /// socket report an error event, read the errors with `recv_err`.
+        /// Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn set_recv_err(&self, val: bool) -> MioResult<()> {
+            os::set_recv_err(&self.desc, val)
+        }
+
+        /// The IPv6 counterpart of `set_recv_err` (`IPV6_RECVERR`). Linux
+        /// only.
+        #[cfg(target_os = "linux")]
+        pub fn set_recv_err_v6(&self, val: bool) -> MioResult<()> {
+            os::set_recv_err_v6(&self.desc, val)
+        }
+
+        /// Reads the next error from the socket's error queue, and the
+        /// payload of the datagram that caused it into `buf`. Returns
+        /// `WouldBlock` once the queue is empty. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn recv_err(&self, buf: &mut MutBuf) -> MioResult<NonBlock<ExtendedError>> {
+            loop {
+                match os::recv_err(&self.desc, buf.mut_bytes()) {
+                    Ok((cnt, Some(err))) => {
+                        buf.advance(cnt);
+                        return Ok(Ready(err));
+                    }
+                    // Skip messages that are not errors
+                    Ok((_, None)) => {}
+                    Err(e) => {
+                        if e.is_would_block() {
+                            return Ok(WouldBlock);
+                        }
+
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        /// Reports the ECN codepoint of received IPv4 datagrams in
+        /// `recv_meta` (`IP_RECVTOS`).
+        pub fn set_recv_ecn(&self, val: bool) -> MioResult<()> {
+            os::set_recv_tos(&self.desc, val)
+        }
+
+        /// The IPv6 counterpart of `set_recv_ecn` (`IPV6_RECVTCLASS`).
+        pub fn set_recv_ecn_v6(&self, val: bool) -> MioResult<()> {
+            os::set_recv_tclass(&self.desc, val)
+        }
+
+        /// Like `send_to`, but sends the datagram from the local address
+        /// `from`, e.g. the `RecvMeta::dst_addr` of the request, so that a
+        /// socket bound to a wildcard address replies from the address the
+        /// peer sent to (`IP_PKTINFO` / `IPV6_PKTINFO`).
+        pub fn send_to_from(&self, buf: &mut Buf, tgt: &SockAddr, from: &IpAddr) -> MioResult<NonBlock<()>> {
+            match os::send_to_from(&self.desc, buf.bytes(), tgt, from) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(()))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Like `send_to`, but marks the datagram with the ECN codepoint
+        /// `ecn`, as congestion controllers such as QUIC's do per packet. The
+        /// DSCP bits are left at zero.
+        pub fn send_to_ecn(&self, buf: &mut Buf, tgt: &SockAddr, ecn: Ecn) -> MioResult<NonBlock<()>> {
+            match os::send_to_ecn(&self.desc, buf.bytes(), tgt, ecn.bits()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(()))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Returns the size of the next queued datagram without receiving
+        /// it, e.g. to allocate a large enough buffer. Linux only.
+        #[cfg(target_os = "linux")]
+        pub fn next_datagram_len(&self) -> MioResult<NonBlock<uint>> {
+            match os::next_datagram_len(&self.desc) {
+                Ok(len) => Ok(Ready(len)),
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Like `recv_from`, but also returns the metadata the socket has
+        /// been configured to report for each datagram.
+        pub fn recv_meta(&self, buf: &mut MutBuf) -> MioResult<NonBlock<RecvMeta>> {
+            match os::recv_meta(&self.desc, buf.mut_bytes()) {
+                Ok((cnt, meta)) => {
+                    buf.advance(cnt);
+                    Ok(Ready(meta))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        pub fn bound(addr: &SockAddr) -> MioResult<UdpSocket> {
+            let sock = try!(UdpSocket::new(addr.family()));
+            try!(sock.bind(addr));
+            Ok(sock)
         }
     }
 
@@ -314,6 +1469,9 @@ pub mod udp {
     impl Socket for UdpSocket {
     }
 
+    impl InetSocket for UdpSocket {
+    }
+
     impl MulticastSocket for UdpSocket {
     }
 
@@ -371,6 +1529,9 @@ pub mod udp {
             }
         }
 
+        /// Receives a datagram. A datagram larger than the buffer is silently
+        /// truncated, use `recv_meta` to find out whether it was and how
+        /// large it was.
         fn recv_from(&mut self, buf: &mut MutBuf) -> MioResult<NonBlock<SockAddr>> {
             match os::recvfrom(&self.desc, buf.mut_bytes()) {
                 Ok((cnt, saddr)) => {
@@ -390,16 +1551,41 @@ pub mod udp {
 }
 
 pub mod pipe {
+    use std::os::unix::Fd;
     use os;
     use error::MioResult;
     use buf::{Buf, MutBuf};
     use io;
     use io::{IoHandle, IoAcceptor, IoReader, IoWriter, NonBlock};
     use io::NonBlock::{Ready, WouldBlock};
-    use net::{Socket, SockAddr, SocketType};
-    use net::SocketType::Stream;
+    use net::{Socket, SockAddr, SocketType, UnconnectedSocket};
+    use net::SocketType::{Dgram, SeqPacket, Stream};
     use net::AddressFamily::Unix;
 
+    /// The identity of the process on the other end of a Unix domain
+    /// connection, see `UnixSocket::peer_cred`.
+    #[deriving(Show, Clone)]
+    pub struct PeerCred {
+        pub pid: i32,
+        pub uid: u32,
+        pub gid: u32,
+    }
+
+    /// Describes a message received with `UnixSocket::recv_fds`
+    #[deriving(Show, Clone, PartialEq)]
+    pub struct RecvFds {
+        /// Number of bytes received
+        pub bytes: uint,
+        /// Number of descriptors stored at the start of `fds`
+        pub fds: uint,
+        /// More descriptors were sent than fit into `fds`, the others were
+        /// closed
+        pub truncated: bool,
+    }
+
+    /// A non-blocking Unix domain stream or sequenced-packet socket. Create
+    /// it with `stream` or `seqpacket`, then either `connect` it to a path or
+    /// `bind` it to one to get a `UnixListener`.
     #[deriving(Show)]
     pub struct UnixSocket {
         desc: os::IoDesc
@@ -410,10 +1596,30 @@ pub mod pipe {
             UnixSocket::new(Stream)
         }
 
+        /// Creates a sequenced-packet socket. It connects, binds and accepts
+        /// like a stream socket, but every `write` is delivered as a single
+        /// message and every `read` returns at most one message, discarding
+        /// the part of it that does not fit into the buffer.
+        pub fn seqpacket() -> MioResult<UnixSocket> {
+            UnixSocket::new(SeqPacket)
+        }
+
         fn new(socket_type: SocketType) -> MioResult<UnixSocket> {
             Ok(UnixSocket { desc: try!(os::socket(Unix, socket_type)) })
         }
 
+        /// Creates a pair of connected, unnamed stream sockets, e.g. to talk
+        /// to a child process or another thread.
+        pub fn pair() -> MioResult<(UnixSocket, UnixSocket)> {
+            let (a, b) = try!(os::socketpair(Stream));
+            Ok((UnixSocket { desc: a }, UnixSocket { desc: b }))
+        }
+
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<UnixSocket> {
+            Ok(UnixSocket { desc: try!(os::dup(&self.desc)) })
+        }
+
         pub fn connect(&self, addr: &SockAddr) -> MioResult<()> {
             debug!("socket connect; addr={}", addr);
 
@@ -432,6 +1638,54 @@ pub mod pipe {
             try!(os::bind(&self.desc, addr))
             Ok(UnixListener { desc: self.desc })
         }
+
+        /// The credentials of the connected peer, as of the time it called
+        /// `connect`, so that a server can authorize a client by user. For
+        /// the connecting side they are those of the listening process.
+        pub fn peer_cred(&self) -> MioResult<PeerCred> {
+            os::peer_cred(&self.desc)
+        }
+
+        /// Sends the buffer's bytes along with the descriptors, which the peer
+        /// receives as new descriptors for the same open files or sockets
+        /// with `recv_fds`. The descriptors here remain open. At least one
+        /// byte has to be sent along with them.
+        pub fn send_fds(&self, fds: &[Fd], buf: &mut Buf) -> MioResult<NonBlock<uint>> {
+            match os::send_fds(&self.desc, buf.bytes(), fds) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Receives bytes along with up to `fds.len()` descriptors sent with
+        /// `send_fds`. The received descriptors are close-on-exec and owned by
+        /// the caller. Descriptors that do not fit into `fds` are closed and
+        /// reported as `truncated`. Unlike `read`, the peer closing the
+        /// connection is returned as zero bytes rather than an EOF error.
+        pub fn recv_fds(&self, buf: &mut MutBuf, fds: &mut [Fd]) -> MioResult<NonBlock<RecvFds>> {
+            match os::recv_fds(&self.desc, buf.mut_bytes(), fds) {
+                Ok(res) => {
+                    buf.advance(res.bytes);
+                    Ok(Ready(res))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
     }
 
     impl IoHandle for UnixSocket {
@@ -455,12 +1709,20 @@ pub mod pipe {
     impl Socket for UnixSocket {
     }
 
+    /// A Unix domain socket bound to a path, not listening yet. Binding fails
+    /// with `AddrInUse` if the path exists, e.g. left behind by a previous
+    /// process, as the socket file is not removed on close.
     #[deriving(Show)]
     pub struct UnixListener {
         desc: os::IoDesc,
     }
 
     impl UnixListener {
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<UnixListener> {
+            Ok(UnixListener { desc: try!(os::dup(&self.desc)) })
+        }
+
         pub fn listen(self, backlog: uint) -> MioResult<UnixAcceptor> {
             try!(os::listen(self.desc(), backlog));
             Ok(UnixAcceptor { desc: self.desc })
@@ -473,6 +1735,8 @@ pub mod pipe {
         }
     }
 
+    /// A listening Unix domain socket. It is readable when connections are
+    /// waiting to be accepted.
     #[deriving(Show)]
     pub struct UnixAcceptor {
         desc: os::IoDesc,
@@ -484,6 +1748,11 @@ pub mod pipe {
             let listener = try!(sock.bind(addr));
             listener.listen(backlog)
         }
+
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<UnixAcceptor> {
+            Ok(UnixAcceptor { desc: try!(os::dup(&self.desc)) })
+        }
     }
 
     impl IoHandle for UnixAcceptor {
@@ -498,7 +1767,7 @@ pub mod pipe {
     impl IoAcceptor<UnixSocket> for UnixAcceptor {
         fn accept(&mut self) -> MioResult<NonBlock<UnixSocket>> {
             match os::accept(self.desc()) {
-                Ok(sock) => Ok(Ready(UnixSocket { desc: sock })),
+                Ok((sock, _)) => Ok(Ready(UnixSocket { desc: sock })),
                 Err(e) => {
                     if e.is_would_block() {
                         return Ok(WouldBlock);
@@ -509,5 +1778,158 @@ pub mod pipe {
             }
         }
     }
+
+    /// A non-blocking Unix domain datagram socket. Message boundaries are
+    /// preserved and, unlike UDP, datagrams are never lost or reordered;
+    /// `send_to` returns `WouldBlock` while the receiver's queue is full.
+    /// `recv_from` reports senders that are not bound as `Path::new("")`.
+    #[deriving(Show)]
+    pub struct UnixDatagram {
+        desc: os::IoDesc
+    }
+
+    impl UnixDatagram {
+        /// Creates an unbound socket. It can send datagrams, but peers cannot
+        /// reply to it until it is bound to a path.
+        pub fn new() -> MioResult<UnixDatagram> {
+            Ok(UnixDatagram { desc: try!(os::socket(Unix, Dgram)) })
+        }
+
+        /// Creates a socket bound to `addr`. As with `UnixListener`, the
+        /// path must not exist yet.
+        pub fn bind(addr: &SockAddr) -> MioResult<UnixDatagram> {
+            let sock = try!(UnixDatagram::new());
+            try!(os::bind(&sock.desc, addr));
+            Ok(sock)
+        }
+
+        /// Creates a new handle to the same socket, see `TcpSocket::try_clone`.
+        pub fn try_clone(&self) -> MioResult<UnixDatagram> {
+            Ok(UnixDatagram { desc: try!(os::dup(&self.desc)) })
+        }
+
+        /// Sets the default destination of the socket, used by `send`, and
+        /// only accepts datagrams from that address. Fails with ECONNREFUSED
+        /// if nothing is bound to the path.
+        pub fn connect(&self, addr: &SockAddr) -> MioResult<()> {
+            try!(os::connect(&self.desc, addr));
+            Ok(())
+        }
+
+        /// Sends the buffer's bytes as a single datagram to the connected
+        /// address.
+        pub fn send(&self, buf: &mut Buf) -> MioResult<NonBlock<uint>> {
+            match os::send(&self.desc, buf.bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Receives a datagram. A datagram larger than the buffer is
+        /// truncated.
+        pub fn recv(&self, buf: &mut MutBuf) -> MioResult<NonBlock<uint>> {
+            match os::recv(&self.desc, buf.mut_bytes()) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Sends a datagram along with the descriptors to the connected
+        /// address, see `UnixSocket::send_fds`.
+        pub fn send_fds(&self, fds: &[Fd], buf: &mut Buf) -> MioResult<NonBlock<uint>> {
+            match os::send_fds(&self.desc, buf.bytes(), fds) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(cnt))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        /// Receives a datagram along with up to `fds.len()` descriptors, see
+        /// `UnixSocket::recv_fds`.
+        pub fn recv_fds(&self, buf: &mut MutBuf, fds: &mut [Fd]) -> MioResult<NonBlock<RecvFds>> {
+            match os::recv_fds(&self.desc, buf.mut_bytes(), fds) {
+                Ok(res) => {
+                    buf.advance(res.bytes);
+                    Ok(Ready(res))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    impl IoHandle for UnixDatagram {
+        fn desc(&self) -> &os::IoDesc {
+            &self.desc
+        }
+    }
+
+    impl Socket for UnixDatagram {
+    }
+
+    impl UnconnectedSocket for UnixDatagram {
+        fn send_to(&mut self, buf: &mut Buf, tgt: &SockAddr) -> MioResult<NonBlock<()>> {
+            match os::sendto(&self.desc, buf.bytes(), tgt) {
+                Ok(cnt) => {
+                    buf.advance(cnt);
+                    Ok(Ready(()))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+
+        fn recv_from(&mut self, buf: &mut MutBuf) -> MioResult<NonBlock<SockAddr>> {
+            match os::recvfrom(&self.desc, buf.mut_bytes()) {
+                Ok((cnt, saddr)) => {
+                    buf.advance(cnt);
+                    Ok(Ready(saddr))
+                }
+                Err(e) => {
+                    if e.is_would_block() {
+                        Ok(WouldBlock)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
 }
 
@@ -1,15 +1,21 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicInt, Relaxed};
-use error::MioResult;
+use std::{fmt, int, uint};
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicInt, AtomicUint, Relaxed, SeqCst};
+use std::time::duration::Duration;
+use time::precise_time_ns;
+use error::{MioResult, MioError};
 use io::IoHandle;
 use os;
-use util::BoundedQueue;
+use util::{BoundedQueue, UnboundedQueue};
+
+use self::NotifyError::{Full, Io};
 
 const SLEEP: int = -1;
 
 /// Send notifications to the event loop, waking it up if necessary. If the
 /// event loop is not currently sleeping, avoid using an OS wake-up strategy
-/// (eventfd, pipe, ...). Backed by a pre-allocated lock free MPMC queue.
+/// (eventfd, pipe, ...). Backed by either a pre-allocated lock free MPMC
+/// queue or, when created with `unbounded`, a growable lock free MPSC queue.
 ///
 /// TODO: Use more efficient wake-up strategy if available
 pub struct Notify<M: Send> {
@@ -24,16 +30,65 @@ impl<M: Send> Notify<M> {
         })
     }
 
+    /// Creates a notification queue that grows as needed, `notify` will never
+    /// return `Full`.
+    #[inline]
+    pub fn unbounded() -> MioResult<Notify<M>> {
+        Ok(Notify {
+            inner: Arc::new(try!(NotifyInner::unbounded()))
+        })
+    }
+
     #[inline]
     pub fn check(&self, max: uint, will_sleep: bool) -> uint {
         self.inner.check(max, will_sleep)
     }
 
+    /// Gives back messages claimed with `check` that `poll` could not return
+    /// yet, because a sender is still in the middle of pushing them. They
+    /// are claimed again by the next `check`.
     #[inline]
-    pub fn notify(&self, value: M) -> Result<(), M> {
+    pub fn unclaim(&self, cnt: uint) {
+        self.inner.unclaim(cnt)
+    }
+
+    #[inline]
+    pub fn notify(&self, value: M) -> Result<(), NotifyError<M>> {
         self.inner.notify(value)
     }
 
+    /// Pushes all messages from the iterator onto the queue, waking up the
+    /// event loop at most once for the whole batch. Returns the number of
+    /// messages queued. If the queue fills up, the message that did not fit
+    /// is returned in the error and the iterator is not advanced any further,
+    /// so it still holds the remaining messages.
+    #[inline]
+    pub fn notify_batch<I: Iterator<M>>(&self, iter: &mut I) -> Result<uint, BatchError<M>> {
+        self.inner.notify_batch(iter)
+    }
+
+    /// Sends the message on the high priority lane. Messages on this lane are
+    /// always delivered before any pending messages on the default lane.
+    #[inline]
+    pub fn notify_urgent(&self, value: M) -> Result<(), NotifyError<M>> {
+        self.inner.notify_urgent(value)
+    }
+
+    /// Blocks the current thread until there is room in the queue for the
+    /// message.
+    #[inline]
+    pub fn notify_wait(&self, value: M) -> Result<(), NotifyError<M>> {
+        self.inner.notify_blocking(value, None)
+    }
+
+    /// Blocks the current thread until there is room in the queue for the
+    /// message or the timeout elapses, in which case `Full` is returned with
+    /// the message.
+    #[inline]
+    pub fn notify_timeout(&self, value: M, timeout: Duration) -> Result<(), NotifyError<M>> {
+        self.inner.notify_blocking(value, Some(timeout))
+    }
+
     #[inline]
     pub fn poll(&self) -> Option<M> {
         self.inner.poll()
@@ -43,6 +98,47 @@ impl<M: Send> Notify<M> {
     pub fn cleanup(&self) {
         self.inner.cleanup();
     }
+
+    /// Number of messages waiting to be processed by the event loop, across
+    /// both lanes. This is an approximation when used concurrently.
+    #[inline]
+    pub fn len(&self) -> uint {
+        self.inner.queue.len() + self.inner.urgent.len()
+    }
+
+    /// Capacity of each lane, `None` if the queue is unbounded.
+    #[inline]
+    pub fn capacity(&self) -> Option<uint> {
+        self.inner.queue.capacity()
+    }
+
+    /// Returns true if a call to `notify` would currently fail with `Full`.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.inner.queue.is_full()
+    }
+
+    /// Returns an iterator that removes all pending messages from the queue.
+    /// Messages that a sender is still in the middle of pushing are left in
+    /// the queue.
+    #[inline]
+    pub fn drain<'a>(&'a self) -> Drain<'a, M> {
+        Drain {
+            notify: self,
+            remaining: 0
+        }
+    }
+
+    /// Returns the wake-up counters for this queue.
+    #[inline]
+    pub fn stats(&self) -> NotifyStats {
+        self.inner.stats()
+    }
+
+    #[inline]
+    pub fn waker(&self) -> Waker {
+        Waker { awaken: self.inner.awaken.clone() }
+    }
 }
 
 impl<M: Send> Clone for Notify<M> {
@@ -55,21 +151,51 @@ impl<M: Send> Clone for Notify<M> {
 
 struct NotifyInner<M> {
     state: AtomicInt,
-    queue: BoundedQueue<M>,
-    awaken: os::Awakener
+    // The default lane
+    queue: Queue<M>,
+    // The high priority lane, drained before the default lane
+    urgent: Queue<M>,
+    awaken: Arc<os::Awakener>,
+    // Number of sender threads blocked waiting for room in the queue
+    waiters: AtomicUint,
+    lock: Mutex<()>,
+    space: Condvar,
+    // Total number of messages sent
+    notified: AtomicUint,
+    // Number of sends that had to wake up the event loop
+    wakeups: AtomicUint,
 }
 
 impl<M: Send> NotifyInner<M> {
     fn with_capacity(capacity: uint) -> MioResult<NotifyInner<M>> {
+        NotifyInner::new(
+            Queue::Bounded(BoundedQueue::with_capacity(capacity)),
+            Queue::Bounded(BoundedQueue::with_capacity(capacity)))
+    }
+
+    fn unbounded() -> MioResult<NotifyInner<M>> {
+        NotifyInner::new(
+            Queue::Unbounded(UnboundedQueue::new()),
+            Queue::Unbounded(UnboundedQueue::new()))
+    }
+
+    fn new(queue: Queue<M>, urgent: Queue<M>) -> MioResult<NotifyInner<M>> {
         Ok(NotifyInner {
             state: AtomicInt::new(0),
-            queue: BoundedQueue::with_capacity(capacity),
-            awaken: try!(os::Awakener::new())
+            queue: queue,
+            urgent: urgent,
+            awaken: Arc::new(try!(os::Awakener::new())),
+            waiters: AtomicUint::new(0),
+            lock: Mutex::new(()),
+            space: Condvar::new(),
+            notified: AtomicUint::new(0),
+            wakeups: AtomicUint::new(0),
         })
     }
 
     fn check(&self, max: uint, will_sleep: bool) -> uint {
-        let max = max as int;
+        // `uint::MAX` claims everything, it must not wrap around to -1
+        let max = if max > int::MAX as uint { int::MAX } else { max as int };
         let mut cur = self.state.load(Relaxed);
         let mut nxt;
         let mut val;
@@ -107,23 +233,144 @@ impl<M: Send> NotifyInner<M> {
         }
     }
 
+    fn unclaim(&self, cnt: uint) {
+        // Only called by the event loop while it is awake, so the state is
+        // not SLEEP
+        self.state.fetch_add(cnt as int, Relaxed);
+    }
+
     fn poll(&self) -> Option<M> {
-        self.queue.pop()
+        // The state counter covers both lanes, so any counted message is in
+        // one of the two queues, though it may not be poppable yet: a sender
+        // that started pushing earlier may not have finished.
+        let ret = match self.urgent.pop() {
+            Some(v) => Some(v),
+            None => self.queue.pop()
+        };
+
+        // A slot has been freed, let any blocked senders know
+        if ret.is_some() && self.waiters.load(SeqCst) > 0 {
+            let _guard = self.lock.lock();
+            self.space.notify_all();
+        }
+
+        ret
+    }
+
+    fn notify_blocking(&self, mut value: M, timeout: Option<Duration>) -> Result<(), NotifyError<M>> {
+        let deadline = match timeout {
+            // Negative timeouts give up as soon as the queue is full
+            Some(t) if t <= Duration::zero() => Some(precise_time_ns()),
+            // Timeouts too long to count in nanoseconds never expire
+            Some(t) => t.num_nanoseconds().map(|ns| precise_time_ns() + ns as u64),
+            None => None
+        };
+
+        loop {
+            match self.notify(value) {
+                Err(Full(v)) => value = v,
+                ret => return ret
+            }
+
+            let guard = self.lock.lock();
+            self.waiters.fetch_add(1, SeqCst);
+
+            // Try again while holding the lock, the event loop may have freed
+            // a slot before the waiter count was incremented.
+            match self.notify(value) {
+                Err(Full(v)) => value = v,
+                ret => {
+                    self.waiters.fetch_sub(1, SeqCst);
+                    return ret;
+                }
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    let now = precise_time_ns();
+
+                    if now >= deadline {
+                        self.waiters.fetch_sub(1, SeqCst);
+                        return Err(Full(value));
+                    }
+
+                    self.space.wait_timeout(&guard, Duration::nanoseconds((deadline - now) as i64));
+                }
+                None => self.space.wait(&guard)
+            }
+
+            self.waiters.fetch_sub(1, SeqCst);
+        }
     }
 
-    fn notify(&self, value: M) -> Result<(), M> {
+    fn notify(&self, value: M) -> Result<(), NotifyError<M>> {
         // First, push the message onto the queue
-        if !self.queue.push(value) {
-            // TODO: Don't fail
-            panic!("queue full");
+        match self.queue.push(value) {
+            Ok(()) => {}
+            Err(value) => return Err(Full(value))
+        }
+
+        self.incr(1)
+    }
+
+    fn notify_batch<I: Iterator<M>>(&self, iter: &mut I) -> Result<uint, BatchError<M>> {
+        let mut cnt = 0u;
+        let mut rejected = None;
+
+        loop {
+            let value = match iter.next() {
+                Some(value) => value,
+                None => break
+            };
+
+            match self.queue.push(value) {
+                Ok(()) => cnt += 1,
+                Err(value) => {
+                    rejected = Some(value);
+                    break;
+                }
+            }
+        }
+
+        let error = if cnt > 0 {
+            match self.incr(cnt as int) {
+                Ok(()) => None,
+                Err(Io(e)) => Some(e),
+                Err(Full(..)) => unreachable!()
+            }
+        } else {
+            None
+        };
+
+        if rejected.is_none() && error.is_none() {
+            return Ok(cnt);
+        }
+
+        Err(BatchError {
+            queued: cnt,
+            rejected: rejected,
+            error: error
+        })
+    }
+
+    fn notify_urgent(&self, value: M) -> Result<(), NotifyError<M>> {
+        match self.urgent.push(value) {
+            Ok(()) => {}
+            Err(value) => return Err(Full(value))
         }
 
+        self.incr(1)
+    }
+
+    // Accounts for messages that were pushed onto one of the lanes, waking up
+    // the event loop if it is sleeping.
+    fn incr(&self, cnt: int) -> Result<(), NotifyError<M>> {
         let mut cur = self.state.load(Relaxed);
         let mut nxt;
         let mut val;
 
         loop {
-            nxt = if cur == SLEEP { 1 } else { cur + 1 };
+            nxt = if cur == SLEEP { cnt } else { cur + cnt };
             val = self.state.compare_and_swap(cur, nxt, Relaxed);
 
             if val == cur {
@@ -133,11 +380,14 @@ impl<M: Send> NotifyInner<M> {
             cur = val;
         }
 
+        self.notified.fetch_add(cnt as uint, Relaxed);
+
         if cur == SLEEP {
-            if self.awaken.wakeup().is_err() {
-                // TODO: Don't fail
-                panic!("failed to awaken event loop");
-            }
+            self.wakeups.fetch_add(1, Relaxed);
+
+            // The message is already in the queue at this point, it will be
+            // processed the next time the event loop wakes up.
+            try!(self.awaken.wakeup().map_err(Io));
         }
 
         Ok(())
@@ -146,6 +396,66 @@ impl<M: Send> NotifyInner<M> {
     fn cleanup(&self) {
         self.awaken.cleanup();
     }
+
+    fn stats(&self) -> NotifyStats {
+        NotifyStats {
+            notified: self.notified.load(Relaxed),
+            wakeups: self.wakeups.load(Relaxed)
+        }
+    }
+}
+
+// The unbounded lanes are MPSC rather than MPMC: any number of senders push,
+// but only the event loop thread pops, through `poll` and `Drain`.
+enum Queue<M> {
+    Bounded(BoundedQueue<M>),
+    Unbounded(UnboundedQueue<M>),
+}
+
+impl<M: Send> Queue<M> {
+    #[inline]
+    fn push(&self, value: M) -> Result<(), M> {
+        match *self {
+            Queue::Bounded(ref q) => q.push(value),
+            Queue::Unbounded(ref q) => {
+                q.push(value);
+                Ok(())
+            }
+        }
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<M> {
+        match *self {
+            Queue::Bounded(ref q) => q.pop(),
+            // Only the event loop thread pops
+            Queue::Unbounded(ref q) => unsafe { q.pop() }
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> uint {
+        match *self {
+            Queue::Bounded(ref q) => q.len(),
+            Queue::Unbounded(ref q) => q.len()
+        }
+    }
+
+    #[inline]
+    fn capacity(&self) -> Option<uint> {
+        match *self {
+            Queue::Bounded(ref q) => Some(q.capacity()),
+            Queue::Unbounded(_) => None
+        }
+    }
+
+    #[inline]
+    fn is_full(&self) -> bool {
+        match *self {
+            Queue::Bounded(ref q) => q.is_full(),
+            Queue::Unbounded(_) => false
+        }
+    }
 }
 
 impl<M: Send> IoHandle for Notify<M> {
@@ -153,3 +463,168 @@ impl<M: Send> IoHandle for Notify<M> {
         self.inner.awaken.desc()
     }
 }
+
+/// Counters describing how effective the wake-up coalescing is. Only sends
+/// that find the event loop sleeping issue an OS level wake-up.
+#[deriving(Clone, Show, PartialEq)]
+pub struct NotifyStats {
+    /// Total number of messages sent
+    pub notified: uint,
+    /// Number of sends that woke up the event loop
+    pub wakeups: uint,
+}
+
+impl NotifyStats {
+    /// Number of messages that were sent while the event loop was awake and
+    /// did not require a wake-up.
+    pub fn coalesced(&self) -> uint {
+        self.notified - self.wakeups
+    }
+}
+
+/// Iterator over the messages remaining in a `Notify` queue, see
+/// `Notify::drain`.
+pub struct Drain<'a, M: 'a + Send> {
+    notify: &'a Notify<M>,
+    remaining: uint
+}
+
+impl<'a, M: Send> Iterator<M> for Drain<'a, M> {
+    fn next(&mut self) -> Option<M> {
+        if self.remaining == 0 {
+            // Claim the pending messages, keeping the state counter in sync
+            // with the queue contents.
+            self.remaining = self.notify.check(uint::MAX, false);
+
+            if self.remaining == 0 {
+                return None;
+            }
+        }
+
+        match self.notify.poll() {
+            Some(msg) => {
+                self.remaining -= 1;
+                Some(msg)
+            }
+            None => {
+                // A sender is in the middle of a push, leave the rest queued
+                self.notify.unclaim(self.remaining);
+                self.remaining = 0;
+                None
+            }
+        }
+    }
+}
+
+/// Wakes up the event loop without sending a message. Unlike `notify`, this
+/// always issues a wake-up through the OS, even if the event loop is awake.
+#[deriving(Clone)]
+pub struct Waker {
+    awaken: Arc<os::Awakener>
+}
+
+impl Waker {
+    pub fn wakeup(&self) -> MioResult<()> {
+        self.awaken.wakeup()
+    }
+}
+
+/// Receiving end of the async-signal-safe wake-up path. Owned by the event
+/// loop, which registers it with the poller.
+pub struct Signal {
+    awaken: Arc<os::SignalAwakener>
+}
+
+impl Signal {
+    pub fn new() -> MioResult<Signal> {
+        Ok(Signal { awaken: Arc::new(try!(os::SignalAwakener::new())) })
+    }
+
+    pub fn waker(&self) -> SignalWaker {
+        SignalWaker { awaken: self.awaken.clone() }
+    }
+
+    pub fn cleanup(&self) {
+        self.awaken.cleanup();
+    }
+}
+
+impl IoHandle for Signal {
+    fn desc(&self) -> &os::IoDesc {
+        self.awaken.desc()
+    }
+}
+
+/// Wakes up the event loop from a Unix signal handler. `wakeup` only performs
+/// a single `write(2)`, it does not allocate or touch the message queue. The
+/// handler's `signaled` callback is invoked once the event loop wakes up.
+///
+/// The waker keeps the event loop's wake-up descriptor open, so it stays
+/// valid after the event loop has been dropped. Cloning and dropping the
+/// waker are not async-signal-safe, only `wakeup` is.
+#[deriving(Clone)]
+pub struct SignalWaker {
+    awaken: Arc<os::SignalAwakener>
+}
+
+impl SignalWaker {
+    #[inline]
+    pub fn wakeup(&self) {
+        os::signal_wakeup(self.awaken.wakeup_fd());
+    }
+}
+
+/// Returned by `Notify::notify` when a message could not be delivered.
+pub enum NotifyError<M> {
+    /// The queue is at capacity, the message is handed back to the caller.
+    Full(M),
+    /// The message was queued, but waking up the event loop failed.
+    Io(MioError),
+}
+
+impl<M> NotifyError<M> {
+    pub fn is_full(&self) -> bool {
+        match *self {
+            Full(..) => true,
+            _ => false
+        }
+    }
+
+    /// Returns the message if it was not queued.
+    pub fn into_inner(self) -> Option<M> {
+        match self {
+            Full(m) => Some(m),
+            _ => None
+        }
+    }
+}
+
+/// Returned by `Notify::notify_batch` when not every message was delivered.
+pub struct BatchError<M> {
+    /// Number of messages that were queued. These are delivered even if
+    /// waking up the event loop failed.
+    pub queued: uint,
+    /// The message that did not fit, the iterator still holds the ones after
+    /// it.
+    pub rejected: Option<M>,
+    /// Waking up the event loop failed.
+    pub error: Option<MioError>,
+}
+
+impl<M> fmt::Show for BatchError<M> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "BatchError {{ queued: {}, rejected: {}, error: {} }}",
+               self.queued,
+               if self.rejected.is_some() { "Some(..)" } else { "None" },
+               self.error)
+    }
+}
+
+impl<M> fmt::Show for NotifyError<M> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Full(..) => write!(fmt, "Full(..)"),
+            Io(ref e) => write!(fmt, "Io({})", e)
+        }
+    }
+}
@@ -1,27 +1,52 @@
 use std::mem;
+use libc::c_int;
+use nix::errno::{EINTR, EINVAL, SysError};
 use nix::fcntl::Fd;
 use nix::sys::epoll::*;
-use nix::unistd::close;
 use error::{MioResult, MioError};
-use os::IoDesc;
+use os::{IoDesc, SigSet};
 use os::event;
 
 pub struct Selector {
-    epfd: Fd
+    epfd: IoDesc,
+    // Signal mask in effect while waiting, applied atomically by epoll_pwait
+    sigmask: Option<SigSet>
 }
 
 impl Selector {
     pub fn new() -> MioResult<Selector> {
         let epfd = try!(epoll_create().map_err(MioError::from_sys_error));
 
-        Ok(Selector { epfd: epfd })
+        Ok(Selector {
+            epfd: IoDesc { fd: epfd },
+            sigmask: None
+        })
+    }
+
+    pub fn set_sigmask(&mut self, sigmask: Option<SigSet>) {
+        self.sigmask = sigmask;
+    }
+
+    /// The epoll descriptor, readable when events are pending
+    pub fn desc(&self) -> &IoDesc {
+        &self.epfd
     }
 
     /// Wait for events from the OS
     pub fn select(&mut self, evts: &mut Events, timeout_ms: uint) -> MioResult<()> {
         // Wait for epoll events for at most timeout_ms milliseconds
-        let cnt = try!(epoll_wait(self.epfd, evts.events.as_mut_slice(), timeout_ms)
-                           .map_err(MioError::from_sys_error));
+        let res = match self.sigmask {
+            Some(ref sigmask) => epoll_pwait(self.epfd.fd, evts.events.as_mut_slice(), timeout_ms, sigmask),
+            None => epoll_wait(self.epfd.fd, evts.events.as_mut_slice(), timeout_ms)
+        };
+
+        let cnt = match res {
+            Ok(cnt) => cnt,
+            // A signal was delivered while waiting, which is what the signal
+            // mask is for, report no events
+            Err(ref e) if e.kind == EINTR => 0,
+            Err(e) => return Err(MioError::from_sys_error(e))
+        };
 
         evts.len = cnt;
         Ok(())
@@ -29,28 +54,62 @@ impl Selector {
 
     /// Register event interests for the given IO handle with the OS
     pub fn register(&mut self, io: &IoDesc, token: uint, interests: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.register_fd(io.fd, token, interests, opts)
+    }
+
+    /// Register event interests for the given file descriptor with the OS
+    pub fn register_fd(&mut self, fd: Fd, token: uint, interests: event::Interest, opts: event::PollOpt) -> MioResult<()> {
         let info = EpollEvent {
             events: ioevent_to_epoll(interests, opts),
             data: token as u64
         };
 
-        epoll_ctl(self.epfd, EpollOp::EpollCtlAdd, io.fd, &info)
+        if opts.contains(event::EXCLUSIVE) {
+            let exclusive = EpollEvent {
+                events: with_exclusive(info.events),
+                data: info.data
+            };
+
+            match epoll_ctl(self.epfd.fd, EpollOp::EpollCtlAdd, fd, &exclusive) {
+                Ok(()) => return Ok(()),
+                // Kernels before 4.5 reject the unknown flag, fall back to a
+                // regular registration
+                Err(ref e) if e.kind == EINVAL => {
+                    debug!("EPOLLEXCLUSIVE not supported; fd={}", fd);
+                }
+                Err(e) => return Err(MioError::from_sys_error(e))
+            }
+        }
+
+        epoll_ctl(self.epfd.fd, EpollOp::EpollCtlAdd, fd, &info)
             .map_err(MioError::from_sys_error)
     }
 
-    /// Register event interests for the given IO handle with the OS
+    /// Register event interests for the given IO handle with the OS. The
+    /// exclusive option cannot be changed, the kernel rejects it for
+    /// `EPOLL_CTL_MOD`.
     pub fn reregister(&mut self, io: &IoDesc, token: uint, interests: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.reregister_fd(io.fd, token, interests, opts)
+    }
+
+    /// Modify event interests for the given file descriptor with the OS
+    pub fn reregister_fd(&mut self, fd: Fd, token: uint, interests: event::Interest, opts: event::PollOpt) -> MioResult<()> {
         let info = EpollEvent {
             events: ioevent_to_epoll(interests, opts),
             data: token as u64
         };
 
-        epoll_ctl(self.epfd, EpollOp::EpollCtlMod, io.fd, &info)
+        epoll_ctl(self.epfd.fd, EpollOp::EpollCtlMod, fd, &info)
             .map_err(MioError::from_sys_error)
     }
 
     /// Deregister event interests for the given IO handle with the OS
     pub fn deregister(&mut self, io: &IoDesc) -> MioResult<()> {
+        self.deregister_fd(io.fd)
+    }
+
+    /// Deregister event interests for the given file descriptor with the OS
+    pub fn deregister_fd(&mut self, fd: Fd) -> MioResult<()> {
         // The &info argument should be ignored by the system,
         // but linux < 2.6.9 required it to be not null.
         // For compatibility, we provide a dummy EpollEvent.
@@ -59,11 +118,44 @@ impl Selector {
             data: 0
         };
 
-        epoll_ctl(self.epfd, EpollOp::EpollCtlDel, io.fd, &info)
+        epoll_ctl(self.epfd.fd, EpollOp::EpollCtlDel, fd, &info)
             .map_err(MioError::from_sys_error)
     }
 }
 
+// Not known to the epoll bindings
+const EPOLLEXCLUSIVE: u32 = 1 << 28;
+
+fn epoll_pwait(epfd: Fd, events: &mut [EpollEvent], timeout_ms: uint, sigmask: &SigSet) -> Result<uint, SysError> {
+    let res = unsafe {
+        ffi::epoll_pwait(epfd, events.as_mut_ptr(), events.len() as c_int,
+                         timeout_ms as c_int, sigmask.as_raw())
+    };
+
+    if res < 0 {
+        return Err(SysError::last());
+    }
+
+    Ok(res as uint)
+}
+
+mod ffi {
+    use libc::c_int;
+    use nix::sys::epoll::EpollEvent;
+    use os::posix::ffi::sigset_t;
+
+    extern {
+        pub fn epoll_pwait(epfd: c_int, events: *mut EpollEvent, maxevents: c_int,
+                           timeout: c_int, sigmask: *const sigset_t) -> c_int;
+    }
+}
+
+fn with_exclusive(kind: EpollEventKind) -> EpollEventKind {
+    // EpollEventKind wraps the raw u32 flags, but drops unknown bits when
+    // built from them
+    unsafe { mem::transmute(kind.bits() | EPOLLEXCLUSIVE) }
+}
+
 fn ioevent_to_epoll(interest: event::Interest, opts: event::PollOpt) -> EpollEventKind {
     let mut kind = EpollEventKind::empty();
 
@@ -79,6 +171,10 @@ fn ioevent_to_epoll(interest: event::Interest, opts: event::PollOpt) -> EpollEve
         kind.insert(EPOLLRDHUP);
     }
 
+    if interest.contains(event::PRIORITY) {
+        kind.insert(EPOLLPRI);
+    }
+
     if opts.contains(event::EDGE) {
         kind.insert(EPOLLET);
     }
@@ -94,22 +190,28 @@ fn ioevent_to_epoll(interest: event::Interest, opts: event::PollOpt) -> EpollEve
     kind
 }
 
-impl Drop for Selector {
-    fn drop(&mut self) {
-        let _ = close(self.epfd);
-    }
-}
-
+/// Fixed capacity buffer of IO events, filled in place by the selector.
+/// Reusing it across polls avoids any allocation on the polling path.
 pub struct Events {
     len: uint,
-    events: [EpollEvent, ..1024]
+    events: Vec<EpollEvent>
 }
 
 impl Events {
     pub fn new() -> Events {
+        Events::with_capacity(1024)
+    }
+
+    pub fn with_capacity(cap: uint) -> Events {
+        let mut events = Vec::with_capacity(cap);
+
+        // The selector fills the buffer in, only the first `len` entries are
+        // ever read.
+        unsafe { events.set_len(cap); }
+
         Events {
             len: 0,
-            events: unsafe { mem::uninitialized() }
+            events: events
         }
     }
 
@@ -118,6 +220,17 @@ impl Events {
         self.len
     }
 
+    /// Maximum number of events returned by a single poll
+    #[inline]
+    pub fn capacity(&self) -> uint {
+        self.events.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     #[inline]
     pub fn get(&self, idx: uint) -> event::IoEvent {
         if idx >= self.len {
@@ -135,6 +248,10 @@ impl Events {
             kind = kind | event::WRITABLE;
         }
 
+        if epoll.contains(EPOLLPRI) {
+            kind = kind | event::PRIORITY;
+        }
+
         // EPOLLHUP - Usually means a socket error happened
         if epoll.contains(EPOLLERR) {
             kind = kind | event::ERROR;
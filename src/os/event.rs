@@ -4,16 +4,50 @@ use os::token::Token;
 #[deriving(PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct PollOpt(uint);
 
+/// Edge-triggered: an event is only reported when the readiness changes
+/// (`EPOLLET` / `EV_CLEAR`), so the handle must be drained until it would
+/// block.
 pub const EDGE: PollOpt    = PollOpt(0x020);
+/// Level-triggered: an event is reported on every poll for as long as the
+/// handle stays ready.
 pub const LEVEL: PollOpt   = PollOpt(0x040);
+/// Oneshot: the registration is disarmed after its first event
+/// (`EPOLLONESHOT` / `EV_ONESHOT`) until re-armed with `reregister`, so an
+/// event is never delivered twice while the handle is being processed.
 pub const ONESHOT: PollOpt = PollOpt(0x080);
+/// Exclusive: when the same handle is registered with several selectors, an
+/// event only wakes up one of them (`EPOLLEXCLUSIVE`, Linux 4.5+). Only
+/// applies to `register`, and is ignored where unsupported.
+pub const EXCLUSIVE: PollOpt = PollOpt(0x100);
 
 impl PollOpt {
+    /// Edge-triggered and oneshot, the handle has to be reregistered after
+    /// each event.
     #[inline]
     pub fn edge() -> PollOpt {
         EDGE | ONESHOT
     }
 
+    /// Level-triggered, the default of `EventLoop::register`.
+    #[inline]
+    pub fn level() -> PollOpt {
+        LEVEL
+    }
+
+    /// Level-triggered and oneshot, the handle has to be reregistered to
+    /// receive another event.
+    #[inline]
+    pub fn oneshot() -> PollOpt {
+        LEVEL | ONESHOT
+    }
+
+    /// Level-triggered and exclusive, for a listener shared by several event
+    /// loops.
+    #[inline]
+    pub fn exclusive() -> PollOpt {
+        LEVEL | EXCLUSIVE
+    }
+
     #[inline]
     pub fn empty() -> PollOpt {
         PollOpt(0)
@@ -21,7 +55,7 @@ impl PollOpt {
 
     #[inline]
     pub fn all() -> PollOpt {
-        EDGE | LEVEL | ONESHOT
+        EDGE | LEVEL | ONESHOT | EXCLUSIVE
     }
 
     #[inline]
@@ -77,7 +111,8 @@ impl fmt::Show for PollOpt {
         let flags = [
             (EDGE, "Edge-Triggered"),
             (LEVEL, "Level-Triggered"),
-            (ONESHOT, "OneShot")];
+            (ONESHOT, "OneShot"),
+            (EXCLUSIVE, "Exclusive")];
 
         for &(flag, msg) in flags.iter() {
             if self.contains(flag) {
@@ -99,10 +134,37 @@ bitflags!(
         const ERROR    = 0x004,
         const HUP      = 0x008,
         const HINTED   = 0x010,
+        const PRIORITY = 0x020,  // Urgent (TCP out-of-band) data, Linux only
         const ALL      = 0x001 | 0x002 | 0x008  //epoll checks for ERROR no matter what
     }
 )
 
+impl Interest {
+    #[inline]
+    pub fn readable() -> Interest {
+        READABLE
+    }
+
+    #[inline]
+    pub fn writable() -> Interest {
+        WRITABLE
+    }
+
+    #[inline]
+    pub fn error() -> Interest {
+        ERROR
+    }
+
+    #[inline]
+    pub fn hup() -> Interest {
+        HUP
+    }
+
+    #[inline]
+    pub fn priority() -> Interest {
+        PRIORITY
+    }
+}
 
 impl fmt::Show for Interest {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -112,7 +174,8 @@ impl fmt::Show for Interest {
             (WRITABLE, "Writable"),
             (ERROR,    "Error"),
             (HUP,      "HupHint"),
-            (HINTED,   "Hinted")];
+            (HINTED,   "Hinted"),
+            (PRIORITY, "Priority")];
 
         for &(flag, msg) in flags.iter() {
             if self.contains(flag) {
@@ -160,7 +223,8 @@ impl fmt::Show for ReadHint {
 #[deriving(Show)]
 pub struct IoEvent {
     kind: Interest,
-    token: Token
+    token: Token,
+    available: Option<uint>
 }
 
 /// IoEvent represents the raw event that the OS-specific selector
@@ -174,7 +238,28 @@ impl IoEvent {
     pub fn new(kind: Interest, token: uint) -> IoEvent {
         IoEvent {
             kind: kind,
-            token: Token(token)
+            token: Token(token),
+            available: None
+        }
+    }
+
+    /// Create a new IoEvent carrying the amount reported available by the
+    /// selector.
+    pub fn with_available(kind: Interest, token: uint, available: uint) -> IoEvent {
+        IoEvent {
+            kind: kind,
+            token: Token(token),
+            available: Some(available)
+        }
+    }
+
+    /// Returns the event with the available amount set, for selectors that
+    /// do not report it themselves.
+    pub fn with_hint(self, available: uint) -> IoEvent {
+        IoEvent {
+            kind: self.kind,
+            token: self.token,
+            available: Some(available)
         }
     }
 
@@ -182,6 +267,12 @@ impl IoEvent {
         self.token
     }
 
+    /// The readiness reported by the selector, a combination of `READABLE`,
+    /// `WRITABLE`, `ERROR` and `HUP`
+    pub fn kind(&self) -> Interest {
+        self.kind - HINTED
+    }
+
     /// Return an optional hint for a readable  handle. Currently,
     /// this method supports the HupHint, which indicates that the
     /// kernel reported that the remote side hung up. This allows a
@@ -209,6 +300,14 @@ impl IoEvent {
         hint
     }
 
+    /// For readable events, the number of bytes that can be read from a
+    /// stream, or the number of connections pending on a listener. kqueue
+    /// always reports it, with epoll it is only filled in when
+    /// `EventLoopConfig::read_available` is enabled.
+    pub fn available(&self) -> Option<uint> {
+        self.available
+    }
+
     /// This event indicated that the  handle is now readable
     pub fn is_readable(&self) -> bool {
         self.kind.contains(READABLE) || self.kind.contains(HUP)
@@ -219,6 +318,20 @@ impl IoEvent {
         self.kind.contains(WRITABLE)
     }
 
+    /// This event indicated that the peer hung up, or at least shut down
+    /// its writing side (`EPOLLRDHUP` / `EV_EOF`). Reported for handles
+    /// registered with the `HUP` interest, see also `HUPHINT`.
+    pub fn is_hup(&self) -> bool {
+        self.kind.contains(HUP)
+    }
+
+    /// This event indicated that urgent data, such as a TCP out-of-band
+    /// byte, can be read from the handle. Only reported for handles
+    /// registered with the `PRIORITY` interest, currently on Linux only.
+    pub fn is_priority(&self) -> bool {
+        self.kind.contains(PRIORITY)
+    }
+
     /// This event indicated that the  handle had an error
     pub fn is_error(&self) -> bool {
         self.kind.contains(ERROR)
@@ -1,29 +1,65 @@
 use std::mem;
+use nix::errno::EINTR;
 use nix::fcntl::Fd;
 use nix::sys::event::*;
 use nix::sys::event::EventFilter::*;
 use error::{MioResult, MioError};
-use os::IoDesc;
+use os::{IoDesc, PipeAwakener, SigSet};
 use os::event;
 use os::event::{IoEvent, Interest, PollOpt};
+use time::precise_time_ns;
 
 pub struct Selector {
-    kq: Fd,
-    changes: Events
+    kq: IoDesc,
+    changes: Events,
+    // Signal mask in effect while waiting
+    sigmask: Option<SigSet>
 }
 
 impl Selector {
     pub fn new() -> MioResult<Selector> {
         Ok(Selector {
-            kq: try!(kqueue().map_err(MioError::from_sys_error)),
-            changes: Events::new()
+            kq: IoDesc { fd: try!(kqueue().map_err(MioError::from_sys_error)) },
+            changes: Events::new(),
+            sigmask: None
         })
     }
 
+    /// kevent has no signal mask argument, the mask is swapped in around the
+    /// call. This is not race-free: unlike with epoll_pwait, a signal may be
+    /// delivered after the mask is swapped in but before kevent blocks, and
+    /// then does not interrupt the wait.
+    pub fn set_sigmask(&mut self, sigmask: Option<SigSet>) {
+        self.sigmask = sigmask;
+    }
+
+    /// The kqueue descriptor, readable when events are pending. Registration
+    /// changes are only submitted to the OS by `select`.
+    pub fn desc(&self) -> &IoDesc {
+        &self.kq
+    }
+
     pub fn select(&mut self, evts: &mut Events, timeout_ms: uint) -> MioResult<()> {
-        let cnt = try!(kevent(self.kq, self.changes.as_slice(),
-                              evts.as_mut_slice(), timeout_ms)
-                                  .map_err(MioError::from_sys_error));
+        let prev = match self.sigmask {
+            Some(ref sigmask) => Some(try!(sigmask.set_thread_mask())),
+            None => None
+        };
+
+        let res = kevent(self.kq.fd, self.changes.as_slice(),
+                         evts.as_mut_slice(), timeout_ms);
+
+        match prev {
+            Some(prev) => { let _ = prev.set_thread_mask(); }
+            None => {}
+        }
+
+        // The changes are applied before waiting, even if a signal
+        // interrupts the wait, which then reports no events
+        let cnt = match res {
+            Ok(cnt) => cnt,
+            Err(ref e) if e.kind == EINTR => 0,
+            Err(e) => return Err(MioError::from_sys_error(e))
+        };
 
         self.changes.len = 0;
 
@@ -32,28 +68,40 @@ impl Selector {
     }
 
     pub fn register(&mut self, io: &IoDesc, token: uint, interests: Interest, opts: PollOpt) -> MioResult<()> {
+        self.register_fd(io.fd, token, interests, opts)
+    }
+
+    pub fn register_fd(&mut self, fd: Fd, token: uint, interests: Interest, opts: PollOpt) -> MioResult<()> {
         debug!("registering; token={}; interests={}", token, interests);
 
-        try!(self.ev_register(io, token, EVFILT_READ, interests.contains(event::READABLE), opts));
-        try!(self.ev_register(io, token, EVFILT_WRITE, interests.contains(event::WRITABLE), opts));
+        try!(self.ev_register(fd, token, EVFILT_READ, interests.contains(event::READABLE), opts));
+        try!(self.ev_register(fd, token, EVFILT_WRITE, interests.contains(event::WRITABLE), opts));
 
         Ok(())
     }
 
     pub fn reregister(&mut self, io: &IoDesc, token: uint, interests: Interest, opts: PollOpt) -> MioResult<()> {
+        self.reregister_fd(io.fd, token, interests, opts)
+    }
+
+    pub fn reregister_fd(&mut self, fd: Fd, token: uint, interests: Interest, opts: PollOpt) -> MioResult<()> {
         // Just need to call register here since EV_ADD is a mod if already
         // registered
-        self.register(io, token, interests, opts)
+        self.register_fd(fd, token, interests, opts)
     }
 
     pub fn deregister(&mut self, io: &IoDesc) -> MioResult<()> {
-        try!(self.ev_push(io, 0, EVFILT_READ, EV_DELETE));
-        try!(self.ev_push(io, 0, EVFILT_WRITE, EV_DELETE));
+        self.deregister_fd(io.fd)
+    }
+
+    pub fn deregister_fd(&mut self, fd: Fd) -> MioResult<()> {
+        try!(self.ev_push(fd, 0, EVFILT_READ, EV_DELETE));
+        try!(self.ev_push(fd, 0, EVFILT_WRITE, EV_DELETE));
 
         Ok(())
     }
 
-    fn ev_register(&mut self, io: &IoDesc, token: uint, filter: EventFilter, enable: bool, opts: PollOpt) -> MioResult<()> {
+    fn ev_register(&mut self, fd: Fd, token: uint, filter: EventFilter, enable: bool, opts: PollOpt) -> MioResult<()> {
         let mut flags = EV_ADD;
 
         if enable {
@@ -70,16 +118,16 @@ impl Selector {
             flags = flags | EV_ONESHOT;
         }
 
-        self.ev_push(io, token, filter, flags)
+        self.ev_push(fd, token, filter, flags)
     }
 
-    fn ev_push(&mut self, io: &IoDesc, token: uint, filter: EventFilter, flags: EventFlag) -> MioResult<()> {
+    fn ev_push(&mut self, fd: Fd, token: uint, filter: EventFilter, flags: EventFlag) -> MioResult<()> {
         try!(self.maybe_flush_changes());
 
         let idx = self.changes.len;
         let ev = &mut self.changes.events[idx];
 
-        ev_set(ev, io.fd as uint, filter, flags, FilterFlag::empty(), token);
+        ev_set(ev, fd as uint, filter, flags, FilterFlag::empty(), token);
 
         self.changes.len += 1;
         Ok(())
@@ -87,7 +135,7 @@ impl Selector {
 
     fn maybe_flush_changes(&mut self) -> MioResult<()> {
         if self.changes.is_full() {
-            try!(kevent(self.kq, self.changes.as_slice(), &mut [], 0)
+            try!(kevent(self.kq.fd, self.changes.as_slice(), &mut [], 0)
                     .map_err(MioError::from_sys_error));
             self.changes.len = 0;
         }
@@ -96,16 +144,28 @@ impl Selector {
     }
 }
 
+/// Fixed capacity buffer of IO events, filled in place by the selector.
+/// Reusing it across polls avoids any allocation on the polling path.
 pub struct Events {
     len: uint,
-    events: [KEvent, ..1024]
+    events: Vec<KEvent>
 }
 
 impl Events {
     pub fn new() -> Events {
+        Events::with_capacity(1024)
+    }
+
+    pub fn with_capacity(cap: uint) -> Events {
+        let mut events = Vec::with_capacity(cap);
+
+        // The selector fills the buffer in, only the first `len` entries are
+        // ever read.
+        unsafe { events.set_len(cap); }
+
         Events {
             len: 0,
-            events: unsafe { mem::uninitialized() }
+            events: events
         }
     }
 
@@ -114,6 +174,17 @@ impl Events {
         self.len
     }
 
+    /// Maximum number of events returned by a single poll
+    #[inline]
+    pub fn capacity(&self) -> uint {
+        self.events.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     // TODO: We will get rid of this eventually in favor of an iterator
     #[inline]
     pub fn get(&self, idx: uint) -> IoEvent {
@@ -149,6 +220,11 @@ impl Events {
             }
         }
 
+        if ev.filter == EVFILT_READ {
+            // Bytes in the receive buffer, or the listen backlog
+            return IoEvent::with_available(kind, token, ev.data as uint);
+        }
+
         IoEvent::new(kind, token)
     }
 
@@ -165,3 +241,139 @@ impl Events {
         self.events.as_mut_slice()
     }
 }
+
+/*
+ *
+ * ===== Awakener =====
+ *
+ */
+
+// Identifier of the user event, there is only one per awakener kqueue
+const WAKEUP: uint = 0;
+
+/// Wakes up the event loop using an `EVFILT_USER` event on a dedicated
+/// kqueue. The kqueue descriptor becomes readable when the user event is
+/// triggered, so it can be registered with the event loop like any other
+/// handle. Falls back to a pipe if the platform does not support user events.
+pub enum Awakener {
+    User(IoDesc),
+    Pipe(PipeAwakener),
+}
+
+impl Awakener {
+    pub fn new() -> MioResult<Awakener> {
+        match Awakener::user() {
+            Ok(awakener) => Ok(awakener),
+            Err(_) => {
+                debug!("EVFILT_USER not supported, falling back to pipe awakener");
+                Ok(Awakener::Pipe(try!(PipeAwakener::new())))
+            }
+        }
+    }
+
+    fn user() -> MioResult<Awakener> {
+        let kq = IoDesc { fd: try!(kqueue().map_err(MioError::from_sys_error)) };
+        let mut ev: KEvent = unsafe { mem::uninitialized() };
+
+        ev_set(&mut ev, WAKEUP, EVFILT_USER, EV_ADD | EV_CLEAR, FilterFlag::empty(), 0);
+
+        try!(kevent(kq.fd, &[ev], &mut [], 0)
+                .map_err(MioError::from_sys_error));
+
+        Ok(Awakener::User(kq))
+    }
+
+    pub fn wakeup(&self) -> MioResult<()> {
+        match *self {
+            Awakener::User(ref kq) => {
+                let mut ev: KEvent = unsafe { mem::uninitialized() };
+
+                ev_set(&mut ev, WAKEUP, EVFILT_USER, EventFlag::empty(), NOTE_TRIGGER, 0);
+
+                kevent(kq.fd, &[ev], &mut [], 0)
+                    .map(|_| ())
+                    .map_err(MioError::from_sys_error)
+            }
+            Awakener::Pipe(ref pipe) => pipe.wakeup()
+        }
+    }
+
+    pub fn desc(&self) -> &IoDesc {
+        match *self {
+            Awakener::User(ref kq) => kq,
+            Awakener::Pipe(ref pipe) => pipe.desc()
+        }
+    }
+
+    pub fn cleanup(&self) {
+        match *self {
+            Awakener::User(ref kq) => {
+                let mut evts: [KEvent, ..1] = unsafe { mem::uninitialized() };
+
+                // Retrieving the event resets it thanks to EV_CLEAR
+                let _ = kevent(kq.fd, &[], evts.as_mut_slice(), 0);
+            }
+            Awakener::Pipe(ref pipe) => pipe.cleanup()
+        }
+    }
+}
+
+/*
+ *
+ * ===== TimerFd =====
+ *
+ */
+
+// Identifier of the timer event, there is only one per timer kqueue
+const TIMER: uint = 0;
+
+/// A timer backed by an `EVFILT_TIMER` event on a dedicated kqueue, which
+/// becomes readable when the timer expires.
+pub struct TimerFd {
+    kq: IoDesc
+}
+
+impl TimerFd {
+    pub fn new() -> MioResult<TimerFd> {
+        Ok(TimerFd {
+            kq: IoDesc { fd: try!(kqueue().map_err(MioError::from_sys_error)) }
+        })
+    }
+
+    /// Arms the timer to expire at the given monotonic time in nanoseconds,
+    /// as returned by `precise_time_ns`, replacing any previous deadline.
+    /// Zero disarms the timer.
+    pub fn set_at_ns(&self, at: u64) -> MioResult<()> {
+        let mut ev: KEvent = unsafe { mem::uninitialized() };
+
+        if at == 0 {
+            ev_set(&mut ev, TIMER, EVFILT_TIMER, EV_DELETE, FilterFlag::empty(), 0);
+
+            // Fails when the timer is not armed
+            let _ = kevent(self.kq.fd, &[ev], &mut [], 0);
+            return Ok(());
+        }
+
+        // kqueue timers are relative
+        let now = precise_time_ns();
+        let delay = if at > now { at - now } else { 0 };
+
+        ev_set(&mut ev, TIMER, EVFILT_TIMER, EV_ADD | EV_ONESHOT, NOTE_NSECONDS, 0);
+        ev.data = delay as int;
+
+        kevent(self.kq.fd, &[ev], &mut [], 0)
+            .map(|_| ())
+            .map_err(MioError::from_sys_error)
+    }
+
+    pub fn desc(&self) -> &IoDesc {
+        &self.kq
+    }
+
+    pub fn cleanup(&self) {
+        let mut evts: [KEvent, ..1] = unsafe { mem::uninitialized() };
+
+        // Retrieving the event consumes the expiration
+        let _ = kevent(self.kq.fd, &[], evts.as_mut_slice(), 0);
+    }
+}
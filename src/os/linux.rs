@@ -1,13 +1,30 @@
-use std::mem;
+use std::{cmp, mem, ptr};
+use std::time::duration::Duration;
+use nix::fcntl::Fd;
+use nix::errno::SysError;
 use super::posix::*;
 use error::{MioResult, MioError};
-
-const MARK: &'static [u8] = b"0x000x000x000x000x000x000x000x01";
+use libc::{c_void, size_t};
+use net::{SockAddr, SockFilter};
+use net::tcp::{TcpInfo, TlsCryptoInfo, TlsVersion};
+use net::udp::{ExtendedError, ErrorOrigin};
 
 mod nix {
+    pub use nix::c_int;
+    pub use libc::c_uint;
     pub use nix::sys::eventfd::*;
+    pub use nix::sys::socket::*;
 }
 
+/*
+ *
+ * ===== Awakener =====
+ *
+ */
+
+/// Wakes up the event loop using an eventfd. Unlike the pipe based awakener,
+/// only a single file descriptor is needed and any number of wake-ups are
+/// collapsed into the eventfd counter, so a single read resets it.
 pub struct Awakener {
     eventfd: IoDesc
 }
@@ -18,24 +35,31 @@ impl Awakener {
     }
 
     pub fn wakeup(&self) -> MioResult<()> {
-        write(&self.eventfd, MARK)
-            .map(|_| ())
+        // eventfd expects a native endian u64 to add to its counter
+        let mark: [u8, ..8] = unsafe { mem::transmute(1u64) };
+
+        match write(&self.eventfd, mark.as_slice()) {
+            Ok(_) => Ok(()),
+            // The counter is saturated, the event loop will wake up anyway
+            Err(ref e) if e.is_would_block() => Ok(()),
+            Err(e) => Err(e)
+        }
     }
 
     pub fn desc(&self) -> &IoDesc {
         &self.eventfd
     }
 
+    /// The descriptor to write to in order to trigger a wake-up
+    pub fn wakeup_fd(&self) -> Fd {
+        self.eventfd.fd
+    }
+
     pub fn cleanup(&self) {
         let mut buf: [u8, ..8] = unsafe { mem::uninitialized() };
 
-        loop {
-            // Consume data until all bytes are purged
-            match read(&self.eventfd, buf.as_mut_slice()) {
-                Ok(_) => {}
-                Err(_) => return
-            }
-        }
+        // Reading resets the counter to zero
+        let _ = read(&self.eventfd, buf.as_mut_slice());
     }
 }
 
@@ -45,3 +69,701 @@ fn eventfd() -> MioResult<IoDesc> {
 
     Ok(IoDesc { fd: fd })
 }
+
+/*
+ *
+ * ===== TimerFd =====
+ *
+ */
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// A `CLOCK_MONOTONIC` timerfd. Deadlines are absolute, so the timer expires
+/// on time regardless of how long the event loop spends dispatching events.
+pub struct TimerFd {
+    desc: IoDesc
+}
+
+impl TimerFd {
+    pub fn new() -> MioResult<TimerFd> {
+        let fd = unsafe {
+            ffi::timerfd_create(ffi::CLOCK_MONOTONIC, ffi::TFD_CLOEXEC | ffi::TFD_NONBLOCK)
+        };
+
+        if fd < 0 {
+            return Err(MioError::from_sys_error(SysError::last()));
+        }
+
+        Ok(TimerFd { desc: IoDesc { fd: fd } })
+    }
+
+    /// Arms the timer to expire at the given `CLOCK_MONOTONIC` time in
+    /// nanoseconds, replacing any previous deadline. Zero disarms the timer.
+    pub fn set_at_ns(&self, at: u64) -> MioResult<()> {
+        let spec = ffi::itimerspec {
+            it_interval: ffi::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: ffi::timespec {
+                tv_sec: (at / NS_PER_SEC) as ffi::time_t,
+                tv_nsec: (at % NS_PER_SEC) as ffi::c_long
+            }
+        };
+
+        let res = unsafe {
+            ffi::timerfd_settime(self.desc.fd, ffi::TFD_TIMER_ABSTIME, &spec, ptr::null_mut())
+        };
+
+        if res < 0 {
+            return Err(MioError::from_sys_error(SysError::last()));
+        }
+
+        Ok(())
+    }
+
+    pub fn desc(&self) -> &IoDesc {
+        &self.desc
+    }
+
+    pub fn cleanup(&self) {
+        let mut buf: [u8, ..8] = unsafe { mem::uninitialized() };
+
+        // Reading the expiration count clears the readiness
+        let _ = read(&self.desc, buf.as_mut_slice());
+    }
+}
+
+/*
+ *
+ * ===== Splice =====
+ *
+ */
+
+// Moves up to len bytes from src to dst, one of which must be a pipe, without
+// copying them through user space
+pub fn splice(src: &IoDesc, dst: &IoDesc, len: uint) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::splice(src.fd, ptr::null_mut(), dst.fd, ptr::null_mut(), len as ffi::size_t,
+                    ffi::SPLICE_F_MOVE | ffi::SPLICE_F_NONBLOCK)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Copies up to len bytes from the src pipe to the dst pipe, leaving them in
+// src
+pub fn tee(src: &IoDesc, dst: &IoDesc, len: uint) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::tee(src.fd, dst.fd, len as ffi::size_t, ffi::SPLICE_F_NONBLOCK)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+/*
+ *
+ * ===== Kernel TLS =====
+ *
+ */
+
+// Attaches the "tls" upper layer protocol to a connected TCP socket
+pub fn set_tcp_ulp_tls(io: &IoDesc) -> MioResult<()> {
+    let name = b"tls";
+
+    let res = unsafe {
+        ffi::setsockopt(io.fd, ffi::SOL_TCP, ffi::TCP_ULP,
+                        name.as_ptr() as *const c_void, name.len() as ffi::socklen_t)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+// Installs the keys for one direction, tx or rx, of a kernel TLS socket
+pub fn set_tls_crypto_info(io: &IoDesc, tx: bool, info: &TlsCryptoInfo) -> MioResult<()> {
+    let name = if tx { ffi::TLS_TX } else { ffi::TLS_RX };
+
+    let version = |v: TlsVersion| {
+        match v {
+            TlsVersion::Tls12 => ffi::TLS_1_2_VERSION,
+            TlsVersion::Tls13 => ffi::TLS_1_3_VERSION
+        }
+    };
+
+    let res = match *info {
+        TlsCryptoInfo::AesGcm128(v, ref keys) => {
+            let raw = ffi::tls12_crypto_info_aes_gcm_128 {
+                version: version(v),
+                cipher_type: ffi::TLS_CIPHER_AES_GCM_128,
+                iv: keys.iv,
+                key: keys.key,
+                salt: keys.salt,
+                rec_seq: keys.rec_seq
+            };
+
+            unsafe {
+                ffi::setsockopt(io.fd, ffi::SOL_TLS, name,
+                                &raw as *const ffi::tls12_crypto_info_aes_gcm_128 as *const c_void,
+                                mem::size_of::<ffi::tls12_crypto_info_aes_gcm_128>() as ffi::socklen_t)
+            }
+        }
+        TlsCryptoInfo::AesGcm256(v, ref keys) => {
+            let raw = ffi::tls12_crypto_info_aes_gcm_256 {
+                version: version(v),
+                cipher_type: ffi::TLS_CIPHER_AES_GCM_256,
+                iv: keys.iv,
+                key: keys.key,
+                salt: keys.salt,
+                rec_seq: keys.rec_seq
+            };
+
+            unsafe {
+                ffi::setsockopt(io.fd, ffi::SOL_TLS, name,
+                                &raw as *const ffi::tls12_crypto_info_aes_gcm_256 as *const c_void,
+                                mem::size_of::<ffi::tls12_crypto_info_aes_gcm_256>() as ffi::socklen_t)
+            }
+        }
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+/*
+ *
+ * ===== Socket options =====
+ *
+ */
+
+// The length of the accept queue of a TCP listener
+pub fn listen_backlog(fd: Fd) -> Option<uint> {
+    let mut info: ffi::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::tcp_info>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(fd, nix::IPPROTO_TCP, ffi::TCP_INFO,
+                        &mut info as *mut ffi::tcp_info as *mut c_void, &mut len)
+    };
+
+    if res < 0 || info.tcpi_state != ffi::TCP_LISTEN {
+        return None;
+    }
+
+    // For listeners, the kernel reports the length of the accept queue here
+    Some(info.tcpi_unacked as uint)
+}
+
+// The CPU that processed the socket's most recent incoming packet, or for a
+// listener, the CPU whose traffic it accepts
+pub fn incoming_cpu(io: &IoDesc) -> MioResult<uint> {
+    let mut v: nix::c_int = 0;
+
+    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_INCOMING_CPU, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as uint)
+}
+
+pub fn set_incoming_cpu(io: &IoDesc, cpu: uint) -> MioResult<()> {
+    let v = cpu as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_INCOMING_CPU, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Attaches a classic BPF program that picks the socket of the SO_REUSEPORT
+// group to hand each connection or datagram to
+pub fn attach_reuseport_cbpf(io: &IoDesc, prog: &[SockFilter]) -> MioResult<()> {
+    set_sock_fprog(io, ffi::SO_ATTACH_REUSEPORT_CBPF, prog)
+}
+
+// Attaches a classic BPF program that decides which packets the socket
+// receives, replacing any previous one
+pub fn attach_filter(io: &IoDesc, prog: &[SockFilter]) -> MioResult<()> {
+    set_sock_fprog(io, ffi::SO_ATTACH_FILTER, prog)
+}
+
+pub fn detach_filter(io: &IoDesc) -> MioResult<()> {
+    let v: nix::c_int = 0;
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_DETACH_FILTER, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+fn set_sock_fprog(io: &IoDesc, name: nix::c_int, prog: &[SockFilter]) -> MioResult<()> {
+    let fprog = ffi::sock_fprog {
+        len: prog.len() as u16,
+        // SockFilter has the layout of struct sock_filter
+        filter: prog.as_ptr() as *const c_void
+    };
+
+    let res = unsafe {
+        ffi::setsockopt(io.fd, nix::SOL_SOCKET, name,
+                        &fprog as *const ffi::sock_fprog as *const c_void,
+                        mem::size_of::<ffi::sock_fprog>() as ffi::socklen_t)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+pub fn tcp_info(io: &IoDesc) -> MioResult<TcpInfo> {
+    let mut info: ffi::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::tcp_info>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_INFO,
+                        &mut info as *mut ffi::tcp_info as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    // Kernels older than 4.9 return a shorter struct, without the delivery rate
+    let delivery_rate = if len as uint >= mem::size_of::<ffi::tcp_info>() {
+        Some(info.tcpi_delivery_rate)
+    } else {
+        None
+    };
+
+    Ok(TcpInfo {
+        rtt: Duration::microseconds(info.tcpi_rtt as i64),
+        rtt_var: Duration::microseconds(info.tcpi_rttvar as i64),
+        retransmits: info.tcpi_total_retrans,
+        cwnd_bytes: info.tcpi_snd_cwnd as u64 * info.tcpi_snd_mss as u64,
+        delivery_rate: delivery_rate
+    })
+}
+
+/*
+ *
+ * ===== Batched datagrams =====
+ *
+ */
+
+// Receives up to one datagram into each buffer with a single recvmmsg call,
+// returning the size and source of each datagram received
+pub fn recv_multi(io: &IoDesc, bufs: &mut [&mut [u8]]) -> MioResult<Vec<(uint, SockAddr)>> {
+    let n = bufs.len();
+
+    let mut addrs: Vec<ffi::sockaddr_storage> = Vec::from_fn(n, |_| unsafe { mem::zeroed() });
+    let mut iovs: Vec<ffi::iovec> = bufs.iter_mut().map(|buf| {
+        ffi::iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len() as size_t
+        }
+    }).collect();
+
+    let mut hdrs: Vec<ffi::mmsghdr> = Vec::with_capacity(n);
+
+    for i in range(0, n) {
+        let mut hdr: ffi::mmsghdr = unsafe { mem::zeroed() };
+
+        unsafe {
+            hdr.msg_hdr.msg_name = addrs.as_mut_ptr().offset(i as int) as *mut c_void;
+            hdr.msg_hdr.msg_iov = iovs.as_mut_ptr().offset(i as int);
+        }
+
+        hdr.msg_hdr.msg_namelen = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+        hdr.msg_hdr.msg_iovlen = 1;
+
+        hdrs.push(hdr);
+    }
+
+    let res = unsafe {
+        ffi::recvmmsg(io.fd, hdrs.as_mut_ptr(), n as nix::c_uint, 0, ptr::null_mut())
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    let mut ret = Vec::with_capacity(res as uint);
+
+    for i in range(0, res as uint) {
+        ret.push((hdrs[i].msg_len as uint, try!(storage_to_sockaddr(&addrs[i]))));
+    }
+
+    Ok(ret)
+}
+
+// Sends each (data, destination) pair as a datagram with a single sendmmsg
+// call, returning the number of datagrams sent. A destination of None sends
+// to the connected address.
+pub fn send_multi(io: &IoDesc, msgs: &[(&[u8], Option<&SockAddr>)]) -> MioResult<uint> {
+    let n = msgs.len();
+
+    let mut addrs: Vec<ffi::sockaddr_storage> = Vec::with_capacity(n);
+    let mut addr_lens: Vec<ffi::socklen_t> = Vec::with_capacity(n);
+
+    for &(_, addr) in msgs.iter() {
+        let (storage, len) = match addr {
+            Some(addr) => sockaddr_to_storage(addr),
+            None => (unsafe { mem::zeroed() }, 0)
+        };
+
+        addrs.push(storage);
+        addr_lens.push(len);
+    }
+
+    let mut iovs: Vec<ffi::iovec> = msgs.iter().map(|&(data, _)| {
+        ffi::iovec {
+            iov_base: data.as_ptr() as *mut c_void,
+            iov_len: data.len() as size_t
+        }
+    }).collect();
+
+    let mut hdrs: Vec<ffi::mmsghdr> = Vec::with_capacity(n);
+
+    for i in range(0, n) {
+        let mut hdr: ffi::mmsghdr = unsafe { mem::zeroed() };
+
+        unsafe {
+            if addr_lens[i] > 0 {
+                hdr.msg_hdr.msg_name = addrs.as_mut_ptr().offset(i as int) as *mut c_void;
+                hdr.msg_hdr.msg_namelen = addr_lens[i];
+            }
+
+            hdr.msg_hdr.msg_iov = iovs.as_mut_ptr().offset(i as int);
+        }
+
+        hdr.msg_hdr.msg_iovlen = 1;
+
+        hdrs.push(hdr);
+    }
+
+    let res = unsafe {
+        ffi::sendmmsg(io.fd, hdrs.as_mut_ptr(), n as nix::c_uint, 0)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+/*
+ *
+ * ===== Zero copy =====
+ *
+ */
+
+// Makes the socket accept MSG_ZEROCOPY sends
+pub fn set_zerocopy(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_ZEROCOPY, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Sends without copying the data into the kernel, the pages are pinned until
+// the completion is read from the error queue
+pub fn send_zerocopy(io: &IoDesc, src: &[u8]) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::send(io.fd, src.as_ptr() as *const c_void, src.len() as size_t, ffi::MSG_ZEROCOPY)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Reads a zero copy completion from the error queue, returning the inclusive
+// range of completed sends and whether the kernel fell back to copying
+pub fn recv_zerocopy_completion(io: &IoDesc) -> MioResult<Option<(u32, u32, bool)>> {
+    let mut control = [0u8, ..128];
+    let mut empty: [u8, ..0] = [];
+
+    let msg = try!(recvmsg(io, empty.as_mut_slice(), control.as_mut_slice(), ffi::MSG_ERRQUEUE));
+
+    for (level, ty, data) in Cmsgs::new(control.slice_to(msg.controllen)) {
+        if !is_recverr(level, ty) || data.len() < mem::size_of::<ffi::sock_extended_err>() {
+            continue;
+        }
+
+        let err: ffi::sock_extended_err = unsafe {
+            ptr::read(data.as_ptr() as *const ffi::sock_extended_err)
+        };
+
+        if err.ee_origin == ffi::SO_EE_ORIGIN_ZEROCOPY {
+            let copied = err.ee_code & ffi::SO_EE_CODE_ZEROCOPY_COPIED != 0;
+            return Ok(Some((err.ee_info, err.ee_data, copied)));
+        }
+    }
+
+    // Some other error queue message
+    Ok(None)
+}
+
+/*
+ *
+ * ===== Error queue =====
+ *
+ */
+
+// Queues extended errors, such as ICMP errors, on the socket's error queue
+pub fn set_recv_err(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_RECVERR, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_recv_err_v6(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_RECVERR, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Reads the next error from the error queue, along with the payload of the
+// datagram that caused it
+pub fn recv_err(io: &IoDesc, dst: &mut [u8]) -> MioResult<(uint, Option<ExtendedError>)> {
+    let mut control = [0u8, ..256];
+
+    let msg = try!(recvmsg(io, dst, control.as_mut_slice(), ffi::MSG_ERRQUEUE));
+    let err_len = mem::size_of::<ffi::sock_extended_err>();
+
+    for (level, ty, data) in Cmsgs::new(control.slice_to(msg.controllen)) {
+        if !is_recverr(level, ty) || data.len() < err_len {
+            continue;
+        }
+
+        let err: ffi::sock_extended_err = unsafe {
+            ptr::read(data.as_ptr() as *const ffi::sock_extended_err)
+        };
+
+        // The address of the node that reported the error follows the
+        // error, AF_UNSPEC when unknown
+        let mut offender: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+        let offender_len = cmp::min(data.len() - err_len, mem::size_of::<ffi::sockaddr_storage>());
+
+        unsafe {
+            ptr::copy_nonoverlapping_memory(&mut offender as *mut ffi::sockaddr_storage as *mut u8,
+                                            data.as_ptr().offset(err_len as int),
+                                            offender_len);
+        }
+
+        let offender = match offender.ss_family as nix::c_int {
+            nix::AF_INET | nix::AF_INET6 => storage_to_sockaddr(&offender).ok(),
+            _ => None
+        };
+
+        let origin = match err.ee_origin {
+            ffi::SO_EE_ORIGIN_LOCAL => ErrorOrigin::Local,
+            ffi::SO_EE_ORIGIN_ICMP => ErrorOrigin::Icmp,
+            ffi::SO_EE_ORIGIN_ICMP6 => ErrorOrigin::Icmp6,
+            other => ErrorOrigin::Other(other)
+        };
+
+        return Ok((msg.cnt, Some(ExtendedError {
+            error: MioError::from_errno(err.ee_errno as nix::c_int),
+            origin: origin,
+            kind: err.ee_type,
+            code: err.ee_code,
+            info: err.ee_info,
+            addr: recvmsg_addr(&msg).ok(),
+            offender: offender
+        })));
+    }
+
+    // A message without an extended error, e.g. a zero copy completion
+    Ok((msg.cnt, None))
+}
+
+fn is_recverr(level: nix::c_int, ty: nix::c_int) -> bool {
+    (level == nix::IPPROTO_IP && ty == ffi::IP_RECVERR) ||
+        (level == ffi::IPPROTO_IPV6 && ty == ffi::IPV6_RECVERR)
+}
+
+mod ffi {
+    pub use libc::{c_int, c_long, c_uint, c_void, size_t, ssize_t, time_t, socklen_t};
+    pub use super::super::posix::ffi::{iovec, msghdr, sockaddr_storage, IPPROTO_IPV6};
+
+    pub const CLOCK_MONOTONIC: c_int = 1;
+    pub const TFD_NONBLOCK: c_int = 0o4000;
+    pub const TFD_CLOEXEC: c_int = 0o2000000;
+    pub const TFD_TIMER_ABSTIME: c_int = 1;
+
+    pub const SPLICE_F_MOVE: c_uint = 1;
+    pub const SPLICE_F_NONBLOCK: c_uint = 2;
+
+    pub const SOL_TCP: c_int = 6;
+    pub const TCP_ULP: c_int = 31;
+    pub const SOL_TLS: c_int = 282;
+    pub const TLS_TX: c_int = 1;
+    pub const TLS_RX: c_int = 2;
+    pub const TLS_1_2_VERSION: u16 = 0x0303;
+    pub const TLS_1_3_VERSION: u16 = 0x0304;
+    pub const TLS_CIPHER_AES_GCM_128: u16 = 51;
+    pub const TLS_CIPHER_AES_GCM_256: u16 = 52;
+
+    pub const SO_ATTACH_FILTER: c_int = 26;
+    pub const SO_DETACH_FILTER: c_int = 27;
+    pub const SO_INCOMING_CPU: c_int = 49;
+    pub const SO_ATTACH_REUSEPORT_CBPF: c_int = 51;
+
+    #[repr(C)]
+    pub struct sock_fprog {
+        pub len: u16,
+        pub filter: *const c_void,
+    }
+
+    pub const TCP_INFO: c_int = 11;
+    pub const TCP_LISTEN: u8 = 10;
+
+    // The leading part of struct tcp_info, up to the delivery rate added in
+    // Linux 4.9
+    #[repr(C)]
+    pub struct tcp_info {
+        pub tcpi_state: u8,
+        pub tcpi_ca_state: u8,
+        pub tcpi_retransmits: u8,
+        pub tcpi_probes: u8,
+        pub tcpi_backoff: u8,
+        pub tcpi_options: u8,
+        pub tcpi_wscale: u8,
+        pub tcpi_flags: u8,
+
+        pub tcpi_rto: u32,
+        pub tcpi_ato: u32,
+        pub tcpi_snd_mss: u32,
+        pub tcpi_rcv_mss: u32,
+
+        pub tcpi_unacked: u32,
+        pub tcpi_sacked: u32,
+        pub tcpi_lost: u32,
+        pub tcpi_retrans: u32,
+        pub tcpi_fackets: u32,
+
+        pub tcpi_last_data_sent: u32,
+        pub tcpi_last_ack_sent: u32,
+        pub tcpi_last_data_recv: u32,
+        pub tcpi_last_ack_recv: u32,
+
+        pub tcpi_pmtu: u32,
+        pub tcpi_rcv_ssthresh: u32,
+        pub tcpi_rtt: u32,
+        pub tcpi_rttvar: u32,
+        pub tcpi_snd_ssthresh: u32,
+        pub tcpi_snd_cwnd: u32,
+        pub tcpi_advmss: u32,
+        pub tcpi_reordering: u32,
+
+        pub tcpi_rcv_rtt: u32,
+        pub tcpi_rcv_space: u32,
+
+        pub tcpi_total_retrans: u32,
+
+        pub tcpi_pacing_rate: u64,
+        pub tcpi_max_pacing_rate: u64,
+        pub tcpi_bytes_acked: u64,
+        pub tcpi_bytes_received: u64,
+        pub tcpi_segs_out: u32,
+        pub tcpi_segs_in: u32,
+
+        pub tcpi_notsent_bytes: u32,
+        pub tcpi_min_rtt: u32,
+        pub tcpi_data_segs_in: u32,
+        pub tcpi_data_segs_out: u32,
+
+        pub tcpi_delivery_rate: u64,
+    }
+
+    #[repr(C)]
+    pub struct mmsghdr {
+        pub msg_hdr: msghdr,
+        pub msg_len: c_uint,
+    }
+
+    pub const SO_ZEROCOPY: c_int = 60;
+    pub const MSG_ZEROCOPY: c_int = 0x4000000;
+    pub const MSG_ERRQUEUE: c_int = 0x2000;
+
+    pub const IP_RECVERR: c_int = 11;
+    pub const IPV6_RECVERR: c_int = 25;
+
+    pub const SO_EE_ORIGIN_LOCAL: u8 = 1;
+    pub const SO_EE_ORIGIN_ICMP: u8 = 2;
+    pub const SO_EE_ORIGIN_ICMP6: u8 = 3;
+    pub const SO_EE_ORIGIN_ZEROCOPY: u8 = 5;
+    pub const SO_EE_CODE_ZEROCOPY_COPIED: u8 = 1;
+
+    #[repr(C)]
+    pub struct sock_extended_err {
+        pub ee_errno: u32,
+        pub ee_origin: u8,
+        pub ee_type: u8,
+        pub ee_code: u8,
+        pub ee_pad: u8,
+        pub ee_info: u32,
+        pub ee_data: u32,
+    }
+
+    #[repr(C)]
+    pub struct tls12_crypto_info_aes_gcm_128 {
+        pub version: u16,
+        pub cipher_type: u16,
+        pub iv: [u8, ..8],
+        pub key: [u8, ..16],
+        pub salt: [u8, ..4],
+        pub rec_seq: [u8, ..8],
+    }
+
+    #[repr(C)]
+    pub struct tls12_crypto_info_aes_gcm_256 {
+        pub version: u16,
+        pub cipher_type: u16,
+        pub iv: [u8, ..8],
+        pub key: [u8, ..32],
+        pub salt: [u8, ..4],
+        pub rec_seq: [u8, ..8],
+    }
+
+    #[repr(C)]
+    pub struct timespec {
+        pub tv_sec: time_t,
+        pub tv_nsec: c_long,
+    }
+
+    #[repr(C)]
+    pub struct itimerspec {
+        pub it_interval: timespec,
+        pub it_value: timespec,
+    }
+
+    extern {
+        pub fn timerfd_create(clockid: c_int, flags: c_int) -> c_int;
+        pub fn timerfd_settime(fd: c_int, flags: c_int, new_value: *const itimerspec, old_value: *mut itimerspec) -> c_int;
+        pub fn splice(fd_in: c_int, off_in: *mut i64, fd_out: c_int, off_out: *mut i64,
+                      len: size_t, flags: c_uint) -> ssize_t;
+        pub fn tee(fd_in: c_int, fd_out: c_int, len: size_t, flags: c_uint) -> ssize_t;
+        pub fn setsockopt(fd: c_int, level: c_int, name: c_int,
+                          val: *const c_void, len: socklen_t) -> c_int;
+        pub fn getsockopt(fd: c_int, level: c_int, name: c_int,
+                          val: *mut c_void, len: *mut socklen_t) -> c_int;
+        pub fn send(fd: c_int, buf: *const c_void, len: size_t, flags: c_int) -> ssize_t;
+        pub fn recvmmsg(fd: c_int, msgvec: *mut mmsghdr, vlen: c_uint, flags: c_int,
+                        timeout: *mut c_void) -> c_int;
+        pub fn sendmmsg(fd: c_int, msgvec: *mut mmsghdr, vlen: c_uint, flags: c_int) -> c_int;
+    }
+}
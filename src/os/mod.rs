@@ -8,10 +8,21 @@ pub use self::kqueue::{Events, Selector};
 pub use self::posix::*;
 
 #[cfg(target_os = "linux")]
-pub use self::linux::Awakener;
+pub use self::linux::{Awakener, TimerFd, splice, tee, set_tcp_ulp_tls, set_tls_crypto_info,
+                      incoming_cpu, set_incoming_cpu, attach_reuseport_cbpf, attach_filter,
+                      detach_filter, tcp_info, recv_multi, send_multi, set_zerocopy, send_zerocopy,
+                      recv_zerocopy_completion, set_recv_err, set_recv_err_v6, recv_err};
+
+// The signal wake-up path must only use async-signal-safe operations, which
+// rules out kevent.
+#[cfg(target_os = "linux")]
+pub use self::linux::Awakener as SignalAwakener;
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub use self::posix::PipeAwakener as SignalAwakener;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
-pub use self::posix::PipeAwakener as Awakener;
+pub use self::kqueue::{Awakener, TimerFd};
 
 #[cfg(windows)]
 pub use self::windows::*;
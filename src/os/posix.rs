@@ -1,16 +1,27 @@
+use std::cmp;
 use std::mem;
+use std::ptr;
 use std::num::Int;
+use std::time::duration::Duration;
+use libc::{c_void, size_t};
+use nix::errno::SysError;
 use error::{MioResult, MioError};
-use net::{AddressFamily, SockAddr, IPv4Addr, SocketType};
-use net::SocketType::{Dgram, Stream};
+use net::{AddressFamily, SockAddr, IPv4Addr, IPv6Addr, SocketType, Shutdown};
+use net::tcp::TcpInfo;
+use net::pipe::{PeerCred, RecvFds};
+use net::udp::{Ecn, RecvMeta};
+use net::SocketType::{Dgram, SeqPacket, Stream};
 use net::SockAddr::{InetAddr, UnixAddr};
 use net::AddressFamily::{Inet, Inet6, Unix};
+#[cfg(target_os = "linux")]
+use super::linux::listen_backlog;
 pub use std::io::net::ip::IpAddr;
 
 mod nix {
     pub use nix::c_int;
+    pub use libc::c_uint;
     pub use nix::fcntl::{Fd, O_NONBLOCK, O_CLOEXEC};
-    pub use nix::errno::EINPROGRESS;
+    pub use nix::errno::{EAFNOSUPPORT, EINPROGRESS, EINVAL, ENOPROTOOPT, EOPNOTSUPP, EPROTONOSUPPORT};
     pub use nix::sys::socket::*;
     pub use nix::unistd::*;
 }
@@ -45,6 +56,11 @@ impl PipeAwakener {
         &self.reader
     }
 
+    /// The descriptor to write to in order to trigger a wake-up
+    pub fn wakeup_fd(&self) -> nix::Fd {
+        self.writer.fd
+    }
+
     pub fn cleanup(&self) {
         let mut buf: [u8, ..128] = unsafe { mem::uninitialized() };
 
@@ -58,6 +74,22 @@ impl PipeAwakener {
     }
 }
 
+/// Triggers a wake-up by writing directly to the awakener's descriptor. Only
+/// async-signal-safe operations are performed, so this may be called from a
+/// signal handler. Errors are ignored, a full pipe or saturated eventfd means
+/// a wake-up is already pending.
+pub fn signal_wakeup(fd: nix::Fd) {
+    // A failed write must not clobber the errno of the code the signal
+    // interrupted
+    let errno = unsafe { *ffi::errno_location() };
+
+    // A native endian 1u64 works for both eventfd and pipes
+    let mark: [u8, ..8] = unsafe { mem::transmute(1u64) };
+    let _ = nix::write(fd, mark.as_slice());
+
+    unsafe { *ffi::errno_location() = errno; }
+}
+
 /// Represents the OS's handle to the IO instance. In this case, it is the file
 /// descriptor.
 #[deriving(Show)]
@@ -71,6 +103,18 @@ impl Drop for IoDesc {
     }
 }
 
+// Duplicates the descriptor, the duplicate shares the file status flags,
+// including O_NONBLOCK, and is atomically marked close-on-exec
+pub fn dup(io: &IoDesc) -> MioResult<IoDesc> {
+    let fd = unsafe { ffi::fcntl(io.fd, ffi::F_DUPFD_CLOEXEC, 0 as nix::c_int) };
+
+    if fd < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(IoDesc { fd: fd })
+}
+
 /*
  *
  * ===== Pipes =====
@@ -84,6 +128,36 @@ pub fn pipe() -> MioResult<(IoDesc, IoDesc)> {
     Ok((IoDesc { fd: rd }, IoDesc { fd: wr }))
 }
 
+// Number of bytes that can be read without blocking
+pub fn bytes_available(io: &IoDesc) -> MioResult<uint> {
+    let mut n: nix::c_int = 0;
+
+    if unsafe { ffi::ioctl(io.fd, ffi::FIONREAD, &mut n) } < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(n as uint)
+}
+
+// The bytes that can be read from a stream, or the connections waiting to be
+// accepted on a TCP listener, for readiness hints. None if neither can be
+// determined.
+pub fn read_available(fd: nix::Fd) -> Option<uint> {
+    let mut n: nix::c_int = 0;
+
+    if unsafe { ffi::ioctl(fd, ffi::FIONREAD, &mut n) } == 0 {
+        return Some(n as uint);
+    }
+
+    // FIONREAD fails with EINVAL on listening sockets
+    listen_backlog(fd)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listen_backlog(_: nix::Fd) -> Option<uint> {
+    None
+}
+
 /*
  *
  * ===== Sockets =====
@@ -98,8 +172,9 @@ pub fn socket(af: AddressFamily, sock_type: SocketType) -> MioResult<IoDesc> {
     };
 
     let socket_type = match sock_type {
-        Dgram  => nix::SOCK_DGRAM,
-        Stream => nix::SOCK_STREAM
+        Dgram     => nix::SOCK_DGRAM,
+        Stream    => nix::SOCK_STREAM,
+        SeqPacket => ffi::SOCK_SEQPACKET
     };
 
     Ok(IoDesc {
@@ -108,6 +183,115 @@ pub fn socket(af: AddressFamily, sock_type: SocketType) -> MioResult<IoDesc> {
     })
 }
 
+// Creates a pair of connected Unix domain sockets
+pub fn socketpair(sock_type: SocketType) -> MioResult<(IoDesc, IoDesc)> {
+    let socket_type = match sock_type {
+        Dgram     => nix::SOCK_DGRAM,
+        Stream    => nix::SOCK_STREAM,
+        SeqPacket => ffi::SOCK_SEQPACKET
+    };
+
+    let mut fds = [0 as nix::c_int, ..2];
+
+    if unsafe { ffi::socketpair(nix::AF_UNIX, socket_type | socket_flags(), 0, fds.as_mut_ptr()) } < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    let pair = (IoDesc { fd: fds[0] }, IoDesc { fd: fds[1] });
+
+    // The descriptors are closed on drop if setting the flags fails
+    try!(set_pair_flags(&pair));
+
+    Ok(pair)
+}
+
+#[cfg(target_os = "linux")]
+fn socket_flags() -> nix::c_int {
+    ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC
+}
+
+#[cfg(not(target_os = "linux"))]
+fn socket_flags() -> nix::c_int {
+    0
+}
+
+#[cfg(target_os = "linux")]
+fn set_pair_flags(_: &(IoDesc, IoDesc)) -> MioResult<()> {
+    // Already set by socket_flags
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_pair_flags(pair: &(IoDesc, IoDesc)) -> MioResult<()> {
+    let (ref a, ref b) = *pair;
+    try!(set_nonblock_cloexec(a));
+    set_nonblock_cloexec(b)
+}
+
+// Creates a Multipath TCP socket, or a plain TCP socket on kernels without
+// MPTCP support
+#[cfg(target_os = "linux")]
+pub fn mptcp_socket(af: AddressFamily) -> MioResult<IoDesc> {
+    let family = match af {
+        Inet  => nix::AF_INET,
+        Inet6 => nix::AF_INET6,
+        Unix  => return socket(af, Stream)
+    };
+
+    let fd = unsafe {
+        ffi::socket(family, ffi::SOCK_STREAM | ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC,
+                    ffi::IPPROTO_MPTCP)
+    };
+
+    if fd < 0 {
+        let err = SysError::last();
+
+        return match err.kind {
+            nix::EINVAL | nix::EPROTONOSUPPORT | nix::ENOPROTOOPT => {
+                debug!("MPTCP not supported, falling back to TCP; err={}", err);
+                socket(af, Stream)
+            }
+            _ => Err(MioError::from_sys_error(err))
+        };
+    }
+
+    Ok(IoDesc { fd: fd })
+}
+
+// Whether the connection negotiated MPTCP with the peer, rather than falling
+// back to plain TCP
+#[cfg(target_os = "linux")]
+pub fn mptcp_negotiated(io: &IoDesc) -> MioResult<bool> {
+    // The leading part of struct mptcp_info, up to mptcpi_flags
+    let mut info = [0u8, ..12];
+    let mut len = info.len() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(io.fd, ffi::SOL_MPTCP, ffi::MPTCP_INFO,
+                        info.as_mut_ptr() as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        let err = SysError::last();
+
+        return match err.kind {
+            // Not an MPTCP socket
+            nix::ENOPROTOOPT | nix::EOPNOTSUPP => Ok(false),
+            _ => Err(MioError::from_sys_error(err))
+        };
+    }
+
+    // Nothing is returned for connections that fell back to TCP
+    if (len as uint) < info.len() {
+        return Ok(false);
+    }
+
+    let flags: u32 = unsafe { ptr::read(info.as_ptr().offset(8) as *const u32) };
+
+    Ok(flags & ffi::MPTCP_INFO_FLAG_FALLBACK == 0 &&
+       flags & ffi::MPTCP_INFO_FLAG_REMOTE_KEY_RECEIVED != 0)
+}
+
 pub fn connect(io: &IoDesc, addr: &SockAddr) -> MioResult<bool> {
     match nix::connect(io.fd, &from_sockaddr(addr)) {
         Ok(_) => Ok(true),
@@ -130,19 +314,292 @@ pub fn listen(io: &IoDesc, backlog: uint) -> MioResult<()> {
         .map_err(MioError::from_sys_error)
 }
 
-pub fn accept(io: &IoDesc) -> MioResult<IoDesc> {
-    Ok(IoDesc {
-        fd: try!(nix::accept4(io.fd, nix::SOCK_NONBLOCK | nix::SOCK_CLOEXEC)
-                     .map_err(MioError::from_sys_error))
-    })
+// Accepts a connection along with the peer's address, which the kernel fills
+// in as part of the call, sparing a getpeername
+pub fn accept(io: &IoDesc) -> MioResult<(IoDesc, SockAddr)> {
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+
+    let desc = try!(accept_raw(io, &mut storage, &mut len));
+
+    // Peers that are not bound to an address, such as unnamed Unix sockets,
+    // come with an empty or unspecified address depending on the platform.
+    // The connection is accepted already, so it is never dropped over its
+    // address.
+    let addr = if len == 0 {
+        UnixAddr(Path::new(""))
+    } else {
+        match storage_to_sockaddr(&storage) {
+            Ok(addr) => addr,
+            Err(e) => {
+                debug!("unknown peer address; family={}; err={}", storage.ss_family, e);
+                UnixAddr(Path::new(""))
+            }
+        }
+    };
+
+    Ok((desc, addr))
+}
+
+// accept4 sets the flags atomically, saving two fcntl calls per connection
+// and leaving no window for a concurrent fork-exec to leak the descriptor
+#[cfg(target_os = "linux")]
+fn accept_raw(io: &IoDesc, storage: &mut ffi::sockaddr_storage, len: &mut ffi::socklen_t) -> MioResult<IoDesc> {
+    let fd = unsafe {
+        ffi::accept4(io.fd, storage as *mut ffi::sockaddr_storage as *mut ffi::sockaddr,
+                     len, ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC)
+    };
+
+    if fd < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(IoDesc { fd: fd })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn accept_raw(io: &IoDesc, storage: &mut ffi::sockaddr_storage, len: &mut ffi::socklen_t) -> MioResult<IoDesc> {
+    let fd = unsafe {
+        ffi::accept(io.fd, storage as *mut ffi::sockaddr_storage as *mut ffi::sockaddr, len)
+    };
+
+    if fd < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    let desc = IoDesc { fd: fd };
+
+    // The descriptor is closed on drop if setting the flags fails
+    try!(set_nonblock_cloexec(&desc));
+
+    Ok(desc)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_nonblock_cloexec(io: &IoDesc) -> MioResult<()> {
+    unsafe {
+        let flags = ffi::fcntl(io.fd, ffi::F_GETFL);
+
+        if flags < 0 || ffi::fcntl(io.fd, ffi::F_SETFL, flags | ffi::O_NONBLOCK) < 0 {
+            return Err(MioError::from_sys_error(SysError::last()));
+        }
+
+        if ffi::fcntl(io.fd, ffi::F_SETFD, ffi::FD_CLOEXEC) < 0 {
+            return Err(MioError::from_sys_error(SysError::last()));
+        }
+    }
+
+    Ok(())
+}
+
+// Sends up to len bytes of the file starting at offset, without copying them
+// through user space. Returns the number of bytes sent, 0 at the end of the
+// file.
+#[cfg(target_os = "linux")]
+pub fn sendfile(io: &IoDesc, file: nix::Fd, offset: u64, len: uint) -> MioResult<uint> {
+    let mut off = offset as ffi::off_t;
+
+    let res = unsafe { ffi::sendfile(io.fd, file, &mut off, len as size_t) };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn sendfile(io: &IoDesc, file: nix::Fd, offset: u64, len: uint) -> MioResult<uint> {
+    // In: the number of bytes to send, out: the number of bytes sent
+    let mut sent = len as ffi::off_t;
+
+    let res = unsafe {
+        ffi::sendfile(file, io.fd, offset as ffi::off_t, &mut sent, ptr::null_mut(), 0)
+    };
+
+    if res < 0 {
+        let err = MioError::from_sys_error(SysError::last());
+
+        // A partial send still reports EAGAIN
+        if err.is_would_block() && sent > 0 {
+            return Ok(sent as uint);
+        }
+
+        return Err(err);
+    }
+
+    Ok(sent as uint)
+}
+
+pub fn local_addr(io: &IoDesc) -> MioResult<SockAddr> {
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockname(io.fd, &mut storage as *mut ffi::sockaddr_storage as *mut ffi::sockaddr, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    storage_to_sockaddr(&storage)
+}
+
+pub fn peer_addr(io: &IoDesc) -> MioResult<SockAddr> {
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getpeername(io.fd, &mut storage as *mut ffi::sockaddr_storage as *mut ffi::sockaddr, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    storage_to_sockaddr(&storage)
+}
+
+// The destination of a connection before it was redirected by netfilter NAT
+#[cfg(target_os = "linux")]
+pub fn original_dst(io: &IoDesc) -> MioResult<SockAddr> {
+    let level = match try!(local_addr(io)).family() {
+        Inet6 => ffi::IPPROTO_IPV6,
+        _ => nix::IPPROTO_IP
+    };
+
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+
+    // IP6T_SO_ORIGINAL_DST shares the value of SO_ORIGINAL_DST
+    let res = unsafe {
+        ffi::getsockopt(io.fd, level, ffi::SO_ORIGINAL_DST,
+                        &mut storage as *mut ffi::sockaddr_storage as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    storage_to_sockaddr(&storage)
+}
+
+pub fn shutdown(io: &IoDesc, how: Shutdown) -> MioResult<()> {
+    let how = match how {
+        Shutdown::Read  => ffi::SHUT_RD,
+        Shutdown::Write => ffi::SHUT_WR,
+        Shutdown::Both  => ffi::SHUT_RDWR
+    };
+
+    if unsafe { ffi::shutdown(io.fd, how) } < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+// Like read, but leaves the data in the socket's receive buffer
+pub fn peek(io: &IoDesc, dst: &mut [u8]) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::recv(io.fd, dst.as_mut_ptr() as *mut c_void, dst.len() as size_t, ffi::MSG_PEEK)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    if res == 0 {
+        return Err(MioError::eof());
+    }
+
+    Ok(res as uint)
+}
+
+// Sends on a connected socket. Unlike write, zero length datagrams are valid.
+pub fn send(io: &IoDesc, src: &[u8]) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::send(io.fd, src.as_ptr() as *const c_void, src.len() as size_t, 0)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Receives on a connected socket. Unlike read, 0 is not treated as EOF, as it
+// is the size of an empty datagram.
+pub fn recv(io: &IoDesc, dst: &mut [u8]) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::recv(io.fd, dst.as_mut_ptr() as *mut c_void, dst.len() as size_t, 0)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Sends a single byte of TCP urgent data
+pub fn send_oob(io: &IoDesc, byte: u8) -> MioResult<()> {
+    let buf = [byte];
+
+    let res = unsafe {
+        ffi::send(io.fd, buf.as_ptr() as *const c_void, 1, ffi::MSG_OOB)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+// Reads the pending urgent byte, EINVAL when there is none
+pub fn recv_oob(io: &IoDesc) -> MioResult<u8> {
+    let mut buf = [0u8];
+
+    let res = unsafe {
+        ffi::recv(io.fd, buf.as_mut_ptr() as *mut c_void, 1, ffi::MSG_OOB)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    if res == 0 {
+        return Err(MioError::eof());
+    }
+
+    Ok(buf[0])
+}
+
+// Like recvfrom, but leaves the datagram queued
+pub fn peek_from(io: &IoDesc, dst: &mut [u8]) -> MioResult<(uint, SockAddr)> {
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::recvfrom(io.fd, dst.as_mut_ptr() as *mut c_void, dst.len() as size_t, ffi::MSG_PEEK,
+                      &mut storage as *mut ffi::sockaddr_storage as *mut ffi::sockaddr, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok((res as uint, try!(storage_to_sockaddr(&storage))))
 }
 
 #[inline]
 pub fn recvfrom(io: &IoDesc, buf: &mut [u8]) -> MioResult<(uint, SockAddr)> {
-    match nix::recvfrom(io.fd, buf).map_err(MioError::from_sys_error) {
-        Ok((cnt, addr)) => Ok((cnt, to_sockaddr(&addr))),
-        Err(e) => Err(e)
-    }
+    let mut empty: [u8, ..0] = [];
+    let msg = try!(recvmsg(io, buf, empty.as_mut_slice(), 0));
+
+    Ok((msg.cnt, try!(recvmsg_addr(&msg))))
 }
 
 #[inline]
@@ -187,110 +644,557 @@ pub fn set_reuseport(io: &IoDesc, val: bool) -> MioResult<()> {
         .map_err(MioError::from_sys_error)
 }
 
-pub fn set_tcp_nodelay(io: &IoDesc, val: bool) -> MioResult<()> {
+// Whether an IPv6 socket only accepts IPv6 traffic, or also IPv4 traffic
+// through v4-mapped addresses
+pub fn set_ipv6_v6only(io: &IoDesc, val: bool) -> MioResult<()> {
     let v: nix::c_int = if val { 1 } else { 0 };
 
-    nix::setsockopt(io.fd, nix::IPPROTO_TCP, nix::TCP_NODELAY, &v)
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_V6ONLY, &v)
         .map_err(MioError::from_sys_error)
 }
 
-pub fn join_multicast_group(io: &IoDesc, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
-    let grp_req = try!(make_ip_mreq(addr, interface));
+pub fn ipv6_v6only(io: &IoDesc) -> MioResult<bool> {
+    let mut v: nix::c_int = 0;
 
-    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_ADD_MEMBERSHIP, &grp_req)
-        .map_err(MioError::from_sys_error)
+    try!(nix::getsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_V6ONLY, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v != 0)
 }
 
-pub fn leave_multicast_group(io: &IoDesc, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
-    let grp_req = try!(make_ip_mreq(addr, interface));
+pub fn recv_buffer_size(io: &IoDesc) -> MioResult<uint> {
+    let mut v: nix::c_int = 0;
 
-    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_ADD_MEMBERSHIP, &grp_req)
-        .map_err(MioError::from_sys_error)
+    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, nix::SO_RCVBUF, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as uint)
 }
 
-pub fn set_multicast_ttl(io: &IoDesc, val: u8) -> MioResult<()> {
-    let v: nix::IpMulticastTtl = val;
+pub fn set_recv_buffer_size(io: &IoDesc, size: uint) -> MioResult<()> {
+    let v = size as nix::c_int;
 
-    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_MULTICAST_TTL, &v)
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, nix::SO_RCVBUF, &v)
         .map_err(MioError::from_sys_error)
 }
 
-pub fn linger(io: &IoDesc) -> MioResult<uint> {
-    let mut linger: nix::linger = unsafe { mem::uninitialized() };
+pub fn send_buffer_size(io: &IoDesc) -> MioResult<uint> {
+    let mut v: nix::c_int = 0;
 
-    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, nix::SO_LINGER, &mut linger)
+    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, nix::SO_SNDBUF, &mut v)
             .map_err(MioError::from_sys_error));
 
-    if linger.l_onoff > 0 {
-        Ok(linger.l_linger as uint)
-    } else {
-        Ok(0)
-    }
+    Ok(v as uint)
 }
 
-pub fn set_linger(io: &IoDesc, dur_s: uint) -> MioResult<()> {
-    let linger = nix::linger {
-        l_onoff: (if dur_s > 0 { 1i } else { 0i }) as nix::c_int,
-        l_linger: dur_s as nix::c_int
-    };
+pub fn set_send_buffer_size(io: &IoDesc, size: uint) -> MioResult<()> {
+    let v = size as nix::c_int;
 
-    nix::setsockopt(io.fd, nix::SOL_SOCKET, nix::SO_LINGER, &linger)
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, nix::SO_SNDBUF, &v)
         .map_err(MioError::from_sys_error)
 }
 
-fn make_ip_mreq(group_addr: &IpAddr, iface_addr: &Option<IpAddr>) -> MioResult<nix::ip_mreq> {
-    Ok(nix::ip_mreq {
-        imr_multiaddr: from_ip_addr_to_inaddr(&Some(*group_addr)),
-        imr_interface: from_ip_addr_to_inaddr(iface_addr)
-    })
+pub fn ip_ttl(io: &IoDesc) -> MioResult<u8> {
+    let mut v: nix::c_int = 0;
+
+    try!(nix::getsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_TTL, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as u8)
 }
 
-fn from_ip_addr_to_inaddr(addr: &Option<IpAddr>) -> nix::in_addr {
-    match *addr {
-        Some(ip) => {
-            match ip {
-                IPv4Addr(a, b, c, d) => ipv4_to_inaddr(a, b, c, d),
-                _ => unimplemented!()
-            }
-        }
-        None => nix::in_addr { s_addr: nix::INADDR_ANY }
-    }
+pub fn set_ip_ttl(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v = val as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_TTL, &v)
+        .map_err(MioError::from_sys_error)
 }
 
-fn to_sockaddr(addr: &nix::SockAddr) -> SockAddr {
-    match *addr {
-        nix::SockAddr::SockIpV4(sin) => {
-            InetAddr(u32be_to_ipv4(sin.sin_addr.s_addr), Int::from_be(sin.sin_port))
-        }
-        nix::SockAddr::SockUnix(addr) => {
-            let mut str_path = String::new();
-            for c in addr.sun_path.iter() {
-                if *c == 0 { break; }
-                str_path.push(*c as u8 as char);
-            }
+pub fn ipv6_unicast_hops(io: &IoDesc) -> MioResult<u8> {
+    let mut v: nix::c_int = 0;
 
-            UnixAddr(Path::new(str_path))
-        }
-        _ => unimplemented!()
-    }
+    try!(nix::getsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_UNICAST_HOPS, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as u8)
 }
 
-fn from_sockaddr(addr: &SockAddr) -> nix::SockAddr {
-    use std::mem;
+pub fn set_ipv6_unicast_hops(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v = val as nix::c_int;
 
-    match *addr {
-        InetAddr(ip, port) => {
-            match ip {
-                IPv4Addr(a, b, c, d) => {
-                    let mut addr: nix::sockaddr_in = unsafe { mem::zeroed() };
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_UNICAST_HOPS, &v)
+        .map_err(MioError::from_sys_error)
+}
 
-                    addr.sin_family = nix::AF_INET as nix::sa_family_t;
-                    addr.sin_port = port.to_be();
-                    addr.sin_addr = ipv4_to_inaddr(a, b, c, d);
+pub fn ip_tos(io: &IoDesc) -> MioResult<u8> {
+    let mut v: nix::c_int = 0;
 
-                    nix::SockAddr::SockIpV4(addr)
-                }
-                _ => unimplemented!()
+    try!(nix::getsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_TOS, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as u8)
+}
+
+pub fn set_ip_tos(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v = val as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_TOS, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn ipv6_tclass(io: &IoDesc) -> MioResult<u8> {
+    let mut v: nix::c_int = 0;
+
+    try!(nix::getsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_TCLASS, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v as u8)
+}
+
+pub fn set_ipv6_tclass(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v = val as nix::c_int;
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_TCLASS, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Allows binding to and accepting connections for non-local addresses, as
+// used with TPROXY
+#[cfg(target_os = "linux")]
+pub fn set_ip_transparent(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_TRANSPARENT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Allows binding to addresses not configured on any interface yet
+#[cfg(target_os = "linux")]
+pub fn set_ip_freebind(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_FREEBIND, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn tcp_nodelay(io: &IoDesc) -> MioResult<bool> {
+    let mut v: nix::c_int = 0;
+
+    try!(nix::getsockopt(io.fd, nix::IPPROTO_TCP, nix::TCP_NODELAY, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v != 0)
+}
+
+pub fn set_tcp_nodelay(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, nix::TCP_NODELAY, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Retrieves and clears the pending error of the socket, e.g. the reason an
+// asynchronous connect failed
+pub fn take_socket_error(io: &IoDesc) -> MioResult<Option<MioError>> {
+    take_socket_error_fd(io.fd)
+}
+
+pub fn take_socket_error_fd(fd: nix::Fd) -> MioResult<Option<MioError>> {
+    let mut err: nix::c_int = 0;
+
+    try!(nix::getsockopt(fd, nix::SOL_SOCKET, nix::SO_ERROR, &mut err)
+            .map_err(MioError::from_sys_error));
+
+    if err == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(MioError::from_errno(err)))
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn tcp_info(io: &IoDesc) -> MioResult<TcpInfo> {
+    let mut info: ffi::tcp_connection_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::tcp_connection_info>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_CONNECTION_INFO,
+                        &mut info as *mut ffi::tcp_connection_info as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(TcpInfo {
+        rtt: Duration::milliseconds(info.tcpi_srtt as i64),
+        rtt_var: Duration::milliseconds(info.tcpi_rttvar as i64),
+        retransmits: info.tcpi_txretransmitpackets as u32,
+        cwnd_bytes: info.tcpi_snd_cwnd as u64,
+        delivery_rate: None
+    })
+}
+
+// The credentials of the process that connected a Unix domain socket, as of
+// the time it called connect, or of the listener for the connecting side
+#[cfg(target_os = "linux")]
+pub fn peer_cred(io: &IoDesc) -> MioResult<PeerCred> {
+    let mut cred: ffi::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<ffi::ucred>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_PEERCRED,
+                        &mut cred as *mut ffi::ucred as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(PeerCred { pid: cred.pid, uid: cred.uid, gid: cred.gid })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn peer_cred(io: &IoDesc) -> MioResult<PeerCred> {
+    let mut uid = 0u32;
+    let mut gid = 0u32;
+
+    if unsafe { ffi::getpeereid(io.fd, &mut uid, &mut gid) } < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    let mut pid: nix::c_int = 0;
+    let mut len = mem::size_of::<nix::c_int>() as ffi::socklen_t;
+
+    let res = unsafe {
+        ffi::getsockopt(io.fd, ffi::SOL_LOCAL, ffi::LOCAL_PEERPID,
+                        &mut pid as *mut nix::c_int as *mut c_void, &mut len)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(PeerCred { pid: pid, uid: uid, gid: gid })
+}
+
+// Time in milliseconds that sent data may remain unacknowledged before the
+// connection is dropped, zero restores the system default
+#[cfg(target_os = "linux")]
+pub fn set_tcp_user_timeout(io: &IoDesc, ms: uint) -> MioResult<()> {
+    let v = ms as nix::c_uint;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_USER_TIMEOUT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn broadcast(io: &IoDesc) -> MioResult<bool> {
+    let mut v: nix::c_int = 0;
+
+    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_BROADCAST, &mut v)
+            .map_err(MioError::from_sys_error));
+
+    Ok(v != 0)
+}
+
+pub fn set_broadcast(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_BROADCAST, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_keepalive(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, nix::SO_KEEPALIVE, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Idle time before the first keepalive probe is sent
+pub fn set_keepalive_idle(io: &IoDesc, secs: uint) -> MioResult<()> {
+    let v = secs as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_KEEPIDLE, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Time between unacknowledged keepalive probes
+pub fn set_keepalive_interval(io: &IoDesc, secs: uint) -> MioResult<()> {
+    let v = secs as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_KEEPINTVL, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Number of unacknowledged probes before the connection is dropped
+pub fn set_keepalive_count(io: &IoDesc, count: uint) -> MioResult<()> {
+    let v = count as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_KEEPCNT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Enables TCP Fast Open on a listener, with the given queue length of
+// pending connections that have not completed the handshake yet
+pub fn set_tcp_fastopen(io: &IoDesc, qlen: uint) -> MioResult<()> {
+    let v = qlen as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_FASTOPEN, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Makes connect return immediately and defers the SYN until the first write,
+// which carries the data if a Fast Open cookie is cached for the peer
+#[cfg(target_os = "linux")]
+pub fn set_tcp_fastopen_connect(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_FASTOPEN_CONNECT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Only wakes up the listener once data arrived on a connection, or the
+// timeout in seconds expired
+#[cfg(target_os = "linux")]
+pub fn set_tcp_defer_accept(io: &IoDesc, secs: uint) -> MioResult<()> {
+    let v = secs as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_TCP, ffi::TCP_DEFER_ACCEPT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+#[cfg(target_os = "linux")]
+pub fn bind_to_device(io: &IoDesc, name: &str) -> MioResult<()> {
+    let res = unsafe {
+        ffi::setsockopt(io.fd, nix::SOL_SOCKET, ffi::SO_BINDTODEVICE,
+                        name.as_ptr() as *const c_void, name.len() as ffi::socklen_t)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+// OS X binds to the interface index rather than its name
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn bind_to_device(io: &IoDesc, name: &str) -> MioResult<()> {
+    let idx = unsafe { ffi::if_nametoindex(name.to_c_str().as_ptr()) };
+
+    if idx == 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    let v = idx as nix::c_int;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_BOUND_IF, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn join_multicast_group(io: &IoDesc, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
+    let grp_req = try!(make_ip_mreq(addr, interface));
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_ADD_MEMBERSHIP, &grp_req)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn leave_multicast_group(io: &IoDesc, addr: &IpAddr, interface: &Option<IpAddr>) -> MioResult<()> {
+    let grp_req = try!(make_ip_mreq(addr, interface));
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_DROP_MEMBERSHIP, &grp_req)
+        .map_err(MioError::from_sys_error)
+}
+
+// Joins an IPv6 multicast group on the interface with the given index, 0
+// lets the kernel pick the interface
+pub fn join_multicast_group_v6(io: &IoDesc, addr: &IpAddr, interface: uint) -> MioResult<()> {
+    let grp_req = try!(make_ipv6_mreq(addr, interface));
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_JOIN_GROUP, &grp_req)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn leave_multicast_group_v6(io: &IoDesc, addr: &IpAddr, interface: uint) -> MioResult<()> {
+    let grp_req = try!(make_ipv6_mreq(addr, interface));
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_LEAVE_GROUP, &grp_req)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_multicast_ttl(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v: nix::IpMulticastTtl = val;
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, nix::IP_MULTICAST_TTL, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Whether multicast datagrams sent by the socket are looped back to local
+// sockets that joined the group
+pub fn set_multicast_loop(io: &IoDesc, val: bool) -> MioResult<()> {
+    // OS X expects a u_char, which Linux accepts as well
+    let v: u8 = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_MULTICAST_LOOP, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// The interface, by address, to send multicast datagrams through
+pub fn set_multicast_if(io: &IoDesc, interface: &IpAddr) -> MioResult<()> {
+    match *interface {
+        IPv4Addr(..) => {}
+        _ => return Err(MioError::from_errno(nix::EINVAL as nix::c_int))
+    }
+
+    let v = from_ip_addr_to_inaddr(&Some(*interface));
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_MULTICAST_IF, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_multicast_hops_v6(io: &IoDesc, val: u8) -> MioResult<()> {
+    let v = val as nix::c_int;
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_MULTICAST_HOPS, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_multicast_loop_v6(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_uint = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_MULTICAST_LOOP, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// The interface, by index, to send IPv6 multicast datagrams through
+pub fn set_multicast_if_v6(io: &IoDesc, interface: uint) -> MioResult<()> {
+    let v = interface as nix::c_uint;
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_MULTICAST_IF, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// None when lingering is disabled, otherwise the linger time in seconds
+pub fn linger(io: &IoDesc) -> MioResult<Option<uint>> {
+    let mut linger: nix::linger = unsafe { mem::uninitialized() };
+
+    try!(nix::getsockopt(io.fd, nix::SOL_SOCKET, nix::SO_LINGER, &mut linger)
+            .map_err(MioError::from_sys_error));
+
+    if linger.l_onoff > 0 {
+        Ok(Some(linger.l_linger as uint))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn set_linger(io: &IoDesc, dur_s: Option<uint>) -> MioResult<()> {
+    let linger = match dur_s {
+        Some(dur_s) => nix::linger {
+            l_onoff: 1,
+            l_linger: dur_s as nix::c_int
+        },
+        None => nix::linger {
+            l_onoff: 0,
+            l_linger: 0
+        }
+    };
+
+    nix::setsockopt(io.fd, nix::SOL_SOCKET, nix::SO_LINGER, &linger)
+        .map_err(MioError::from_sys_error)
+}
+
+fn make_ipv6_mreq(group_addr: &IpAddr, interface: uint) -> MioResult<ffi::ipv6_mreq> {
+    match *group_addr {
+        IPv6Addr(a, b, c, d, e, f, g, h) => {
+            Ok(ffi::ipv6_mreq {
+                ipv6mr_multiaddr: ipv6_to_in6addr(a, b, c, d, e, f, g, h),
+                ipv6mr_interface: interface as nix::c_uint
+            })
+        }
+        _ => Err(MioError::from_errno(nix::EINVAL as nix::c_int))
+    }
+}
+
+fn make_ip_mreq(group_addr: &IpAddr, iface_addr: &Option<IpAddr>) -> MioResult<nix::ip_mreq> {
+    // Only IPv4 addresses are valid in an ip_mreq
+    match (*group_addr, *iface_addr) {
+        (IPv4Addr(..), None) | (IPv4Addr(..), Some(IPv4Addr(..))) => {}
+        _ => return Err(MioError::from_errno(nix::EINVAL as nix::c_int))
+    }
+
+    Ok(nix::ip_mreq {
+        imr_multiaddr: from_ip_addr_to_inaddr(&Some(*group_addr)),
+        imr_interface: from_ip_addr_to_inaddr(iface_addr)
+    })
+}
+
+fn from_ip_addr_to_inaddr(addr: &Option<IpAddr>) -> nix::in_addr {
+    match *addr {
+        Some(ip) => {
+            match ip {
+                IPv4Addr(a, b, c, d) => ipv4_to_inaddr(a, b, c, d),
+                _ => unimplemented!()
+            }
+        }
+        None => nix::in_addr { s_addr: nix::INADDR_ANY }
+    }
+}
+
+// Fails with EAFNOSUPPORT for other families, including AF_UNSPEC when the
+// kernel did not fill in an address
+pub fn storage_to_sockaddr(storage: &ffi::sockaddr_storage) -> MioResult<SockAddr> {
+    unsafe {
+        match storage.ss_family as nix::c_int {
+            nix::AF_INET => {
+                let sin: *const nix::sockaddr_in = mem::transmute(storage);
+                Ok(InetAddr(u32be_to_ipv4((*sin).sin_addr.s_addr), Int::from_be((*sin).sin_port)))
+            }
+            nix::AF_INET6 => {
+                let sin6: *const nix::sockaddr_in6 = mem::transmute(storage);
+                Ok(InetAddr(in6addr_to_ipv6(&(*sin6).sin6_addr), Int::from_be((*sin6).sin6_port)))
+            }
+            nix::AF_UNIX => {
+                let sun: *const nix::sockaddr_un = mem::transmute(storage);
+                let mut str_path = String::new();
+
+                for c in (*sun).sun_path.iter() {
+                    if *c == 0 { break; }
+                    str_path.push(*c as u8 as char);
+                }
+
+                Ok(UnixAddr(Path::new(str_path)))
+            }
+            _ => Err(MioError::from_errno(nix::EAFNOSUPPORT as nix::c_int))
+        }
+    }
+}
+
+fn from_sockaddr(addr: &SockAddr) -> nix::SockAddr {
+    use std::mem;
+
+    match *addr {
+        InetAddr(ip, port) => {
+            match ip {
+                IPv4Addr(a, b, c, d) => {
+                    let mut addr: nix::sockaddr_in = unsafe { mem::zeroed() };
+
+                    addr.sin_family = nix::AF_INET as nix::sa_family_t;
+                    addr.sin_port = port.to_be();
+                    addr.sin_addr = ipv4_to_inaddr(a, b, c, d);
+
+                    nix::SockAddr::SockIpV4(addr)
+                }
+                IPv6Addr(a, b, c, d, e, f, g, h) => {
+                    let mut addr: nix::sockaddr_in6 = unsafe { mem::zeroed() };
+
+                    addr.sin6_family = nix::AF_INET6 as nix::sa_family_t;
+                    addr.sin6_port = port.to_be();
+                    addr.sin6_addr = ipv6_to_in6addr(a, b, c, d, e, f, g, h);
+
+                    nix::SockAddr::SockIpV6(addr)
+                }
             }
         }
         UnixAddr(ref path) => {
@@ -309,12 +1213,38 @@ fn from_sockaddr(addr: &SockAddr) -> nix::SockAddr {
     }
 }
 
-fn ipv4_to_u32(a: u8, b: u8, c: u8, d: u8) -> nix::InAddrT {
-    Int::from_be((a as u32 << 24) |
-                 (b as u32 << 16) |
-                 (c as u32 <<  8) |
-                 (d as u32 <<  0))
-}
+// Converts the address to raw form, for calls taking a sockaddr pointer
+pub fn sockaddr_to_storage(addr: &SockAddr) -> (ffi::sockaddr_storage, ffi::socklen_t) {
+    let mut storage: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = unsafe {
+        let dst = &mut storage as *mut ffi::sockaddr_storage;
+
+        match from_sockaddr(addr) {
+            nix::SockAddr::SockIpV4(sin) => {
+                ptr::write(dst as *mut nix::sockaddr_in, sin);
+                mem::size_of::<nix::sockaddr_in>()
+            }
+            nix::SockAddr::SockIpV6(sin6) => {
+                ptr::write(dst as *mut nix::sockaddr_in6, sin6);
+                mem::size_of::<nix::sockaddr_in6>()
+            }
+            nix::SockAddr::SockUnix(sun) => {
+                ptr::write(dst as *mut nix::sockaddr_un, sun);
+                mem::size_of::<nix::sockaddr_un>()
+            }
+        }
+    };
+
+    (storage, len as ffi::socklen_t)
+}
+
+fn ipv4_to_u32(a: u8, b: u8, c: u8, d: u8) -> nix::InAddrT {
+    Int::from_be((a as u32 << 24) |
+                 (b as u32 << 16) |
+                 (c as u32 <<  8) |
+                 (d as u32 <<  0))
+}
 
 fn u32be_to_ipv4(net: u32) -> IpAddr {
     u32_to_ipv4(Int::from_be(net))
@@ -327,9 +1257,1021 @@ fn u32_to_ipv4(net: u32) -> IpAddr {
          (net & 0xff) as u8)
 }
 
+fn ipv6_to_in6addr(a: u16, b: u16, c: u16, d: u16,
+                   e: u16, f: u16, g: u16, h: u16) -> nix::in6_addr {
+    let mut addr: nix::in6_addr = unsafe { mem::zeroed() };
+    addr.s6_addr = [a.to_be(), b.to_be(), c.to_be(), d.to_be(),
+                    e.to_be(), f.to_be(), g.to_be(), h.to_be()];
+    addr
+}
+
+fn in6addr_to_ipv6(addr: &nix::in6_addr) -> IpAddr {
+    let s = &addr.s6_addr;
+
+    IPv6Addr(Int::from_be(s[0]), Int::from_be(s[1]),
+             Int::from_be(s[2]), Int::from_be(s[3]),
+             Int::from_be(s[4]), Int::from_be(s[5]),
+             Int::from_be(s[6]), Int::from_be(s[7]))
+}
+
 fn ipv4_to_inaddr(a: u8, b: u8, c: u8, d: u8) -> nix::in_addr {
     nix::in_addr {
         s_addr: ipv4_to_u32(a, b, c, d)
     }
 }
 
+/*
+ *
+ * ===== Messages =====
+ *
+ */
+
+// The result of a recvmsg call, the control messages are in the buffer passed
+// to recvmsg
+pub struct RecvMsg {
+    pub cnt: uint,
+    pub addr: ffi::sockaddr_storage,
+    // Zero if the sender has no address
+    pub addrlen: uint,
+    pub flags: nix::c_int,
+    pub controllen: uint,
+}
+
+pub fn recvmsg(io: &IoDesc, dst: &mut [u8], control: &mut [u8], flags: nix::c_int) -> MioResult<RecvMsg> {
+    let mut addr: ffi::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let mut iov = ffi::iovec {
+        iov_base: dst.as_mut_ptr() as *mut c_void,
+        iov_len: dst.len() as size_t
+    };
+
+    let mut msg: ffi::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut addr as *mut ffi::sockaddr_storage as *mut c_void;
+    msg.msg_namelen = mem::size_of::<ffi::sockaddr_storage>() as ffi::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if control.len() > 0 {
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = control.len() as ffi::controllen_t;
+    }
+
+    let res = unsafe { ffi::recvmsg(io.fd, &mut msg, flags) };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(RecvMsg {
+        cnt: res as uint,
+        addr: addr,
+        addrlen: msg.msg_namelen as uint,
+        flags: msg.msg_flags,
+        controllen: msg.msg_controllen as uint
+    })
+}
+
+pub fn recvmsg_addr(msg: &RecvMsg) -> MioResult<SockAddr> {
+    // Unix domain senders that are not bound to a path have no address, the
+    // kernel leaves it empty
+    if msg.addrlen == 0 {
+        return Ok(UnixAddr(Path::new("")));
+    }
+
+    storage_to_sockaddr(&msg.addr)
+}
+
+// Bytes needed in a control buffer for a message with len bytes of data
+pub fn cmsg_space(len: uint) -> uint {
+    cmsg_align(mem::size_of::<ffi::cmsghdr>()) + cmsg_align(len)
+}
+
+fn cmsg_align(len: uint) -> uint {
+    let align = mem::size_of::<ffi::cmsg_align_t>();
+    (len + align - 1) & !(align - 1)
+}
+
+// Iterates over the (level, type, data) of the control messages in a buffer
+// filled in by recvmsg
+pub struct Cmsgs<'a> {
+    control: &'a [u8],
+    offset: uint,
+}
+
+impl<'a> Cmsgs<'a> {
+    pub fn new(control: &'a [u8]) -> Cmsgs<'a> {
+        Cmsgs { control: control, offset: 0 }
+    }
+}
+
+impl<'a> Iterator<(nix::c_int, nix::c_int, &'a [u8])> for Cmsgs<'a> {
+    fn next(&mut self) -> Option<(nix::c_int, nix::c_int, &'a [u8])> {
+        let hdr_len = mem::size_of::<ffi::cmsghdr>();
+
+        if self.offset + hdr_len > self.control.len() {
+            return None;
+        }
+
+        let hdr: ffi::cmsghdr = unsafe {
+            ptr::read(self.control.as_ptr().offset(self.offset as int) as *const ffi::cmsghdr)
+        };
+
+        let start = self.offset + cmsg_align(hdr_len);
+        let end = self.offset + hdr.cmsg_len as uint;
+
+        if (hdr.cmsg_len as uint) < hdr_len || end > self.control.len() {
+            return None;
+        }
+
+        self.offset += cmsg_align(hdr.cmsg_len as uint);
+
+        Some((hdr.cmsg_level, hdr.cmsg_type, self.control.slice(start, end)))
+    }
+}
+
+// Receives up to one datagram into each buffer, returning the size and source
+// of each datagram received
+#[cfg(not(target_os = "linux"))]
+pub fn recv_multi(io: &IoDesc, bufs: &mut [&mut [u8]]) -> MioResult<Vec<(uint, SockAddr)>> {
+    let mut ret = Vec::with_capacity(bufs.len());
+    let mut empty: [u8, ..0] = [];
+
+    for buf in bufs.iter_mut() {
+        let res = recvmsg(io, &mut **buf, empty.as_mut_slice(), 0)
+            .and_then(|msg| recvmsg_addr(&msg).map(|addr| (msg.cnt, addr)));
+
+        match res {
+            Ok(datagram) => ret.push(datagram),
+            // Report the datagrams received before the error, like recvmmsg
+            Err(e) => {
+                if ret.is_empty() {
+                    return Err(e);
+                }
+
+                break;
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+// Sends each (data, destination) pair as a datagram, returning the number of
+// datagrams sent. A destination of None sends to the connected address.
+#[cfg(not(target_os = "linux"))]
+pub fn send_multi(io: &IoDesc, msgs: &[(&[u8], Option<&SockAddr>)]) -> MioResult<uint> {
+    let mut sent = 0;
+
+    for &(data, addr) in msgs.iter() {
+        let res = match addr {
+            Some(addr) => sendto(io, data, addr),
+            None => send(io, data)
+        };
+
+        match res {
+            Ok(_) => sent += 1,
+            // Report the datagrams sent before the error, like sendmmsg
+            Err(e) => {
+                if sent == 0 {
+                    return Err(e);
+                }
+
+                break;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+// Reports the destination address and interface of received datagrams as
+// control messages
+pub fn set_recv_pktinfo(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_RECVPKTINFO, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_recv_pktinfo_v6(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_RECVPKTINFO, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Makes the kernel split each datagram sent into segments of the given size,
+// 0 disables segmentation
+#[cfg(target_os = "linux")]
+pub fn set_udp_segment(io: &IoDesc, size: u16) -> MioResult<()> {
+    let v = size as nix::c_int;
+
+    nix::setsockopt(io.fd, ffi::SOL_UDP, ffi::UDP_SEGMENT, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Lets the kernel coalesce received datagrams of a flow, reporting the
+// segment size in a control message
+#[cfg(target_os = "linux")]
+pub fn set_udp_gro(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, ffi::SOL_UDP, ffi::UDP_GRO, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+#[cfg(target_os = "linux")]
+fn parse_gro_cmsg(meta: &mut RecvMeta, level: nix::c_int, ty: nix::c_int, data: &[u8]) {
+    if level == ffi::SOL_UDP && ty == ffi::UDP_GRO && data.len() >= mem::size_of::<nix::c_int>() {
+        let size: nix::c_int = unsafe { ptr::read(data.as_ptr() as *const nix::c_int) };
+        meta.segment_size = Some(size as uint);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn parse_gro_cmsg(_meta: &mut RecvMeta, _level: nix::c_int, _ty: nix::c_int, _data: &[u8]) {
+}
+
+// Reports the TOS byte, or the traffic class for IPv6, of received datagrams as
+// control messages, which carries the ECN bits
+pub fn set_recv_tos(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, nix::IPPROTO_IP, ffi::IP_RECVTOS, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+pub fn set_recv_tclass(io: &IoDesc, val: bool) -> MioResult<()> {
+    let v: nix::c_int = if val { 1 } else { 0 };
+
+    nix::setsockopt(io.fd, ffi::IPPROTO_IPV6, ffi::IPV6_RECVTCLASS, &v)
+        .map_err(MioError::from_sys_error)
+}
+
+// Sends a datagram with the given ECN codepoint, passed as an IP_TOS or
+// IPV6_TCLASS control message
+pub fn send_to_ecn(io: &IoDesc, src: &[u8], tgt: &SockAddr, ecn: u8) -> MioResult<uint> {
+    let (level, ty) = match tgt.family() {
+        Inet6 => (ffi::IPPROTO_IPV6, ffi::IPV6_TCLASS),
+        _ => (nix::IPPROTO_IP, ffi::IP_TOS)
+    };
+
+    send_to_cmsg(io, src, tgt, level, ty, &(ecn as nix::c_int))
+}
+
+// Sends a datagram from the given local address, passed as an IP_PKTINFO or
+// IPV6_PKTINFO control message. The address must be assigned to the host,
+// the kernel picks the interface.
+pub fn send_to_from(io: &IoDesc, src: &[u8], tgt: &SockAddr, from: &IpAddr) -> MioResult<uint> {
+    match *from {
+        IPv4Addr(..) => {
+            let info = ffi::in_pktinfo {
+                ipi_ifindex: 0,
+                ipi_spec_dst: from_ip_addr_to_inaddr(&Some(*from)),
+                ipi_addr: nix::in_addr { s_addr: nix::INADDR_ANY }
+            };
+
+            send_to_cmsg(io, src, tgt, nix::IPPROTO_IP, ffi::IP_PKTINFO, &info)
+        }
+        IPv6Addr(a, b, c, d, e, f, g, h) => {
+            let info = ffi::in6_pktinfo {
+                ipi6_addr: ipv6_to_in6addr(a, b, c, d, e, f, g, h),
+                ipi6_ifindex: 0
+            };
+
+            send_to_cmsg(io, src, tgt, ffi::IPPROTO_IPV6, ffi::IPV6_PKTINFO, &info)
+        }
+    }
+}
+
+// Sends a datagram along with a single control message holding val
+fn send_to_cmsg<T>(io: &IoDesc, src: &[u8], tgt: &SockAddr,
+                   level: nix::c_int, ty: nix::c_int, val: &T) -> MioResult<uint> {
+    let (mut addr, addr_len) = sockaddr_to_storage(tgt);
+    let data_len = mem::size_of::<T>();
+
+    // Room for a single control message holding a pktinfo struct or smaller
+    let mut control = [0u8, ..64];
+    let control_len = cmsg_space(data_len);
+    assert!(control_len <= control.len());
+
+    unsafe {
+        let hdr = ffi::cmsghdr {
+            cmsg_len: (cmsg_align(mem::size_of::<ffi::cmsghdr>()) + data_len) as ffi::controllen_t,
+            cmsg_level: level,
+            cmsg_type: ty
+        };
+
+        ptr::write(control.as_mut_ptr() as *mut ffi::cmsghdr, hdr);
+        ptr::copy_nonoverlapping_memory(
+            control.as_mut_ptr().offset(cmsg_align(mem::size_of::<ffi::cmsghdr>()) as int),
+            val as *const T as *const u8, data_len);
+    }
+
+    let mut iov = ffi::iovec {
+        iov_base: src.as_ptr() as *mut c_void,
+        iov_len: src.len() as size_t
+    };
+
+    let mut msg: ffi::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut addr as *mut ffi::sockaddr_storage as *mut c_void;
+    msg.msg_namelen = addr_len;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = control_len as ffi::controllen_t;
+
+    let res = unsafe { ffi::sendmsg(io.fd, &msg, 0) };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// The size of the next queued datagram, without dequeuing it
+#[cfg(target_os = "linux")]
+pub fn next_datagram_len(io: &IoDesc) -> MioResult<uint> {
+    let res = unsafe {
+        ffi::recv(io.fd, ptr::null_mut(), 0, ffi::MSG_PEEK | ffi::MSG_TRUNC)
+    };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Receives a datagram along with the metadata reported in control messages
+pub fn recv_meta(io: &IoDesc, dst: &mut [u8]) -> MioResult<(uint, RecvMeta)> {
+    let mut control = [0u8, ..256];
+
+    let buf_len = dst.len();
+
+    // On Linux, MSG_TRUNC makes recvmsg return the full size of a truncated
+    // datagram, rather than the number of bytes copied
+    let msg = try!(recvmsg(io, dst, control.as_mut_slice(), ffi::MSG_TRUNC_LEN));
+
+    let mut meta = RecvMeta {
+        addr: try!(recvmsg_addr(&msg)),
+        dst_addr: None,
+        interface: None,
+        segment_size: None,
+        ecn: None,
+        truncated: msg.flags & ffi::MSG_TRUNC != 0,
+        datagram_len: if ffi::MSG_TRUNC_LEN != 0 { Some(msg.cnt) } else { None }
+    };
+
+    for (level, ty, data) in Cmsgs::new(control.slice_to(msg.controllen)) {
+        if level == nix::IPPROTO_IP && ty == ffi::IP_PKTINFO &&
+                data.len() >= mem::size_of::<ffi::in_pktinfo>() {
+            let info: ffi::in_pktinfo = unsafe {
+                ptr::read(data.as_ptr() as *const ffi::in_pktinfo)
+            };
+
+            meta.dst_addr = Some(u32be_to_ipv4(info.ipi_addr.s_addr));
+            meta.interface = Some(info.ipi_ifindex as uint);
+        } else if level == ffi::IPPROTO_IPV6 && ty == ffi::IPV6_PKTINFO &&
+                data.len() >= mem::size_of::<ffi::in6_pktinfo>() {
+            let info: ffi::in6_pktinfo = unsafe {
+                ptr::read(data.as_ptr() as *const ffi::in6_pktinfo)
+            };
+
+            meta.dst_addr = Some(in6addr_to_ipv6(&info.ipi6_addr));
+            meta.interface = Some(info.ipi6_ifindex as uint);
+        } else if level == nix::IPPROTO_IP && ty == ffi::IP_RECVTOS_CMSG && data.len() >= 1 {
+            meta.ecn = Some(Ecn::from_bits(data[0]));
+        } else if level == ffi::IPPROTO_IPV6 && ty == ffi::IPV6_TCLASS &&
+                data.len() >= mem::size_of::<nix::c_int>() {
+            let tclass: nix::c_int = unsafe { ptr::read(data.as_ptr() as *const nix::c_int) };
+            meta.ecn = Some(Ecn::from_bits(tclass as u8));
+        } else {
+            parse_gro_cmsg(&mut meta, level, ty, data);
+        }
+    }
+
+    Ok((cmp::min(msg.cnt, buf_len), meta))
+}
+
+/*
+ *
+ * ===== Descriptor passing =====
+ *
+ */
+
+// Sends the bytes along with duplicates of the descriptors, passed as a
+// SCM_RIGHTS control message. At least one byte has to be sent for the
+// descriptors to be delivered on stream sockets.
+pub fn send_fds(io: &IoDesc, src: &[u8], fds: &[nix::Fd]) -> MioResult<uint> {
+    let data_len = fds.len() * mem::size_of::<nix::Fd>();
+    let mut control = Vec::from_elem(cmsg_space(data_len), 0u8);
+
+    let mut iov = ffi::iovec {
+        iov_base: src.as_ptr() as *mut c_void,
+        iov_len: src.len() as size_t
+    };
+
+    let mut msg: ffi::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if fds.len() > 0 {
+        unsafe {
+            let hdr = ffi::cmsghdr {
+                cmsg_len: (cmsg_align(mem::size_of::<ffi::cmsghdr>()) + data_len) as ffi::controllen_t,
+                cmsg_level: nix::SOL_SOCKET,
+                cmsg_type: ffi::SCM_RIGHTS
+            };
+
+            ptr::write(control.as_mut_ptr() as *mut ffi::cmsghdr, hdr);
+            ptr::copy_nonoverlapping_memory(
+                control.as_mut_ptr().offset(cmsg_align(mem::size_of::<ffi::cmsghdr>()) as int)
+                    as *mut nix::Fd,
+                fds.as_ptr(), fds.len());
+        }
+
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = control.len() as ffi::controllen_t;
+    }
+
+    let res = unsafe { ffi::sendmsg(io.fd, &msg, 0) };
+
+    if res < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(res as uint)
+}
+
+// Receives bytes along with up to fds.len() descriptors. The descriptors are
+// close-on-exec, any that do not fit into fds are closed.
+pub fn recv_fds(io: &IoDesc, dst: &mut [u8], fds: &mut [nix::Fd]) -> MioResult<RecvFds> {
+    // Alignment padding can leave room for more descriptors than asked for
+    let mut control = Vec::from_elem(cmsg_space(fds.len() * mem::size_of::<nix::Fd>()), 0u8);
+    let msg = try!(recvmsg(io, dst, control.as_mut_slice(), ffi::MSG_CMSG_CLOEXEC));
+
+    // Owning every received descriptor first ensures that none leak, whether
+    // setting close-on-exec fails or they do not fit
+    let mut received = Vec::new();
+
+    for (level, ty, data) in Cmsgs::new(control.slice_to(msg.controllen)) {
+        if level != nix::SOL_SOCKET || ty != ffi::SCM_RIGHTS {
+            continue;
+        }
+
+        for chunk in data.chunks(mem::size_of::<nix::Fd>()) {
+            if chunk.len() < mem::size_of::<nix::Fd>() {
+                break;
+            }
+
+            received.push(IoDesc { fd: unsafe { ptr::read(chunk.as_ptr() as *const nix::Fd) } });
+        }
+    }
+
+    for desc in received.iter() {
+        try!(set_recv_cloexec(desc.fd));
+    }
+
+    let truncated = msg.flags & ffi::MSG_CTRUNC != 0 || received.len() > fds.len();
+    let mut cnt = 0;
+
+    for desc in received.into_iter() {
+        if cnt == fds.len() {
+            // Dropping the rest closes them
+            break;
+        }
+
+        fds[cnt] = desc.fd;
+        unsafe { mem::forget(desc) };
+        cnt += 1;
+    }
+
+    Ok(RecvFds {
+        bytes: msg.cnt,
+        fds: cnt,
+        truncated: truncated
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn set_recv_cloexec(_: nix::Fd) -> MioResult<()> {
+    // Already set by MSG_CMSG_CLOEXEC
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_recv_cloexec(fd: nix::Fd) -> MioResult<()> {
+    if unsafe { ffi::fcntl(fd, ffi::F_SETFD, ffi::FD_CLOEXEC) } < 0 {
+        return Err(MioError::from_sys_error(SysError::last()));
+    }
+
+    Ok(())
+}
+
+/*
+ *
+ * ===== Signal masks =====
+ *
+ */
+
+/// A set of signals, used as the signal mask in effect while the selector
+/// is blocked, see `EventLoop::set_poll_sigmask`.
+#[deriving(Clone)]
+pub struct SigSet {
+    raw: ffi::sigset_t
+}
+
+impl SigSet {
+    /// A set containing no signals
+    pub fn empty() -> SigSet {
+        let mut raw = unsafe { mem::uninitialized() };
+        unsafe { ffi::sigemptyset(&mut raw) };
+        SigSet { raw: raw }
+    }
+
+    /// A set containing all signals
+    pub fn all() -> SigSet {
+        let mut raw = unsafe { mem::uninitialized() };
+        unsafe { ffi::sigfillset(&mut raw) };
+        SigSet { raw: raw }
+    }
+
+    pub fn add(&mut self, signum: int) {
+        unsafe { ffi::sigaddset(&mut self.raw, signum as nix::c_int) };
+    }
+
+    pub fn remove(&mut self, signum: int) {
+        unsafe { ffi::sigdelset(&mut self.raw, signum as nix::c_int) };
+    }
+
+    pub fn contains(&self, signum: int) -> bool {
+        unsafe { ffi::sigismember(&self.raw, signum as nix::c_int) == 1 }
+    }
+
+    pub fn as_raw(&self) -> &ffi::sigset_t {
+        &self.raw
+    }
+
+    // Replaces the signal mask of the calling thread, returning the previous
+    // one
+    pub fn set_thread_mask(&self) -> MioResult<SigSet> {
+        let mut prev = SigSet::empty();
+
+        let res = unsafe {
+            ffi::pthread_sigmask(ffi::SIG_SETMASK, &self.raw, &mut prev.raw)
+        };
+
+        // pthread_sigmask returns the error instead of setting errno
+        if res != 0 {
+            return Err(MioError::from_errno(res));
+        }
+
+        Ok(prev)
+    }
+}
+
+pub mod ffi {
+    use libc::{c_char, c_int, c_uint, c_ulong, c_void, size_t, ssize_t};
+
+    pub use libc::{off_t, sockaddr, sockaddr_storage, socklen_t};
+
+    #[cfg(target_os = "linux")]
+    pub const SOCK_STREAM: c_int = 1;
+
+    pub const SOCK_SEQPACKET: c_int = 5;
+
+    #[cfg(target_os = "linux")]
+    pub const SOCK_NONBLOCK: c_int = 0o4000;
+
+    #[cfg(target_os = "linux")]
+    pub const SOCK_CLOEXEC: c_int = 0o2000000;
+
+    #[cfg(target_os = "linux")]
+    pub const FIONREAD: c_ulong = 0x541b;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const FIONREAD: c_ulong = 0x4004667f;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_KEEPIDLE: c_int = 4;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_KEEPINTVL: c_int = 5;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_KEEPCNT: c_int = 6;
+
+    // Named TCP_KEEPALIVE on OS X
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const TCP_KEEPIDLE: c_int = 0x10;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const TCP_KEEPINTVL: c_int = 0x101;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const TCP_KEEPCNT: c_int = 0x102;
+
+    pub const MSG_OOB: c_int = 0x1;
+    pub const MSG_PEEK: c_int = 0x2;
+
+    #[cfg(target_os = "linux")]
+    pub const MSG_TRUNC: c_int = 0x20;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const MSG_TRUNC: c_int = 0x10;
+
+    // Only Linux reports the full length of truncated datagrams when passed
+    // MSG_TRUNC
+    #[cfg(target_os = "linux")]
+    pub const MSG_TRUNC_LEN: c_int = MSG_TRUNC;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const MSG_TRUNC_LEN: c_int = 0;
+
+    #[cfg(target_os = "linux")]
+    pub const MSG_CMSG_CLOEXEC: c_int = 0x40000000;
+
+    // Not supported, received descriptors are made close-on-exec with fcntl
+    // instead
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const MSG_CMSG_CLOEXEC: c_int = 0;
+
+    #[cfg(target_os = "linux")]
+    pub const MSG_CTRUNC: c_int = 0x8;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const MSG_CTRUNC: c_int = 0x20;
+
+    pub const SCM_RIGHTS: c_int = 1;
+
+    pub const SHUT_RD: c_int = 0;
+    pub const SHUT_WR: c_int = 1;
+    pub const SHUT_RDWR: c_int = 2;
+
+    pub const F_SETFD: c_int = 2;
+    pub const F_GETFL: c_int = 3;
+    pub const F_SETFL: c_int = 4;
+    pub const FD_CLOEXEC: c_int = 1;
+
+    #[cfg(target_os = "linux")]
+    pub const F_DUPFD_CLOEXEC: c_int = 1030;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const F_DUPFD_CLOEXEC: c_int = 67;
+
+    #[cfg(target_os = "linux")]
+    pub const O_NONBLOCK: c_int = 0x800;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const O_NONBLOCK: c_int = 0x4;
+
+    pub const IPPROTO_IPV6: c_int = 41;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_V6ONLY: c_int = 26;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_V6ONLY: c_int = 27;
+
+    #[cfg(target_os = "linux")]
+    pub const SOL_UDP: c_int = 17;
+
+    #[cfg(target_os = "linux")]
+    pub const UDP_SEGMENT: c_int = 103;
+
+    #[cfg(target_os = "linux")]
+    pub const UDP_GRO: c_int = 104;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_RECVTOS: c_int = 13;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_RECVTOS: c_int = 27;
+
+    // The type of the control message carrying the TOS byte of a received
+    // datagram
+    #[cfg(target_os = "linux")]
+    pub const IP_RECVTOS_CMSG: c_int = IP_TOS;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_RECVTOS_CMSG: c_int = IP_RECVTOS;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_RECVTCLASS: c_int = 66;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_RECVTCLASS: c_int = 35;
+
+    // Linux uses IP_PKTINFO both to enable the option and as the type of the
+    // control message
+    #[cfg(target_os = "linux")]
+    pub const IP_RECVPKTINFO: c_int = 8;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_PKTINFO: c_int = 8;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_RECVPKTINFO: c_int = 49;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_PKTINFO: c_int = 50;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_RECVPKTINFO: c_int = 26;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_PKTINFO: c_int = 26;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_RECVPKTINFO: c_int = 61;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_PKTINFO: c_int = 46;
+
+    #[repr(C)]
+    pub struct in_pktinfo {
+        pub ipi_ifindex: c_uint,
+        pub ipi_spec_dst: super::nix::in_addr,
+        pub ipi_addr: super::nix::in_addr,
+    }
+
+    #[repr(C)]
+    pub struct in6_pktinfo {
+        pub ipi6_addr: super::nix::in6_addr,
+        pub ipi6_ifindex: c_uint,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub const IP_MULTICAST_IF: c_int = 32;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_MULTICAST_LOOP: c_int = 34;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_MULTICAST_IF: c_int = 9;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_MULTICAST_LOOP: c_int = 11;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_MULTICAST_IF: c_int = 17;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_MULTICAST_HOPS: c_int = 18;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_MULTICAST_LOOP: c_int = 19;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_MULTICAST_IF: c_int = 9;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_MULTICAST_HOPS: c_int = 10;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_MULTICAST_LOOP: c_int = 11;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_JOIN_GROUP: c_int = 20;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_LEAVE_GROUP: c_int = 21;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_JOIN_GROUP: c_int = 12;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_LEAVE_GROUP: c_int = 13;
+
+    #[repr(C)]
+    pub struct ipv6_mreq {
+        pub ipv6mr_multiaddr: super::nix::in6_addr,
+        pub ipv6mr_interface: c_uint,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub const SO_BROADCAST: c_int = 6;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const SO_BROADCAST: c_int = 0x20;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_TTL: c_int = 2;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_TTL: c_int = 4;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_UNICAST_HOPS: c_int = 16;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_UNICAST_HOPS: c_int = 4;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_TOS: c_int = 1;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_TOS: c_int = 3;
+
+    #[cfg(target_os = "linux")]
+    pub const IPV6_TCLASS: c_int = 67;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IPV6_TCLASS: c_int = 36;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_FREEBIND: c_int = 15;
+
+    #[cfg(target_os = "linux")]
+    pub const IP_TRANSPARENT: c_int = 19;
+
+    #[cfg(target_os = "linux")]
+    pub const SO_ORIGINAL_DST: c_int = 80;
+
+    #[cfg(target_os = "linux")]
+    pub const IPPROTO_MPTCP: c_int = 262;
+
+    #[cfg(target_os = "linux")]
+    pub const SOL_MPTCP: c_int = 284;
+
+    #[cfg(target_os = "linux")]
+    pub const MPTCP_INFO: c_int = 1;
+
+    #[cfg(target_os = "linux")]
+    pub const MPTCP_INFO_FLAG_FALLBACK: u32 = 1 << 0;
+
+    #[cfg(target_os = "linux")]
+    pub const MPTCP_INFO_FLAG_REMOTE_KEY_RECEIVED: u32 = 1 << 1;
+
+    #[repr(C)]
+    pub struct iovec {
+        pub iov_base: *mut c_void,
+        pub iov_len: size_t,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub type controllen_t = size_t;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub type controllen_t = socklen_t;
+
+    // Control messages are aligned to a size_t on Linux, and to 4 bytes on
+    // OS X
+    #[cfg(target_os = "linux")]
+    pub type cmsg_align_t = size_t;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub type cmsg_align_t = u32;
+
+    #[repr(C)]
+    pub struct msghdr {
+        pub msg_name: *mut c_void,
+        pub msg_namelen: socklen_t,
+        pub msg_iov: *mut iovec,
+        #[cfg(target_os = "linux")]
+        pub msg_iovlen: size_t,
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        pub msg_iovlen: c_int,
+        pub msg_control: *mut c_void,
+        pub msg_controllen: controllen_t,
+        pub msg_flags: c_int,
+    }
+
+    #[repr(C)]
+    pub struct cmsghdr {
+        pub cmsg_len: controllen_t,
+        pub cmsg_level: c_int,
+        pub cmsg_type: c_int,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub const SO_BINDTODEVICE: c_int = 25;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const IP_BOUND_IF: c_int = 25;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_DEFER_ACCEPT: c_int = 9;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_FASTOPEN: c_int = 23;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_FASTOPEN_CONNECT: c_int = 30;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const TCP_FASTOPEN: c_int = 0x105;
+
+    #[cfg(target_os = "linux")]
+    pub const TCP_USER_TIMEOUT: c_int = 18;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const TCP_CONNECTION_INFO: c_int = 0x106;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    #[repr(C)]
+    pub struct tcp_connection_info {
+        pub tcpi_state: u8,
+        pub tcpi_snd_wscale: u8,
+        pub tcpi_rcv_wscale: u8,
+        pub __pad1: u8,
+        pub tcpi_options: u32,
+        pub tcpi_flags: u32,
+        pub tcpi_rto: u32,
+        pub tcpi_maxseg: u32,
+        pub tcpi_snd_ssthresh: u32,
+        pub tcpi_snd_cwnd: u32,
+        pub tcpi_snd_wnd: u32,
+        pub tcpi_snd_sbbytes: u32,
+        pub tcpi_rcv_wnd: u32,
+        pub tcpi_rttcur: u32,
+        pub tcpi_srtt: u32,
+        pub tcpi_rttvar: u32,
+        pub tcpi_tfo_flags: u32,
+        pub tcpi_txpackets: u64,
+        pub tcpi_txbytes: u64,
+        pub tcpi_txretransmitbytes: u64,
+        pub tcpi_rxpackets: u64,
+        pub tcpi_rxbytes: u64,
+        pub tcpi_rxoutoforderbytes: u64,
+        pub tcpi_txretransmitpackets: u64,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub const SO_PEERCRED: c_int = 17;
+
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    pub struct ucred {
+        pub pid: i32,
+        pub uid: u32,
+        pub gid: u32,
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const SOL_LOCAL: c_int = 0;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const LOCAL_PEERPID: c_int = 0x002;
+
+    #[cfg(target_os = "linux")]
+    pub const SIG_SETMASK: c_int = 2;
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub const SIG_SETMASK: c_int = 3;
+
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    #[deriving(Clone)]
+    pub struct sigset_t {
+        val: [u64, ..16],
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub type sigset_t = u32;
+
+    extern {
+        pub fn ioctl(fd: c_int, req: c_ulong, ...) -> c_int;
+        pub fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+        pub fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+        pub fn socketpair(domain: c_int, ty: c_int, protocol: c_int, fds: *mut c_int) -> c_int;
+        pub fn shutdown(fd: c_int, how: c_int) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn sendfile(out_fd: c_int, in_fd: c_int, offset: *mut off_t, count: size_t) -> ssize_t;
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        pub fn sendfile(fd: c_int, s: c_int, offset: off_t, len: *mut off_t,
+                        hdtr: *mut c_void, flags: c_int) -> c_int;
+        pub fn getsockname(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t) -> c_int;
+        pub fn getpeername(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t) -> c_int;
+        pub fn setsockopt(fd: c_int, level: c_int, name: c_int,
+                          val: *const c_void, len: socklen_t) -> c_int;
+        pub fn getsockopt(fd: c_int, level: c_int, name: c_int,
+                          val: *mut c_void, len: *mut socklen_t) -> c_int;
+        pub fn if_nametoindex(name: *const c_char) -> c_uint;
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        pub fn getpeereid(fd: c_int, uid: *mut u32, gid: *mut u32) -> c_int;
+        pub fn send(fd: c_int, buf: *const c_void, len: size_t, flags: c_int) -> ssize_t;
+        pub fn recvmsg(fd: c_int, msg: *mut msghdr, flags: c_int) -> ssize_t;
+        pub fn sendmsg(fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t;
+        pub fn recv(fd: c_int, buf: *mut c_void, len: size_t, flags: c_int) -> ssize_t;
+        pub fn recvfrom(fd: c_int, buf: *mut c_void, len: size_t, flags: c_int,
+                        addr: *mut sockaddr, addrlen: *mut socklen_t) -> ssize_t;
+        pub fn accept(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn accept4(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t, flags: c_int) -> c_int;
+        pub fn sigemptyset(set: *mut sigset_t) -> c_int;
+        pub fn sigfillset(set: *mut sigset_t) -> c_int;
+        pub fn sigaddset(set: *mut sigset_t, signum: c_int) -> c_int;
+        pub fn sigdelset(set: *mut sigset_t, signum: c_int) -> c_int;
+        pub fn sigismember(set: *const sigset_t, signum: c_int) -> c_int;
+        pub fn pthread_sigmask(how: c_int, set: *const sigset_t, oldset: *mut sigset_t) -> c_int;
+        #[cfg(target_os = "linux")]
+        #[link_name = "__errno_location"]
+        pub fn errno_location() -> *mut c_int;
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        #[link_name = "__error"]
+        pub fn errno_location() -> *mut c_int;
+    }
+}
@@ -1,36 +1,69 @@
+use std::collections::HashMap;
+use nix::fcntl::Fd;
 use error::MioResult;
 use io::IoHandle;
 use os;
 use os::token::Token;
 use os::event;
 
+pub use os::Events;
+
+/// The readiness layer the `EventLoop` is built on, usable on its own by
+/// code that runs its own loop.
+///
+/// IO handles are registered with a token, and `poll` fills a caller owned
+/// `Events` buffer with the tokens of the handles that became ready.
 pub struct Poll {
     selector: os::Selector,
-    events: os::Events
+    fds: HashMap<Fd, Registration>,
+    // Several descriptors may share a token, kept in sync with `fds` to find
+    // the descriptors of an event's token without a scan
+    tokens: HashMap<Token, Vec<Fd>>,
+}
+
+/// The token and interest an IO handle is currently registered with.
+#[deriving(Clone, Show)]
+pub struct Registration {
+    pub token: Token,
+    pub interest: event::Interest,
+    pub opts: event::PollOpt,
 }
 
 impl Poll {
     pub fn new() -> MioResult<Poll> {
         Ok(Poll {
             selector: try!(os::Selector::new()),
-            events: os::Events::new()
+            fds: HashMap::new(),
+            tokens: HashMap::new(),
         })
     }
 
     pub fn register<H: IoHandle>(&mut self, io: &H, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.register_fd(io.desc().fd, token, interest, opts)
+    }
+
+    /// Registers a file descriptor owned elsewhere, which must stay open
+    /// until it is deregistered.
+    pub fn register_fd(&mut self, fd: Fd, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
         debug!("registering  with poller");
 
         // Register interests for this socket
-        try!(self.selector.register(io.desc(), token.as_uint(), interest, opts));
+        try!(self.selector.register_fd(fd, token.as_uint(), interest, opts));
+        self.track(fd, token, interest, opts);
 
         Ok(())
     }
 
     pub fn reregister<H: IoHandle>(&mut self, io: &H, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
+        self.reregister_fd(io.desc().fd, token, interest, opts)
+    }
+
+    pub fn reregister_fd(&mut self, fd: Fd, token: Token, interest: event::Interest, opts: event::PollOpt) -> MioResult<()> {
         debug!("registering  with poller");
 
         // Register interests for this socket
-        try!(self.selector.reregister(io.desc(), token.as_uint(), interest, opts));
+        try!(self.selector.reregister_fd(fd, token.as_uint(), interest, opts));
+        self.track(fd, token, interest, opts);
 
         Ok(())
     }
@@ -38,18 +71,150 @@ impl Poll {
     pub fn deregister<H: IoHandle>(&mut self, io: &H) -> MioResult<()> {
         debug!("deregistering IO with poller");
 
-        // Deregister interests for this socket
-        try!(self.selector.deregister(io.desc()));
+        // Deregister interests for this socket, the descriptor is no longer
+        // tracked even if the OS refuses
+        let res = self.selector.deregister(io.desc());
+        self.untrack(io.desc().fd);
+        res
+    }
 
-        Ok(())
+    /// Deregisters the given file descriptor.
+    pub fn deregister_fd(&mut self, fd: Fd) -> MioResult<()> {
+        // Stop tracking the descriptor even if the OS no longer knows about
+        // it, for example because it has been closed.
+        let res = self.selector.deregister_fd(fd);
+        self.untrack(fd);
+        res
+    }
+
+    /// Returns the token the given file descriptor is registered with.
+    pub fn token_for(&self, fd: Fd) -> Option<Token> {
+        self.fds.get(&fd).map(|reg| reg.token)
+    }
+
+    /// Returns the current registration of the given file descriptor.
+    pub fn registration(&self, fd: Fd) -> Option<Registration> {
+        self.fds.get(&fd).map(|reg| reg.clone())
+    }
+
+    /// Returns the file descriptors currently registered.
+    pub fn registered_fds(&self) -> Vec<Fd> {
+        self.fds.keys().map(|fd| *fd).collect()
+    }
+
+    /// Returns the file descriptors registered with the given token.
+    pub fn fds_for(&self, token: Token) -> &[Fd] {
+        match self.tokens.get(&token) {
+            Some(fds) => fds.as_slice(),
+            None => &[]
+        }
+    }
+
+    /// Returns a file descriptor registered with the given token, an
+    /// arbitrary one if several descriptors share the token.
+    pub fn fd_for(&self, token: Token) -> Option<Fd> {
+        self.fds_for(token).get(0).map(|fd| *fd)
+    }
+
+    fn track(&mut self, fd: Fd, token: Token, interest: event::Interest, opts: event::PollOpt) {
+        let reg = Registration {
+            token: token,
+            interest: interest,
+            opts: opts
+        };
+
+        // Replaces the previous registration, the descriptor may also have
+        // been closed without being deregistered and reused by the OS.
+        self.untrack(fd);
+        self.fds.insert(fd, reg);
+
+        match self.tokens.get_mut(&token) {
+            Some(fds) => {
+                fds.push(fd);
+                return;
+            }
+            None => {}
+        }
+
+        self.tokens.insert(token, vec![fd]);
+    }
+
+    fn untrack(&mut self, fd: Fd) {
+        let token = match self.token_for(fd) {
+            Some(token) => token,
+            None => return
+        };
+
+        self.fds.remove(&fd);
+
+        let empty = match self.tokens.get_mut(&token) {
+            Some(fds) => {
+                match fds.iter().position(|f| *f == fd) {
+                    Some(idx) => { fds.swap_remove(idx); }
+                    None => {}
+                }
+
+                fds.is_empty()
+            }
+            None => false
+        };
+
+        if empty {
+            self.tokens.remove(&token);
+        }
     }
 
-    pub fn poll(&mut self, timeout_ms: uint) -> MioResult<uint> {
-        try!(self.selector.select(&mut self.events, timeout_ms));
-        Ok(self.events.len())
+    /// Sets the signal mask in effect while `poll` blocks, `None` keeps the
+    /// mask of the calling thread.
+    pub fn set_sigmask(&mut self, sigmask: Option<os::SigSet>) {
+        self.selector.set_sigmask(sigmask);
     }
 
-    pub fn event(&self, idx: uint) -> event::IoEvent {
-        self.events.get(idx)
+    /// Waits for at most `timeout_ms` for IO events and stores them in
+    /// `events`, replacing its previous contents. Returns the number of
+    /// events, at most the capacity of the buffer.
+    pub fn poll(&mut self, events: &mut Events, timeout_ms: uint) -> MioResult<uint> {
+        try!(self.selector.select(events, timeout_ms));
+        Ok(events.len())
+    }
+}
+
+// The selector's own descriptor becomes readable when IO events are pending,
+// so the poller can be embedded in a foreign event loop.
+impl IoHandle for Poll {
+    fn desc(&self) -> &os::IoDesc {
+        self.selector.desc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Poll;
+    use io::{pipe, IoHandle};
+    use os::event;
+    use os::token::Token;
+
+    #[test]
+    fn test_shared_token() {
+        let mut poll = Poll::new().unwrap();
+        let (r1, _w1) = pipe().unwrap();
+        let (r2, _w2) = pipe().unwrap();
+
+        // Both descriptors are tracked under the same token
+        poll.register(&r1, Token(5), event::READABLE, event::LEVEL).unwrap();
+        poll.register(&r2, Token(5), event::READABLE, event::LEVEL).unwrap();
+        assert_eq!(Some(Token(5)), poll.token_for(r1.desc().fd));
+        assert_eq!(Some(Token(5)), poll.token_for(r2.desc().fd));
+        assert_eq!(2, poll.fds_for(Token(5)).len());
+
+        // Deregistering one leaves the other in place
+        poll.deregister(&r1).unwrap();
+        assert_eq!(None, poll.token_for(r1.desc().fd));
+        assert_eq!([r2.desc().fd].as_slice(), poll.fds_for(Token(5)));
+
+        // Reregistering under a new token leaves nothing under the old one
+        poll.reregister(&r2, Token(6), event::READABLE, event::LEVEL).unwrap();
+        assert!(poll.fds_for(Token(5)).is_empty());
+        assert_eq!([r2.desc().fd].as_slice(), poll.fds_for(Token(6)));
     }
 }
@@ -1,12 +1,14 @@
 use std::uint;
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::time::duration::Duration;
-use std::num::UnsignedInt;
-use time::precise_time_ns;
+use std::num::{Int, UnsignedInt};
+use clock::{Clock, MonotonicClock, MonotonicInstant};
+use io::IoHandle;
+use os;
 use os::token::Token;
 use util::Slab;
 
-use self::TimerErrorKind::TimerOverflow;
+use self::TimerErrorKind::{TimerOverflow, TimerInvalidPeriod};
 
 const EMPTY: Token = Token(uint::MAX);
 const NS_PER_MS: u64 = 1_000_000;
@@ -14,11 +16,14 @@ const NS_PER_MS: u64 = 1_000_000;
 // Implements coarse-grained timeouts using an algorithm based on hashed timing
 // wheels by Varghese & Lauck.
 //
+// Inserting and clearing a timeout is O(1) regardless of the number of
+// pending timeouts, so per connection timeouts can be reset on every read.
+//
 // TODO:
-// * Handle the case when the timer falls more than an entire wheel behind. There
-//   is no point to loop multiple times around the wheel in one go.
 // * New type for tick, now() -> Tick
 pub struct Timer<T> {
+    // Source of the current time
+    clock: Box<Clock + Send>,
     // Size of each tick in milliseconds
     tick_ms: u64,
     // Slab of timeout entries
@@ -34,6 +39,11 @@ pub struct Timer<T> {
     next: Token,
     // Masks the target tick to get the slot
     mask: u64,
+    // Wake-ups are delayed to the next multiple of the slack, in ms
+    slack_ms: u64,
+    // Precise timeouts, kept out of the wheel and sorted by descending
+    // deadline in ns, so the next one to trigger is last
+    precise: Vec<(u64, Token)>,
 }
 
 pub const EMPTY_TIMEOUT: Timeout = Timeout{token: EMPTY, tick: 0u64};
@@ -46,21 +56,35 @@ pub struct Timeout {
 }
 
 impl<T> Timer<T> {
-    pub fn new(tick_ms: u64, mut slots: uint, mut capacity: uint) -> Timer<T> {
+    pub fn new(tick_ms: u64, slots: uint, capacity: uint) -> Timer<T> {
+        Timer::with_clock(tick_ms, slots, capacity, box MonotonicClock)
+    }
+
+    pub fn with_clock(tick_ms: u64, mut slots: uint, mut capacity: uint, clock: Box<Clock + Send>) -> Timer<T> {
         slots = UnsignedInt::next_power_of_two(slots);
         capacity = UnsignedInt::next_power_of_two(capacity);
 
         Timer {
+            clock: clock,
             tick_ms: tick_ms,
             entries: Slab::new(capacity),
             wheel: Vec::from_fn(slots, |_| EMPTY),
             start: 0,
             tick: 0,
             next: EMPTY,
-            mask: (slots as u64) - 1
+            mask: (slots as u64) - 1,
+            slack_ms: 0,
+            precise: Vec::new(),
         }
     }
 
+    // Delays wake-ups for the next tick to the next multiple of `slack_ms` on
+    // the clock, so that ticks falling within the same window are handled by
+    // a single wake-up. Zero disables coalescing.
+    pub fn set_slack_ms(&mut self, slack_ms: u64) {
+        self.slack_ms = slack_ms;
+    }
+
     pub fn count(&self) -> uint {
         self.entries.count()
     }
@@ -68,7 +92,7 @@ impl<T> Timer<T> {
     // Number of ms remaining until the next tick
     pub fn next_tick_in_ms(&self) -> u64 {
         let now = self.now_ms();
-        let nxt = self.start + (self.tick + 1) * self.tick_ms;
+        let nxt = self.with_slack(self.start + (self.tick + 1) * self.tick_ms);
 
         if nxt <= now {
             return 0;
@@ -77,6 +101,57 @@ impl<T> Timer<T> {
         nxt - now
     }
 
+    // Time of the next tick in ns, on the timer's clock
+    pub fn next_tick_at_ns(&self) -> u64 {
+        self.with_slack(self.start + self.tick * self.tick_ms) * NS_PER_MS
+    }
+
+    // Time of the next wake-up in ns on the timer's clock, the earlier of the
+    // next tick when timeouts are pending in the wheel and the next precise
+    // deadline. Zero when no timeout is pending.
+    pub fn next_wakeup_at_ns(&self) -> u64 {
+        let tick = if self.entries.count() > self.precise.len() {
+            Some(self.next_tick_at_ns())
+        } else {
+            None
+        };
+
+        match (tick, self.next_precise_at_ns()) {
+            (Some(tick), Some(precise)) => min(tick, max(precise, 1)),
+            (Some(tick), None) => tick,
+            (None, Some(precise)) => max(precise, 1),
+            (None, None) => 0
+        }
+    }
+
+    // Deadline of the next precise timeout in ns, on the timer's clock
+    pub fn next_precise_at_ns(&self) -> Option<u64> {
+        self.precise.last().map(|&(at, _)| at)
+    }
+
+    // Number of ms remaining until the next precise timeout, rounded up
+    pub fn next_precise_in_ms(&self) -> Option<u64> {
+        self.next_precise_at_ns().map(|at| {
+            let now = self.now_ns();
+
+            if at <= now {
+                return 0;
+            }
+
+            (at - now + NS_PER_MS - 1) / NS_PER_MS
+        })
+    }
+
+    // Rounds the time in ms up to the slack window
+    #[inline]
+    fn with_slack(&self, ms: u64) -> u64 {
+        if self.slack_ms == 0 {
+            return ms;
+        }
+
+        (ms + self.slack_ms - 1) / self.slack_ms * self.slack_ms
+    }
+
     /*
      *
      * ===== Initialization =====
@@ -105,46 +180,148 @@ impl<T> Timer<T> {
         self.timeout_at_ms(token, at)
     }
 
-    pub fn timeout_at_ms(&mut self, token: T, mut at: u64) -> TimerResult<Timeout> {
-        // Make relative to start
-        at -= self.start;
-        // Calculate tick
-        let mut tick = (at + self.tick_ms - 1) / self.tick_ms;
+    pub fn timeout_ms(&mut self, token: T, delay: u64) -> TimerResult<Timeout> {
+        let at = self.now_ms() + delay;
+        self.timeout_at_ms(token, at)
+    }
 
-        // Always target at least 1 tick in the future
-        if tick <= self.tick {
-            tick = self.tick + 1;
-        }
+    pub fn timeout_at(&mut self, token: T, at: MonotonicInstant) -> TimerResult<Timeout> {
+        self.timeout_at_ms(token, at.as_ns() / NS_PER_MS)
+    }
 
+    pub fn timeout_at_ms(&mut self, token: T, at: u64) -> TimerResult<Timeout> {
+        let tick = self.tick_for_ms(at);
         self.insert(token, tick)
     }
 
+    // Triggers once the clock reaches `at`, in ns, rather than on the tick
+    // covering it. Slack does not apply.
+    pub fn timeout_at_ns(&mut self, token: T, at: u64) -> TimerResult<Timeout> {
+        let mut entry = Entry::new(token, at, EMPTY);
+        entry.precise = true;
+
+        let token = try!(
+            self.entries.insert(entry)
+            .map_err(|_| TimerError::overflow()));
+
+        self.link_precise(token, at);
+
+        Ok(Timeout {
+            token: token,
+            tick: at
+        })
+    }
+
+    pub fn timeout_ns(&mut self, token: T, delay: u64) -> TimerResult<Timeout> {
+        let at = self.now_ns().saturating_add(delay);
+        self.timeout_at_ns(token, at)
+    }
+
+    pub fn reset(&mut self, timeout: Timeout, delay: Duration) -> Option<Timeout> {
+        let at = self.now_ms() + (max(0, delay.num_milliseconds()) as u64);
+        self.reset_at_ms(timeout, at)
+    }
+
+    pub fn reset_ms(&mut self, timeout: Timeout, delay: u64) -> Option<Timeout> {
+        let at = self.now_ms() + delay;
+        self.reset_at_ms(timeout, at)
+    }
+
+    // Moves a pending timeout to a new deadline, reusing its entry. Returns
+    // None if the timeout already fired or was cleared.
+    pub fn reset_at_ms(&mut self, timeout: Timeout, at: u64) -> Option<Timeout> {
+        let (links, precise) = match self.entries.get(timeout.token) {
+            Some(e) => (e.links, e.precise),
+            None => return None
+        };
+
+        // Sanity check
+        if self.entries[timeout.token].id != timeout.tick {
+            return None;
+        }
+
+        // A precise timeout stays precise, with the new deadline in ms
+        if precise {
+            let at = at * NS_PER_MS;
+
+            self.unlink_precise(timeout.token);
+            self.link_precise(timeout.token, at);
+            self.entries[timeout.token].id = at;
+
+            return Some(Timeout {
+                token: timeout.token,
+                tick: at
+            });
+        }
+
+        let tick = self.tick_for_ms(at);
+
+        self.unlink(&links, timeout.token);
+        self.link(timeout.token, tick);
+
+        let entry = &mut self.entries[timeout.token];
+        entry.id = tick;
+
+        match entry.interval {
+            Some(ref mut interval) => interval.deadline = at,
+            None => {}
+        }
+
+        Some(Timeout {
+            token: timeout.token,
+            tick: tick
+        })
+    }
+
     pub fn clear(&mut self, timeout: Timeout) -> bool {
-        let links = match self.entries.get(timeout.token) {
-            Some(e) => e.links,
+        let (links, precise) = match self.entries.get(timeout.token) {
+            Some(e) => (e.links, e.precise),
             None => return false
         };
 
         // Sanity check
-        if links.tick != timeout.tick {
+        if self.entries[timeout.token].id != timeout.tick {
             return false;
         }
 
-        self.unlink(&links, timeout.token);
+        if precise {
+            self.unlink_precise(timeout.token);
+        } else {
+            self.unlink(&links, timeout.token);
+        }
+
         self.entries.remove(timeout.token);
         true
     }
 
     fn insert(&mut self, token: T, tick: u64) -> TimerResult<Timeout> {
-        // Get the slot for the requested tick
-        let slot = (tick & self.mask) as uint;
-        let curr = self.wheel[slot];
-
-        // Insert the new entry
+        // Insert the new entry, it gets linked into its slot below
         let token = try!(
-            self.entries.insert(Entry::new(token, tick, curr))
+            self.entries.insert(Entry::new(token, tick, EMPTY))
             .map_err(|_| TimerError::overflow()));
 
+        self.link(token, tick);
+
+        debug!("inserted timout; slot={}; token={}", self.slot_for(tick), token);
+
+        // Return the new timeout
+        Ok(Timeout {
+            token: token,
+            tick: tick
+        })
+    }
+
+    // Links the entry at the head of the slot for the requested tick
+    fn link(&mut self, token: Token, tick: u64) {
+        let slot = self.slot_for(tick);
+        let curr = self.wheel[slot];
+
+        self.entries[token].links = EntryLinks {
+            tick: tick,
+            prev: EMPTY,
+            next: curr,
+        };
+
         if curr != EMPTY {
             // If there was a previous entry, set its prev pointer to the new
             // entry
@@ -153,14 +330,6 @@ impl<T> Timer<T> {
 
         // Update the head slot
         self.wheel[slot] = token;
-
-        debug!("inserted timout; slot={}; token={}", slot, token);
-
-        // Return the new timeout
-        Ok(Timeout {
-            token: token,
-            tick: tick
-        })
     }
 
     fn unlink(&mut self, links: &EntryLinks, token: Token) {
@@ -185,6 +354,22 @@ impl<T> Timer<T> {
         }
     }
 
+    // Inserts a precise timeout, keeping the list sorted
+    fn link_precise(&mut self, token: Token, at: u64) {
+        let idx = self.precise.iter()
+            .position(|&(deadline, _)| deadline < at)
+            .unwrap_or(self.precise.len());
+
+        self.precise.insert(idx, (at, token));
+    }
+
+    fn unlink_precise(&mut self, token: Token) {
+        match self.precise.iter().position(|&(_, t)| t == token) {
+            Some(idx) => { self.precise.remove(idx); }
+            None => {}
+        }
+    }
+
     /*
      *
      * ===== Advance time =====
@@ -195,9 +380,30 @@ impl<T> Timer<T> {
         self.ms_to_tick(self.now_ms())
     }
 
+    // Returns the next precise timeout due at `now`, in ns
+    pub fn precise_to(&mut self, now: u64) -> Option<T> {
+        match self.precise.last() {
+            Some(&(at, _)) if at <= now => {}
+            _ => return None
+        }
+
+        let (_, token) = self.precise.pop().unwrap();
+
+        self.entries.remove(token)
+            .map(|e| e.token)
+    }
+
     pub fn tick_to(&mut self, now: u64) -> Option<T> {
         debug!("tick_to; now={}; tick={}", now, self.tick);
 
+        // When the timer falls more than an entire wheel behind, a single
+        // revolution ending at `now` visits every slot once at a tick no
+        // earlier than any due entry in it, so skip the remaining laps.
+        if now > self.tick + self.mask {
+            self.tick = now - self.mask;
+            self.next = self.wheel[self.slot_for(self.tick)];
+        }
+
         while self.tick <= now {
             let curr = self.next;
 
@@ -215,6 +421,25 @@ impl<T> Timer<T> {
                     // Unlink will also advance self.next
                     self.unlink(&links, curr);
 
+                    let interval = self.entries[curr].interval;
+
+                    match interval {
+                        Some(mut interval) => {
+                            // The next deadline is based on the previous
+                            // one, so the interval does not drift
+                            interval.deadline += interval.period;
+
+                            let tick = self.tick_for_ms(interval.deadline);
+                            self.link(curr, tick);
+
+                            let entry = &mut self.entries[curr];
+                            entry.interval = Some(interval);
+
+                            return Some((interval.clone)(&entry.token));
+                        }
+                        None => {}
+                    }
+
                     // Remove and return the token
                     return self.entries.remove(curr)
                         .map(|e| e.token);
@@ -244,15 +469,37 @@ impl<T> Timer<T> {
         (self.mask & tick) as uint
     }
 
+    // Tick at which a timeout for the given time triggers, always at least
+    // one tick in the future
+    fn tick_for_ms(&self, at: u64) -> u64 {
+        // Deadlines before the timer started, e.g. an instant taken before
+        // the event loop was created, are due immediately
+        let at = max(at, self.start);
+
+        // Make relative to start, rounding up
+        let tick = (at - self.start + self.tick_ms - 1) / self.tick_ms;
+
+        if tick <= self.tick {
+            return self.tick + 1;
+        }
+
+        tick
+    }
+
     // Convert a ms duration into a number of ticks, rounds up
     #[inline]
     fn ms_to_tick(&self, ms: u64) -> u64 {
-        (ms - self.start) / self.tick_ms
+        (max(ms, self.start) - self.start) / self.tick_ms
     }
 
     #[inline]
     fn now_ms(&self) -> u64 {
-        precise_time_ns() / NS_PER_MS
+        self.now_ns() / NS_PER_MS
+    }
+
+    #[inline]
+    pub fn now_ns(&self) -> u64 {
+        self.clock.now_ns()
     }
 }
 
@@ -261,12 +508,36 @@ impl<T> Timer<T> {
 struct Entry<T> {
     token: T,
     links: EntryLinks,
+    // Tick the entry was scheduled for when created or last reset, matched
+    // against the tick of a `Timeout` to detect stale handles
+    id: u64,
+    interval: Option<Interval<T>>,
+    // Not linked into the wheel, see `Timer::timeout_at_ns`. The id holds the
+    // deadline in ns.
+    precise: bool,
+}
+
+// Schedule of a recurring timeout
+struct Interval<T> {
+    // Period in milliseconds
+    period: u64,
+    // The current deadline in milliseconds
+    deadline: u64,
+    // Creates the token passed to each trigger
+    clone: fn(&T) -> T,
+}
+
+fn clone_token<T: Clone>(token: &T) -> T {
+    token.clone()
 }
 
 impl<T> Entry<T> {
     fn new(token: T, tick: u64, next: Token) -> Entry<T> {
         Entry {
             token: token,
+            id: tick,
+            interval: None,
+            precise: false,
             links: EntryLinks {
                 tick: tick,
                 prev: EMPTY,
@@ -282,6 +553,38 @@ struct EntryLinks {
     next: Token
 }
 
+impl<T: Clone> Timer<T> {
+    pub fn interval_ms(&mut self, token: T, period: u64) -> TimerResult<Timeout> {
+        let at = self.now_ms() + period;
+        self.interval_at_ms(token, at, period)
+    }
+
+    // Triggers at `at` and every `period` milliseconds afterwards, until
+    // cleared. A zero period is rejected, as it would re-arm on every tick.
+    pub fn interval_at_ms(&mut self, token: T, at: u64, period: u64) -> TimerResult<Timeout> {
+        if period == 0 {
+            return Err(TimerError::invalid_period());
+        }
+
+        let tick = self.tick_for_ms(at);
+        let timeout = try!(self.insert(token, tick));
+
+        self.entries[timeout.token].interval = Some(Interval {
+            period: period,
+            deadline: at,
+            clone: clone_token::<T>,
+        });
+
+        Ok(timeout)
+    }
+}
+
+impl IoHandle for os::TimerFd {
+    fn desc(&self) -> &os::IoDesc {
+        self.desc()
+    }
+}
+
 pub type TimerResult<T> = Result<T, TimerError>;
 
 #[deriving(PartialEq, Eq, Clone, Show)]
@@ -297,16 +600,24 @@ impl TimerError {
             desc: "too many timer entries"
         }
     }
+
+    fn invalid_period() -> TimerError {
+        TimerError {
+            kind: TimerInvalidPeriod,
+            desc: "interval period must not be zero"
+        }
+    }
 }
 
 #[deriving(PartialEq, Eq, Clone, Show)]
 pub enum TimerErrorKind {
     TimerOverflow,
+    TimerInvalidPeriod,
 }
 
 #[cfg(test)]
 mod test {
-    use super::Timer;
+    use super::{Timer, NS_PER_MS};
 
     #[test]
     pub fn test_timeout_next_tick() {
@@ -468,6 +779,156 @@ mod test {
         assert_eq!(0, t.count());
     }
 
+    #[test]
+    pub fn test_catching_up_multiple_wheels() {
+        let mut t = timer();
+        let mut tick;
+
+        t.timeout_at_ms("a", 100).unwrap();
+        t.timeout_at_ms("b", 100 + 3 * TICK * SLOTS as u64).unwrap();
+        t.timeout_at_ms("c", 100 + 5 * TICK * SLOTS as u64).unwrap();
+
+        tick = t.ms_to_tick(100 + 4 * TICK * SLOTS as u64);
+        let mut rcv = vec![t.tick_to(tick).unwrap(), t.tick_to(tick).unwrap()];
+        assert_eq!(None, t.tick_to(tick));
+
+        rcv.sort();
+        assert!(rcv.as_slice() == ["a", "b"].as_slice(), "actual={}", rcv.as_slice());
+        assert_eq!(1, t.count());
+
+        tick = t.ms_to_tick(100 + 5 * TICK * SLOTS as u64);
+        assert_eq!(Some("c"), t.tick_to(tick));
+        assert_eq!(0, t.count());
+    }
+
+    #[test]
+    pub fn test_resetting_timeout() {
+        let mut t = timer();
+        let mut tick;
+
+        let a = t.timeout_at_ms("a", 100).unwrap();
+        let _ = t.timeout_at_ms("b", 100).unwrap();
+
+        let a = t.reset_at_ms(a, 300).unwrap();
+        assert_eq!(2, t.count());
+
+        tick = t.ms_to_tick(100);
+        assert_eq!(Some("b"), t.tick_to(tick));
+        assert_eq!(None, t.tick_to(tick));
+
+        tick = t.ms_to_tick(300);
+        assert_eq!(Some("a"), t.tick_to(tick));
+        assert_eq!(0, t.count());
+
+        // Already fired
+        assert!(t.reset_at_ms(a, 400).is_none());
+        assert_eq!(0, t.count());
+    }
+
+    #[test]
+    pub fn test_interval_zero_period() {
+        let mut t = timer();
+
+        assert!(t.interval_at_ms("a", 100, 0).is_err());
+        assert!(t.interval_ms("a", 0).is_err());
+        assert_eq!(0, t.count());
+    }
+
+    #[test]
+    pub fn test_interval() {
+        let mut t = timer();
+        let mut tick;
+
+        let a = t.interval_at_ms("a", 100, 150).unwrap();
+
+        tick = t.ms_to_tick(100);
+        assert_eq!(Some("a"), t.tick_to(tick));
+        assert_eq!(None, t.tick_to(tick));
+
+        // Next deadline at 250ms, triggered on the tick covering it
+        tick = t.ms_to_tick(200);
+        assert_eq!(None, t.tick_to(tick));
+
+        tick = t.ms_to_tick(300);
+        assert_eq!(Some("a"), t.tick_to(tick));
+        assert_eq!(None, t.tick_to(tick));
+
+        // Next deadline at 400ms, not pushed back by the late trigger
+        tick = t.ms_to_tick(400);
+        assert_eq!(Some("a"), t.tick_to(tick));
+        assert_eq!(1, t.count());
+
+        assert!(t.clear(a));
+        assert_eq!(0, t.count());
+
+        tick = t.ms_to_tick(600);
+        assert_eq!(None, t.tick_to(tick));
+    }
+
+    #[test]
+    pub fn test_timeout_before_start() {
+        let mut t = timer();
+        t.set_start_ms(1_000);
+
+        t.timeout_at_ms("a", 500).unwrap();
+
+        let tick = t.ms_to_tick(1_100);
+        assert_eq!(Some("a"), t.tick_to(tick));
+    }
+
+    #[test]
+    pub fn test_slack_rounds_wakeups() {
+        let mut t = timer();
+
+        t.timeout_at_ms("a", 100).unwrap();
+
+        let tick = t.ms_to_tick(50);
+        assert_eq!(None, t.tick_to(tick));
+        assert_eq!(100 * NS_PER_MS, t.next_tick_at_ns());
+
+        t.set_slack_ms(250);
+        assert_eq!(250 * NS_PER_MS, t.next_tick_at_ns());
+    }
+
+    #[test]
+    pub fn test_precise_timeouts() {
+        let mut t = timer();
+
+        let tick = t.ms_to_tick(0);
+        assert_eq!(None, t.tick_to(tick));
+
+        let a = t.timeout_at_ns("a", 300_000).unwrap();
+        let b = t.timeout_at_ns("b", 100_000).unwrap();
+        t.timeout_at_ns("c", 200_000).unwrap();
+        t.timeout_at_ms("d", 100).unwrap();
+
+        // The precise deadline comes before the first tick
+        assert_eq!(Some(100_000), t.next_precise_at_ns());
+        assert_eq!(100_000, t.next_wakeup_at_ns());
+
+        assert!(t.clear(b));
+        assert_eq!(Some(200_000), t.next_precise_at_ns());
+
+        let a = t.reset_at_ms(a, 1).unwrap();
+        assert_eq!(Some(200_000), t.next_precise_at_ns());
+
+        assert_eq!(None, t.precise_to(199_999));
+        assert_eq!(Some("c"), t.precise_to(200_000));
+        assert_eq!(None, t.precise_to(200_000));
+        assert_eq!(Some(NS_PER_MS), t.next_precise_at_ns());
+
+        // Precise timeouts do not fire from the wheel
+        let tick = t.ms_to_tick(100);
+        assert_eq!(Some("d"), t.tick_to(tick));
+        assert_eq!(None, t.tick_to(tick));
+        assert_eq!(NS_PER_MS, t.next_wakeup_at_ns());
+
+        assert_eq!(Some("a"), t.precise_to(NS_PER_MS));
+        assert!(!t.clear(a));
+        assert_eq!(0, t.count());
+        assert_eq!(0, t.next_wakeup_at_ns());
+    }
+
     const TICK: u64 = 100;
     const SLOTS: uint = 16;
 
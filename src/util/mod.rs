@@ -1,5 +1,7 @@
 pub use self::mpmc_bounded_queue::Queue as BoundedQueue;
+pub use self::mpsc_queue::Queue as UnboundedQueue;
 pub use self::slab::Slab;
 
 mod mpmc_bounded_queue;
+mod mpsc_queue;
 mod slab;
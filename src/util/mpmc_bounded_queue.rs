@@ -84,7 +84,7 @@ impl<T: Send> State<T> {
         }
     }
 
-    fn push(&self, value: T) -> bool {
+    fn push(&self, value: T) -> Result<(), T> {
         let mask = self.mask;
         let mut pos = self.enqueue_pos.load(Relaxed);
         loop {
@@ -104,12 +104,25 @@ impl<T: Send> State<T> {
                     pos = enqueue_pos;
                 }
             } else if diff < 0 {
-                return false
+                return Err(value)
             } else {
                 pos = self.enqueue_pos.load(Relaxed);
             }
         }
-        true
+        Ok(())
+    }
+
+    fn len(&self) -> uint {
+        let dequeue_pos = self.dequeue_pos.load(Relaxed);
+        let enqueue_pos = self.enqueue_pos.load(Relaxed);
+
+        // The positions are loaded independently, so the result is only an
+        // approximation when other threads are pushing / popping concurrently.
+        if enqueue_pos > dequeue_pos {
+            enqueue_pos - dequeue_pos
+        } else {
+            0
+        }
     }
 
     fn pop(&self) -> Option<T> {
@@ -146,13 +159,25 @@ impl<T: Send> Queue<T> {
         }
     }
 
-    pub fn push(&self, value: T) -> bool {
+    pub fn push(&self, value: T) -> Result<(), T> {
         self.state.push(value)
     }
 
     pub fn pop(&self) -> Option<T> {
         self.state.pop()
     }
+
+    pub fn len(&self) -> uint {
+        self.state.len()
+    }
+
+    pub fn capacity(&self) -> uint {
+        self.state.mask + 1
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
 }
 
 impl<T: Send> Clone for Queue<T> {
@@ -171,6 +196,7 @@ mod tests {
         let nmsgs = 1000u;
         let q = Queue::with_capacity(nthreads*nmsgs);
         assert_eq!(None, q.pop());
+        assert_eq!(0, q.len());
         let (tx, rx) = channel();
 
         for _ in range(0, nthreads) {
@@ -179,7 +205,7 @@ mod tests {
             spawn(proc() {
                 let q = q;
                 for i in range(0, nmsgs) {
-                    assert!(q.push(i));
+                    assert!(q.push(i).is_ok());
                 }
                 tx.send(());
             });
@@ -213,4 +239,23 @@ mod tests {
             rx.recv();
         }
     }
+
+    #[test]
+    fn test_len_and_capacity() {
+        let q = Queue::with_capacity(3);
+        assert_eq!(4, q.capacity());
+        assert!(!q.is_full());
+
+        for i in range(0u, 4) {
+            assert!(q.push(i).is_ok());
+        }
+
+        assert_eq!(4, q.len());
+        assert!(q.is_full());
+        assert!(q.push(4).is_err());
+
+        q.pop();
+        assert_eq!(3, q.len());
+        assert!(!q.is_full());
+    }
 }
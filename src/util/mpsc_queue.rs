@@ -0,0 +1,173 @@
+/* Copyright (c) 2010-2011 Dmitry Vyukov. All rights reserved.
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ *    1. Redistributions of source code must retain the above copyright notice,
+ *       this list of conditions and the following disclaimer.
+ *
+ *    2. Redistributions in binary form must reproduce the above copyright
+ *       notice, this list of conditions and the following disclaimer in the
+ *       documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY DMITRY VYUKOV "AS IS" AND ANY EXPRESS OR IMPLIED
+ * WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT
+ * SHALL DMITRY VYUKOV OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT,
+ * INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+ * LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+ * PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+ * LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE
+ * OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF
+ * ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ * The views and conclusions contained in the software and documentation are
+ * those of the authors and should not be interpreted as representing official
+ * policies, either expressed or implied, of Dmitry Vyukov.
+ */
+
+#![allow(missing_docs, dead_code)]
+
+// http://www.1024cores.net/home/lock-free-algorithms/queues/non-intrusive-mpsc-node-based-queue
+
+// This queue is adapted from the old rust stdlib mpsc_queue. Pushing is lock
+// free and never fails, the queue grows as needed. There must only ever be a
+// single consumer, in mio's case this is the event loop thread.
+
+use std::mem;
+use std::cell::UnsafeCell;
+
+use std::sync::atomic::{AtomicPtr, AtomicUint, Release, Acquire, AcqRel, Relaxed};
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    value: Option<T>,
+}
+
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: UnsafeCell<*mut Node<T>>,
+    // Approximate number of values in the queue
+    len: AtomicUint,
+}
+
+impl<T> Node<T> {
+    unsafe fn new(v: Option<T>) -> *mut Node<T> {
+        mem::transmute(box Node {
+            next: AtomicPtr::new(0 as *mut Node<T>),
+            value: v,
+        })
+    }
+}
+
+impl<T: Send> Queue<T> {
+    pub fn new() -> Queue<T> {
+        let stub = unsafe { Node::new(None) };
+        Queue {
+            head: AtomicPtr::new(stub),
+            tail: UnsafeCell::new(stub),
+            len: AtomicUint::new(0),
+        }
+    }
+
+    pub fn push(&self, t: T) {
+        unsafe {
+            let n = Node::new(Some(t));
+            let prev = self.head.swap(n, AcqRel);
+            (*prev).next.store(n, Release);
+        }
+
+        self.len.fetch_add(1, Relaxed);
+    }
+
+    pub fn len(&self) -> uint {
+        self.len.load(Relaxed)
+    }
+
+    /// Pops a value from the queue. Returns `None` if the queue is empty, or
+    /// if a producer is in the middle of a push that the values after it
+    /// depend on, in which case the caller should retry later.
+    ///
+    /// Unsafe because there must only be a single consumer, concurrent pops
+    /// are a data race.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let tail = *self.tail.get();
+        let next = (*tail).next.load(Acquire);
+
+        if next.is_null() {
+            return None;
+        }
+
+        *self.tail.get() = next;
+        assert!((*tail).value.is_none());
+        assert!((*next).value.is_some());
+        let ret = (*next).value.take().unwrap();
+        let _: Box<Node<T>> = mem::transmute(tail);
+        self.len.fetch_sub(1, Relaxed);
+        Some(ret)
+    }
+}
+
+#[unsafe_destructor]
+impl<T: Send> Drop for Queue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut cur = *self.tail.get();
+            while !cur.is_null() {
+                let next = (*cur).next.load(Relaxed);
+                let _: Box<Node<T>> = mem::transmute(cur);
+                cur = next;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::Queue;
+
+    #[test]
+    fn test_push_pop() {
+        let q = Queue::new();
+        q.push(box 1i);
+        q.push(box 2i);
+        unsafe {
+            assert_eq!(Some(box 1i), q.pop());
+            assert_eq!(Some(box 2i), q.pop());
+            assert_eq!(None, q.pop());
+        }
+    }
+
+    #[test]
+    fn test() {
+        let nthreads = 8u;
+        let nmsgs = 1000u;
+        let q = Queue::new();
+        assert_eq!(None, unsafe { q.pop() });
+        let (tx, rx) = channel();
+        let q = Arc::new(q);
+
+        for _ in range(0, nthreads) {
+            let tx = tx.clone();
+            let q = q.clone();
+            spawn(proc() {
+                for i in range(0, nmsgs) {
+                    q.push(i);
+                }
+                tx.send(());
+            });
+        }
+
+        let mut i = 0u;
+        while i < nthreads * nmsgs {
+            match unsafe { q.pop() } {
+                None => {},
+                Some(_) => i += 1,
+            }
+        }
+        drop(tx);
+        for _ in range(0, nthreads) {
+            rx.recv();
+        }
+    }
+}
@@ -1,21 +1,44 @@
 #![feature(globs)]
 #![feature(phase)]
 
+extern crate libc;
 extern crate mio;
 
 #[phase(plugin, link)]
 extern crate log;
 
+use std::io::timer::sleep;
+use std::time::Duration;
+use mio::NonBlock;
+use mio::net::tcp::{TcpAcceptor, TcpSocket};
+
 pub use ports::localhost;
 
+// Accepts a connection on a non-blocking listener, waiting for the client to
+// get through
+pub fn accept(server: &mut TcpAcceptor) -> TcpSocket {
+    loop {
+        match server.accept().unwrap() {
+            NonBlock::Ready((sock, _)) => return sock,
+            NonBlock::WouldBlock => sleep(Duration::milliseconds(10))
+        }
+    }
+}
+
 mod test_close_on_drop;
 mod test_echo_server;
+mod test_local_addr;
 mod test_notify;
 mod test_timer;
 mod test_udp_socket;
 mod test_udp_socket_connectionless;
+mod test_udp_datagrams;
 mod test_register_deregister;
+mod test_tcp_socket;
 mod test_unix_echo_server;
+mod test_unix_socket;
+#[cfg(target_os = "linux")]
+mod test_zerocopy;
 
 mod ports {
     use std::sync::atomic::{AtomicUint, SeqCst, INIT_ATOMIC_UINT};
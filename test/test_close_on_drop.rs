@@ -4,6 +4,7 @@ use mio::net::*;
 use mio::net::tcp::*;
 use super::localhost;
 use mio::event as evt;
+use std::time::Duration;
 
 use self::TestState::{Initial, AfterRead, AfterHup};
 
@@ -115,3 +116,19 @@ pub fn test_close_on_drop() {
 
     assert!(handler.state == AfterHup, "actual={}", handler.state);
 }
+
+#[test]
+pub fn test_linger_rounds_up() {
+    debug!("Starting TEST_LINGER_ROUNDS_UP");
+    let sock = TcpSocket::v4().unwrap();
+
+    // A sub-second linger time still closes gracefully
+    sock.set_linger(Some(Duration::milliseconds(500))).unwrap();
+    assert!(sock.linger().unwrap() == Some(Duration::seconds(1)));
+
+    sock.set_linger(Some(Duration::zero())).unwrap();
+    assert!(sock.linger().unwrap() == Some(Duration::zero()));
+
+    sock.set_linger(None).unwrap();
+    assert!(sock.linger().unwrap().is_none());
+}
@@ -84,7 +84,7 @@ impl EchoServer {
     fn accept(&mut self, event_loop: &mut TestEventLoop) -> MioResult<()> {
         debug!("server accepting socket");
 
-        let sock = self.sock.accept().unwrap().unwrap();
+        let (sock, _) = self.sock.accept().unwrap().unwrap();
         let conn = EchoConn::new(sock,);
         let tok = self.conns.insert(conn)
             .ok().expect("could not add connectiont o slab");
@@ -0,0 +1,33 @@
+use mio::net::*;
+use mio::net::tcp::*;
+use mio::net::udp::*;
+use mio::net::SockAddr::InetAddr;
+
+#[test]
+pub fn test_tcp_local_addr_of_port_zero() {
+    let addr = SockAddr::parse("127.0.0.1:0").expect("could not parse InetAddr");
+
+    let srv = TcpSocket::v4().unwrap();
+    srv.set_reuseaddr(true).unwrap();
+
+    let srv = srv.bind(&addr).unwrap();
+
+    match srv.local_addr().unwrap() {
+        InetAddr(ip, port) => {
+            assert_eq!(ip, IPv4Addr(127, 0, 0, 1));
+            assert!(port != 0, "kernel did not assign a port");
+        }
+        a => panic!("unexpected address {}", a)
+    }
+}
+
+#[test]
+pub fn test_udp_local_addr_of_port_zero() {
+    let addr = SockAddr::parse("127.0.0.1:0").expect("could not parse InetAddr");
+    let sock = UdpSocket::bound(&addr).unwrap();
+
+    match sock.local_addr().unwrap() {
+        InetAddr(_, port) => assert!(port != 0, "kernel did not assign a port"),
+        a => panic!("unexpected address {}", a)
+    }
+}
@@ -1,3 +1,4 @@
+use std::default::Default;
 use std::io::timer::sleep;
 use std::time::Duration;
 use mio::*;
@@ -72,3 +73,253 @@ pub fn test_notify() {
 
     assert!(h.notify == 2, "actual={}", h.notify);
 }
+
+#[test]
+pub fn test_notify_batch_full() {
+    debug!("Starting TEST_NOTIFY_BATCH_FULL");
+    let config = EventLoopConfig { notify_capacity: 4, .. Default::default() };
+    let event_loop: EventLoop<uint, uint> = EventLoop::configured(config).unwrap();
+    let sender = event_loop.channel();
+
+    let mut msgs = range(0u, 6);
+    let err = sender.send_batch(&mut msgs).unwrap_err();
+
+    // The messages that fit are queued, the one that did not is handed back
+    // and the rest are left in the iterator
+    assert!(err.queued == 4, "actual={}", err.queued);
+    assert!(err.rejected == Some(4), "actual={}", err.rejected);
+    assert!(err.error.is_none());
+    assert!(msgs.next() == Some(5));
+    assert!(sender.is_full());
+}
+
+// Records the messages it receives, shutting the event loop down once it has
+// seen `expect` of them
+struct RecordHandler {
+    msgs: Vec<uint>,
+    dropped: Vec<uint>,
+    expect: uint,
+    shutdown: uint
+}
+
+impl RecordHandler {
+    fn new(expect: uint) -> RecordHandler {
+        RecordHandler {
+            msgs: vec![],
+            dropped: vec![],
+            expect: expect,
+            shutdown: 0
+        }
+    }
+}
+
+impl Handler<uint, uint> for RecordHandler {
+    fn notify(&mut self, event_loop: &mut EventLoop<uint, uint>, msg: uint) {
+        self.msgs.push(msg);
+
+        if self.msgs.len() == self.expect {
+            event_loop.shutdown();
+        }
+    }
+
+    fn shutdown(&mut self, _event_loop: &mut EventLoop<uint, uint>) {
+        self.shutdown += 1;
+    }
+
+    fn notify_dropped(&mut self, _event_loop: &mut EventLoop<uint, uint>, msg: uint) {
+        self.dropped.push(msg);
+    }
+}
+
+fn bounded_event_loop(capacity: uint) -> EventLoop<uint, uint> {
+    let config = EventLoopConfig { notify_capacity: capacity, .. Default::default() };
+    EventLoop::configured(config).unwrap()
+}
+
+#[test]
+pub fn test_notify_full() {
+    debug!("Starting TEST_NOTIFY_FULL");
+    let mut event_loop = bounded_event_loop(2);
+    let sender = event_loop.channel();
+
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+
+    // The message that does not fit is handed back to the caller
+    let err = sender.send(2).unwrap_err();
+    assert!(err.is_full());
+    assert!(err.into_inner() == Some(2));
+
+    let h = event_loop.run(RecordHandler::new(2))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs == vec![0, 1], "actual={}", h.msgs);
+
+    // Once the event loop has caught up, there is room again
+    sender.send(3).unwrap();
+}
+
+#[test]
+pub fn test_notify_io_error() {
+    debug!("Starting TEST_NOTIFY_IO_ERROR");
+    // An IO error means the message was queued, so there is nothing to hand
+    // back
+    let err: NotifyError<uint> = NotifyError::Io(MioError::eof());
+    assert!(!err.is_full());
+    assert!(err.into_inner().is_none());
+}
+
+#[test]
+pub fn test_notify_timeout() {
+    debug!("Starting TEST_NOTIFY_TIMEOUT");
+    let event_loop = bounded_event_loop(2);
+    let sender = event_loop.channel();
+
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+
+    // Nothing drains the queue, so the send gives up after the timeout
+    let start = MonotonicInstant::now();
+    let err = sender.send_timeout(2, Duration::milliseconds(100)).unwrap_err();
+    let elapsed = MonotonicInstant::now().duration_since(start);
+
+    assert!(err.into_inner() == Some(2));
+    assert!(elapsed >= Duration::milliseconds(90), "actual={}", elapsed);
+
+    // A negative timeout does not wait at all
+    let err = sender.send_timeout(3, Duration::milliseconds(-100)).unwrap_err();
+    assert!(err.into_inner() == Some(3));
+}
+
+#[test]
+pub fn test_notify_timeout_long() {
+    debug!("Starting TEST_NOTIFY_TIMEOUT_LONG");
+    let mut event_loop = bounded_event_loop(2);
+    let sender = event_loop.channel();
+
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+
+    let (tx, rx) = channel();
+
+    // Too long to count in nanoseconds, so the send waits for the event
+    // loop instead of timing out
+    spawn(proc() {
+        let res = sender.send_timeout(2, Duration::days(365_000));
+        let waited = res.is_ok();
+
+        if !waited {
+            sender.send_wait(2).unwrap();
+        }
+
+        tx.send(waited);
+    });
+
+    sleep(Duration::milliseconds(50));
+
+    let h = event_loop.run(RecordHandler::new(3))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs == vec![0, 1, 2], "actual={}", h.msgs);
+    assert!(rx.recv());
+}
+
+#[test]
+pub fn test_notify_wait() {
+    debug!("Starting TEST_NOTIFY_WAIT");
+    let mut event_loop = bounded_event_loop(2);
+    let sender = event_loop.channel();
+
+    // Once the queue is full, every send blocks until the event loop has
+    // received a message
+    spawn(proc() {
+        for i in range(0u, 8) {
+            sender.send_wait(i).unwrap();
+        }
+    });
+
+    let h = event_loop.run(RecordHandler::new(8))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs == vec![0, 1, 2, 3, 4, 5, 6, 7], "actual={}", h.msgs);
+}
+
+#[test]
+pub fn test_notify_urgent() {
+    debug!("Starting TEST_NOTIFY_URGENT");
+    let mut event_loop = bounded_event_loop(4);
+    let sender = event_loop.channel();
+
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+    sender.send_urgent(2).unwrap();
+    sender.send_urgent(3).unwrap();
+
+    let h = event_loop.run(RecordHandler::new(4))
+        .ok().expect("failed to execute event loop");
+
+    // Urgent messages overtake the pending ones and keep their own order
+    assert!(h.msgs == vec![2, 3, 0, 1], "actual={}", h.msgs);
+}
+
+#[test]
+pub fn test_notify_dropped_on_shutdown() {
+    debug!("Starting TEST_NOTIFY_DROPPED_ON_SHUTDOWN");
+    let mut event_loop = bounded_event_loop(4);
+    let sender = event_loop.channel();
+
+    sender.send(0).unwrap();
+    sender.send(1).unwrap();
+
+    // Requested before the event loop runs, so no message is delivered
+    // through `notify`
+    event_loop.shutdown_handle().shutdown().unwrap();
+
+    let h = event_loop.run(RecordHandler::new(2))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs.is_empty(), "actual={}", h.msgs);
+    assert!(h.dropped == vec![0, 1], "actual={}", h.dropped);
+    assert!(h.shutdown == 1, "actual={}", h.shutdown);
+    assert!(sender.len() == 0, "actual={}", sender.len());
+
+    // The drain leaves the queue in a consistent state, so the event loop
+    // can run again
+    sender.send(2).unwrap();
+
+    let h = event_loop.run(RecordHandler::new(1))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs == vec![2], "actual={}", h.msgs);
+    assert!(h.dropped.is_empty(), "actual={}", h.dropped);
+}
+
+#[test]
+pub fn test_shutdown_handle() {
+    debug!("Starting TEST_SHUTDOWN_HANDLE");
+    let config = EventLoopConfig { io_poll_timeout_ms: 10_000, .. Default::default() };
+    let mut event_loop: EventLoop<uint, uint> = EventLoop::configured(config).unwrap();
+    let handle = event_loop.shutdown_handle();
+
+    spawn(proc() {
+        sleep(Duration::milliseconds(100));
+        handle.shutdown().unwrap();
+    });
+
+    // The handle wakes the event loop up long before the poll timeout
+    let start = MonotonicInstant::now();
+    let h = event_loop.run(RecordHandler::new(1))
+        .ok().expect("failed to execute event loop");
+    let elapsed = MonotonicInstant::now().duration_since(start);
+
+    assert!(h.shutdown == 1, "actual={}", h.shutdown);
+    assert!(elapsed < Duration::milliseconds(5_000), "actual={}", elapsed);
+
+    // The shutdown request does not stick, the event loop can run again
+    event_loop.channel().send(0).unwrap();
+
+    let h = event_loop.run(RecordHandler::new(1))
+        .ok().expect("failed to execute event loop");
+
+    assert!(h.msgs == vec![0], "actual={}", h.msgs);
+}
@@ -2,7 +2,8 @@ use mio::*;
 use mio::net::*;
 use mio::net::tcp::*;
 use mio::event::*;
-use super::localhost;
+use super::{accept, localhost};
+use std::io::timer::sleep;
 use std::time::Duration;
 
 const SERVER: Token = Token(0);
@@ -30,7 +31,7 @@ impl Handler<uint, ()> for TestHandler {
     fn readable(&mut self, event_loop: &mut TestEventLoop, token: Token, _: ReadHint) {
         match token {
             SERVER => {
-                let mut sock = self.server.accept().unwrap().unwrap();
+                let (mut sock, _) = self.server.accept().unwrap().unwrap();
                 sock.write(&mut buf::wrap("foobar".as_bytes())).unwrap();
             }
             CLIENT => {
@@ -88,3 +89,146 @@ pub fn test_register_deregister() {
 
     assert!(handler.state == 2, "unexpected final state {}", handler.state);
 }
+
+const FIRST: Token = Token(1);
+const SECOND: Token = Token(2);
+const UNKNOWN: Token = Token(99);
+
+struct ReuseHandler {
+    server: TcpAcceptor,
+    first: Option<TcpSocket>,
+    second: Option<TcpSocket>,
+}
+
+impl Handler<uint, ()> for ReuseHandler {
+    fn readable(&mut self, event_loop: &mut TestEventLoop, token: Token, _: ReadHint) {
+        match token {
+            FIRST => {
+                // A token the event loop does not know about was never
+                // deregistered
+                assert!(!event_loop.is_deregistered(UNKNOWN));
+
+                // Close the connection, then accept another one, which gets
+                // the same descriptor, all while dispatching
+                event_loop.deregister(self.first.as_ref().unwrap()).unwrap();
+                self.first = None;
+
+                let sock = accept(&mut self.server);
+                event_loop.register_opt(&sock, SECOND, READABLE, LEVEL).unwrap();
+                self.second = Some(sock);
+
+                // The first token no longer has a descriptor, yet its
+                // remaining events are still discarded
+                assert!(event_loop.is_deregistered(FIRST));
+                assert!(!event_loop.is_deregistered(SECOND));
+            }
+            SECOND => event_loop.shutdown(),
+            _ => panic!("unexpected token {}", token),
+        }
+    }
+
+    fn writable(&mut self, _event_loop: &mut TestEventLoop, token: Token) {
+        panic!("writable called for a deregistered token; token={}", token);
+    }
+}
+
+#[test]
+pub fn test_register_reused_fd_while_dispatching() {
+    debug!("Starting TEST_REGISTER_REUSED_FD_WHILE_DISPATCHING");
+    let mut event_loop = EventLoop::new().unwrap();
+
+    let addr = SockAddr::parse(localhost().as_slice())
+        .expect("could not parse InetAddr");
+
+    let server = TcpSocket::v4().unwrap();
+    server.set_reuseaddr(true).unwrap();
+
+    let mut server = server.bind(&addr).unwrap().listen(256).unwrap();
+
+    let mut client1 = TcpSocket::v4().unwrap();
+    client1.connect(&addr).unwrap();
+    let first = accept(&mut server);
+
+    let mut client2 = TcpSocket::v4().unwrap();
+    client2.connect(&addr).unwrap();
+
+    // Let both connections complete
+    sleep(Duration::milliseconds(50));
+
+    client1.write(&mut buf::wrap(b"first")).unwrap();
+    client2.write(&mut buf::wrap(b"second")).unwrap();
+
+    // Let the data arrive, so the first event for the connection is both
+    // readable and writable
+    sleep(Duration::milliseconds(50));
+    event_loop.register_opt(&first, FIRST, READABLE | WRITABLE, LEVEL).unwrap();
+
+    let handler = event_loop.run(ReuseHandler { server: server, first: Some(first), second: None })
+        .ok().expect("failed to execute event loop");
+
+    assert!(handler.first.is_none());
+    assert!(handler.second.is_some());
+}
+
+const SHARED: Token = Token(3);
+const SINGLE: Token = Token(4);
+
+struct ErrorHandler {
+    errors: Vec<Token>,
+    ready_errors: Vec<Token>,
+}
+
+impl Handler<uint, ()> for ErrorHandler {
+    fn ready(&mut self, _event_loop: &mut TestEventLoop, token: Token, events: IoEvent) {
+        if events.is_error() {
+            self.ready_errors.push(token);
+        }
+    }
+
+    fn error(&mut self, _event_loop: &mut TestEventLoop, token: Token, _error: MioError) {
+        self.errors.push(token);
+    }
+}
+
+#[test]
+pub fn test_socket_error_shared_token() {
+    debug!("Starting TEST_SOCKET_ERROR_SHARED_TOKEN");
+    let mut event_loop = EventLoop::new().unwrap();
+    let mut handler = ErrorHandler { errors: vec![], ready_errors: vec![] };
+
+    // Nothing listens on the address, the connections are refused
+    let addr = SockAddr::parse(localhost().as_slice())
+        .expect("could not parse InetAddr");
+
+    let shared1 = TcpSocket::v4().unwrap();
+    let shared2 = TcpSocket::v4().unwrap();
+    let single = TcpSocket::v4().unwrap();
+
+    event_loop.register_opt(&shared1, SHARED, WRITABLE, LEVEL).unwrap();
+    event_loop.register_opt(&shared2, SHARED, WRITABLE, LEVEL).unwrap();
+    event_loop.register_opt(&single, SINGLE, WRITABLE, EDGE).unwrap();
+
+    shared1.connect(&addr).unwrap();
+    shared2.connect(&addr).unwrap();
+    single.connect(&addr).unwrap();
+
+    for _ in range(0u, 10) {
+        event_loop.run_once(&mut handler).unwrap();
+
+        if !handler.errors.is_empty() && !handler.ready_errors.is_empty() {
+            break;
+        }
+    }
+
+    // The error of a socket with its own token is fetched and reported
+    assert!(handler.errors.as_slice() == [SINGLE].as_slice(), "actual={}", handler.errors);
+    assert!(single.take_socket_error().unwrap().is_none());
+
+    // The error of sockets sharing a token is passed on to ready and left
+    // for the handler to fetch from each socket
+    assert!(handler.ready_errors.iter().all(|t| *t == SHARED), "actual={}", handler.ready_errors);
+
+    sleep(Duration::milliseconds(50));
+    assert!(shared1.take_socket_error().unwrap().is_some());
+    assert!(shared2.take_socket_error().unwrap().is_some());
+}
@@ -0,0 +1,81 @@
+use mio::*;
+use mio::buf;
+use mio::net::*;
+use mio::net::tcp::*;
+use super::{accept, localhost};
+use std::io::timer::sleep;
+use std::time::Duration;
+
+fn connected_pair() -> (TcpSocket, TcpSocket) {
+    let addr = SockAddr::parse(localhost().as_slice())
+        .expect("could not parse InetAddr");
+
+    let server = TcpSocket::v4().unwrap();
+    server.set_reuseaddr(true).unwrap();
+
+    let mut server = server.bind(&addr).unwrap().listen(256).unwrap();
+
+    let client = TcpSocket::v4().unwrap();
+    client.connect(&addr).unwrap();
+
+    // Let the connection complete
+    sleep(Duration::milliseconds(50));
+
+    let conn = accept(&mut server);
+    (client, conn)
+}
+
+#[test]
+pub fn test_tcp_peek() {
+    debug!("Starting TEST_TCP_PEEK");
+    let (mut client, mut conn) = connected_pair();
+
+    client.write(&mut buf::wrap(b"hello")).unwrap().unwrap();
+
+    sleep(Duration::milliseconds(50));
+
+    // Peeking does not consume the bytes, the next peek and read see them
+    // again
+    let mut small = [0u8, ..3];
+
+    for _ in range(0u, 2) {
+        let cnt = conn.peek(&mut buf::wrap_mut(&mut small)).unwrap().unwrap();
+        assert!(small.slice_to(cnt) == b"hel", "actual={}", small.slice_to(cnt));
+    }
+
+    let mut data = [0u8, ..16];
+
+    let cnt = conn.read(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"hello", "actual={}", data.slice_to(cnt));
+
+    assert!(conn.peek(&mut buf::wrap_mut(&mut data)).unwrap().would_block());
+}
+
+#[test]
+pub fn test_tcp_shutdown_write() {
+    debug!("Starting TEST_TCP_SHUTDOWN_WRITE");
+    let (mut client, mut conn) = connected_pair();
+
+    client.write(&mut buf::wrap(b"hello")).unwrap().unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    sleep(Duration::milliseconds(50));
+
+    // The data sent before the FIN is still delivered, then EOF
+    let mut data = [0u8, ..16];
+    let cnt = conn.read(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"hello", "actual={}", data.slice_to(cnt));
+
+    match conn.read(&mut buf::wrap_mut(&mut data)) {
+        Err(e) => assert!(e.is_eof(), "actual={}", e),
+        ret => panic!("expected EOF; actual={}", ret)
+    }
+
+    // The other direction stays open
+    conn.write(&mut buf::wrap(b"world")).unwrap().unwrap();
+
+    sleep(Duration::milliseconds(50));
+
+    let cnt = client.read(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"world", "actual={}", data.slice_to(cnt));
+}
@@ -3,6 +3,8 @@ use mio::net::*;
 use mio::net::tcp::*;
 use super::localhost;
 use mio::event as evt;
+use std::default::Default;
+use std::io::timer::sleep;
 use std::time::Duration;
 
 use self::TestState::{Initial, AfterRead, AfterHup};
@@ -40,7 +42,7 @@ impl Handler<TcpSocket, ()> for TestHandler {
         match tok {
             SERVER => {
                 debug!("server connection ready for accept");
-                let conn = self.srv.accept().unwrap().unwrap();
+                let (conn, _) = self.srv.accept().unwrap().unwrap();
                 event_loop.timeout(conn, Duration::milliseconds(200)).unwrap();
 
                 event_loop.reregister(&self.srv, SERVER, evt::READABLE, evt::EDGE).unwrap();
@@ -136,3 +138,117 @@ pub fn test_timer() {
 
     assert!(handler.state == AfterHup, "actual={}", handler.state);
 }
+
+struct TimerFdHandler {
+    fired: uint
+}
+
+impl Handler<uint, ()> for TimerFdHandler {
+    fn notify(&mut self, _event_loop: &mut EventLoop<uint, ()>, _msg: ()) {
+    }
+
+    fn timeout(&mut self, event_loop: &mut EventLoop<uint, ()>, _timeout: uint) {
+        self.fired += 1;
+        event_loop.shutdown();
+    }
+}
+
+fn timer_fd_config() -> EventLoopConfig {
+    // The poll timeout is long enough that only the timer fd can wake the
+    // loop up in time
+    EventLoopConfig {
+        io_poll_timeout_ms: 2_000,
+        timer_tick_ms: 10,
+        timer_fd: true,
+        .. Default::default()
+    }
+}
+
+#[test]
+pub fn test_timer_fd() {
+    debug!("Starting TEST_TIMER_FD");
+    let mut event_loop = EventLoop::configured(timer_fd_config()).unwrap();
+
+    event_loop.timeout_ms(1, 50).unwrap();
+
+    let start = MonotonicInstant::now();
+    let handler = event_loop.run(TimerFdHandler { fired: 0 })
+        .ok().expect("failed to execute event loop");
+    let elapsed = MonotonicInstant::now().duration_since(start);
+
+    assert!(handler.fired == 1, "actual={}", handler.fired);
+    assert!(elapsed < Duration::milliseconds(1_000), "actual={}", elapsed);
+}
+
+#[test]
+pub fn test_timer_fd_precise() {
+    debug!("Starting TEST_TIMER_FD_PRECISE");
+    let config = EventLoopConfig { timer_tick_ms: 100, .. timer_fd_config() };
+    let mut event_loop = EventLoop::configured(config).unwrap();
+
+    event_loop.timeout_ms(1, 500).unwrap();
+    event_loop.timeout_precise(2, Duration::microseconds(2_500)).unwrap();
+
+    // The precise timeout fires well before the first 100ms tick
+    let start = MonotonicInstant::now();
+    let handler = event_loop.run(TimerFdHandler { fired: 0 })
+        .ok().expect("failed to execute event loop");
+    let elapsed = MonotonicInstant::now().duration_since(start);
+
+    assert!(handler.fired == 1, "actual={}", handler.fired);
+    assert!(elapsed >= Duration::milliseconds(2), "actual={}", elapsed);
+    assert!(elapsed < Duration::milliseconds(50), "actual={}", elapsed);
+}
+
+#[test]
+pub fn test_timer_fd_disarmed() {
+    debug!("Starting TEST_TIMER_FD_DISARMED");
+    let mut event_loop = EventLoop::configured(timer_fd_config()).unwrap();
+    let mut handler = TimerFdHandler { fired: 0 };
+
+    let timeout = event_loop.timeout_ms(1, 100).unwrap();
+
+    // Wake the loop up while the timer fd is armed for the timeout
+    let sender = event_loop.channel();
+
+    spawn(proc() {
+        sleep(Duration::milliseconds(20));
+        sender.send(()).unwrap();
+    });
+
+    event_loop.run_once(&mut handler).unwrap();
+    assert!(event_loop.clear_timeout(timeout));
+
+    // With the only timeout cleared, the stale deadline must not wake the
+    // loop up, only the poll timeout does
+    let start = MonotonicInstant::now();
+    event_loop.run_once(&mut handler).unwrap();
+    let elapsed = MonotonicInstant::now().duration_since(start);
+
+    assert!(handler.fired == 0, "actual={}", handler.fired);
+    assert!(elapsed > Duration::milliseconds(1_000), "actual={}", elapsed);
+}
+
+#[test]
+pub fn test_timeout_at_before_event_loop() {
+    debug!("Starting TEST_TIMEOUT_AT_BEFORE_EVENT_LOOP");
+    let before = MonotonicInstant::now();
+    sleep(Duration::milliseconds(20));
+
+    let config = EventLoopConfig { timer_tick_ms: 10, io_poll_timeout_ms: 100, .. Default::default() };
+    let mut event_loop = EventLoop::configured(config).unwrap();
+    let mut handler = TimerFdHandler { fired: 0 };
+
+    // A deadline before the timer started is due on the next tick
+    event_loop.timeout_at(1, before).unwrap();
+
+    for _ in range(0u, 10) {
+        event_loop.run_once(&mut handler).unwrap();
+
+        if handler.fired > 0 {
+            break;
+        }
+    }
+
+    assert!(handler.fired == 1, "actual={}", handler.fired);
+}
@@ -0,0 +1,116 @@
+use mio::*;
+use mio::buf;
+use mio::net::*;
+use mio::net::udp::*;
+use super::localhost;
+use std::io::timer::sleep;
+use std::time::Duration;
+
+fn bind_udp(addr: &str) -> UdpSocket {
+    let sock = UdpSocket::v4().unwrap();
+    sock.bind(&SockAddr::parse(addr).expect("could not parse InetAddr")).unwrap();
+    sock
+}
+
+#[test]
+pub fn test_udp_send_multi_recv_multi() {
+    debug!("Starting TEST_UDP_SEND_MULTI_RECV_MULTI");
+    let send_addr = localhost();
+    let recv_addr = localhost();
+
+    let sender = bind_udp(send_addr.as_slice());
+    let receiver = bind_udp(recv_addr.as_slice());
+
+    let payloads: [&[u8], ..3] = [b"a", b"hello", b"world!!"];
+    let msgs: Vec<MsgBuf> = payloads.iter().map(|payload| {
+        MsgBuf::from_bytes(*payload, SockAddr::parse(recv_addr.as_slice()))
+    }).collect();
+
+    let cnt = sender.send_multi(msgs.as_slice()).unwrap().unwrap();
+    assert!(cnt == 3, "actual={}", cnt);
+
+    sleep(Duration::milliseconds(50));
+
+    // One buffer more than there are datagrams, it is left untouched
+    let mut bufs = Vec::from_fn(4, |_| MsgBuf::with_capacity(64));
+    let cnt = receiver.recv_multi(bufs.as_mut_slice()).unwrap().unwrap();
+    assert!(cnt == 3, "actual={}", cnt);
+
+    // Each datagram keeps its own size and the sender's address
+    for (msg, payload) in bufs.iter().zip(payloads.iter()) {
+        assert!(msg.bytes() == *payload, "actual={}", msg.bytes());
+
+        let src = format!("{}", msg.addr().expect("missing source address"));
+        assert!(src == send_addr, "actual={}", src);
+    }
+
+    assert!(bufs[3].bytes().is_empty());
+    assert!(bufs[3].addr().is_none());
+
+    // Nothing else is queued
+    assert!(receiver.recv_multi(bufs.as_mut_slice()).unwrap().would_block());
+}
+
+#[test]
+pub fn test_udp_connected_send_recv() {
+    debug!("Starting TEST_UDP_CONNECTED_SEND_RECV");
+    let a_addr = localhost();
+    let b_addr = localhost();
+
+    let a = bind_udp(a_addr.as_slice());
+    let b = bind_udp(b_addr.as_slice());
+    let mut other = bind_udp(localhost().as_slice());
+
+    a.connect(&SockAddr::parse(b_addr.as_slice()).unwrap()).unwrap();
+    b.connect(&SockAddr::parse(a_addr.as_slice()).unwrap()).unwrap();
+
+    let peer = format!("{}", a.peer_addr().unwrap());
+    assert!(peer == b_addr, "actual={}", peer);
+
+    // A connected socket only accepts datagrams from its peer
+    let b_sock_addr = SockAddr::parse(b_addr.as_slice()).unwrap();
+    other.send_to(&mut buf::wrap(b"ignored"), &b_sock_addr).unwrap().unwrap();
+
+    assert!(a.send(&mut buf::wrap(b"hello")).unwrap().unwrap() == 5);
+    assert!(a.send(&mut buf::wrap(b"")).unwrap().unwrap() == 0);
+
+    sleep(Duration::milliseconds(50));
+
+    let mut data = [0u8, ..16];
+    let cnt = b.recv(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"hello", "actual={}", data.slice_to(cnt));
+
+    // An empty datagram is not mistaken for EOF
+    assert!(b.recv(&mut buf::wrap_mut(&mut data)).unwrap().unwrap() == 0);
+
+    assert!(b.recv(&mut buf::wrap_mut(&mut data)).unwrap().would_block());
+}
+
+#[test]
+pub fn test_udp_peek_from() {
+    debug!("Starting TEST_UDP_PEEK_FROM");
+    let send_addr = localhost();
+    let recv_addr = localhost();
+
+    let mut sender = bind_udp(send_addr.as_slice());
+    let mut receiver = bind_udp(recv_addr.as_slice());
+
+    let dst = SockAddr::parse(recv_addr.as_slice()).unwrap();
+    sender.send_to(&mut buf::wrap(b"hello"), &dst).unwrap().unwrap();
+
+    sleep(Duration::milliseconds(50));
+
+    // Peeking leaves the datagram queued, a short buffer only sees its start
+    let mut small = [0u8, ..3];
+    let src = receiver.peek_from(&mut buf::wrap_mut(&mut small)).unwrap().unwrap();
+    assert!(format!("{}", src) == send_addr, "actual={}", src);
+    assert!(small.as_slice() == b"hel", "actual={}", small.as_slice());
+
+    let mut data = [0u8, ..16];
+
+    let src = receiver.recv_from(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(format!("{}", src) == send_addr, "actual={}", src);
+    assert!(data.slice_to(5) == b"hello", "actual={}", data.slice_to(5));
+
+    assert!(receiver.peek_from(&mut buf::wrap_mut(&mut data)).unwrap().would_block());
+}
@@ -0,0 +1,159 @@
+use std::io::{File, TempDir};
+use std::os::unix::AsRawFd;
+use libc;
+use mio::*;
+use mio::buf;
+use mio::net::*;
+use mio::net::pipe::*;
+
+#[test]
+pub fn test_unix_socket_send_fds() {
+    debug!("Starting TEST_UNIX_SOCKET_SEND_FDS");
+    let tmp_dir = TempDir::new("test_unix_socket_send_fds").unwrap();
+    let path = tmp_dir.path().join("file");
+    let file = File::create(&path).unwrap();
+
+    let (a, b) = UnixSocket::pair().unwrap();
+
+    let cnt = a.send_fds(&[file.as_raw_fd()], &mut buf::wrap(b"x")).unwrap().unwrap();
+    assert!(cnt == 1, "actual={}", cnt);
+
+    let mut data = [0u8, ..16];
+    let mut fds = [-1, ..1];
+    let res = b.recv_fds(&mut buf::wrap_mut(&mut data), &mut fds).unwrap().unwrap();
+
+    assert!(res.bytes == 1 && res.fds == 1 && !res.truncated, "actual={}", res);
+    assert!(data[0] == b'x');
+    assert!(fds[0] >= 0 && fds[0] != file.as_raw_fd());
+
+    // The received descriptor refers to the same open file
+    let msg = b"passed";
+
+    unsafe {
+        let res = libc::write(fds[0], msg.as_ptr() as *const libc::c_void, msg.len() as libc::size_t);
+        assert!(res == msg.len() as libc::ssize_t);
+        libc::close(fds[0]);
+    }
+
+    drop(file);
+    assert!(File::open(&path).read_to_end().unwrap().as_slice() == msg);
+}
+
+#[test]
+pub fn test_unix_socket_recv_fds_truncated() {
+    debug!("Starting TEST_UNIX_SOCKET_RECV_FDS_TRUNCATED");
+    let tmp_dir = TempDir::new("test_unix_socket_recv_fds_truncated").unwrap();
+    let file = File::create(&tmp_dir.path().join("file")).unwrap();
+    let fd = file.as_raw_fd();
+
+    let (a, b) = UnixSocket::pair().unwrap();
+
+    // More descriptors than the receiver has room for, some of which fit
+    // into the control buffer's alignment padding
+    a.send_fds(&[fd, fd, fd, fd], &mut buf::wrap(b"x")).unwrap().unwrap();
+
+    let mut data = [0u8, ..16];
+    let mut fds = [-1, ..1];
+    let res = b.recv_fds(&mut buf::wrap_mut(&mut data), &mut fds).unwrap().unwrap();
+
+    assert!(res.bytes == 1 && res.fds == 1 && res.truncated, "actual={}", res);
+
+    unsafe { libc::close(fds[0]); }
+}
+
+#[test]
+pub fn test_unix_datagram_send_to_recv_from() {
+    debug!("Starting TEST_UNIX_DATAGRAM_SEND_TO_RECV_FROM");
+    let tmp_dir = TempDir::new("test_unix_datagram_send_to_recv_from").unwrap();
+    let a_path = tmp_dir.path().join("a");
+    let b_path = tmp_dir.path().join("b");
+
+    let mut a = UnixDatagram::bind(&SockAddr::from_path(a_path.clone())).unwrap();
+    let mut b = UnixDatagram::bind(&SockAddr::from_path(b_path.clone())).unwrap();
+
+    let b_addr = SockAddr::from_path(b_path);
+    a.send_to(&mut buf::wrap(b"hello"), &b_addr).unwrap().unwrap();
+    a.send_to(&mut buf::wrap(b"world!"), &b_addr).unwrap().unwrap();
+
+    // Each datagram is received on its own, along with the sender's path
+    let mut data = [0u8, ..16];
+    let msgs: [&[u8], ..2] = [b"hello", b"world!"];
+
+    for msg in msgs.iter() {
+        let cnt = {
+            let mut dst = buf::wrap_mut(&mut data);
+            let addr = b.recv_from(&mut dst).unwrap().unwrap();
+
+            match addr {
+                SockAddr::UnixAddr(ref path) => assert!(*path == a_path, "actual={}", path.display()),
+                _ => panic!("unexpected address; addr={}", addr)
+            }
+
+            16 - dst.remaining()
+        };
+
+        assert!(data.slice_to(cnt) == *msg, "actual={}", data.slice_to(cnt));
+    }
+
+    // Nothing else is queued
+    assert!(b.recv_from(&mut buf::wrap_mut(&mut data)).unwrap().would_block());
+}
+
+#[test]
+pub fn test_unix_datagram_recv_from_unbound() {
+    debug!("Starting TEST_UNIX_DATAGRAM_RECV_FROM_UNBOUND");
+    let tmp_dir = TempDir::new("test_unix_datagram_recv_from_unbound").unwrap();
+    let path = tmp_dir.path().join("sock");
+
+    let mut sender = UnixDatagram::new().unwrap();
+    let mut receiver = UnixDatagram::bind(&SockAddr::from_path(path.clone())).unwrap();
+
+    sender.send_to(&mut buf::wrap(b"hello"), &SockAddr::from_path(path)).unwrap().unwrap();
+
+    // The sender has no address, it is reported as an empty path
+    let mut data = [0u8, ..16];
+    let addr = receiver.recv_from(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+
+    match addr {
+        SockAddr::UnixAddr(ref path) => assert!(*path == Path::new(""), "actual={}", path.display()),
+        _ => panic!("unexpected address; addr={}", addr)
+    }
+
+    assert!(data.slice_to(5) == b"hello");
+}
+
+#[test]
+pub fn test_unix_seqpacket_message_boundaries() {
+    debug!("Starting TEST_UNIX_SEQPACKET_MESSAGE_BOUNDARIES");
+    let tmp_dir = TempDir::new("test_unix_seqpacket_message_boundaries").unwrap();
+    let addr = SockAddr::from_path(tmp_dir.path().join("sock"));
+
+    let mut srv = UnixSocket::seqpacket().unwrap()
+        .bind(&addr).unwrap()
+        .listen(256u).unwrap();
+
+    let mut client = UnixSocket::seqpacket().unwrap();
+    client.connect(&addr).unwrap();
+
+    let mut conn = srv.accept().unwrap().unwrap();
+
+    client.write(&mut buf::wrap(b"hello")).unwrap().unwrap();
+    client.write(&mut buf::wrap(b"truncated")).unwrap().unwrap();
+    client.write(&mut buf::wrap(b"world")).unwrap().unwrap();
+
+    // A read never returns more than one message, even with room for all
+    // of them
+    let mut data = [0u8, ..64];
+    let cnt = conn.read(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"hello", "actual={}", data.slice_to(cnt));
+
+    // The part of a message that does not fit is discarded
+    let mut small = [0u8, ..4];
+    let cnt = conn.read(&mut buf::wrap_mut(&mut small)).unwrap().unwrap();
+    assert!(small.slice_to(cnt) == b"trun", "actual={}", small.slice_to(cnt));
+
+    let cnt = conn.read(&mut buf::wrap_mut(&mut data)).unwrap().unwrap();
+    assert!(data.slice_to(cnt) == b"world", "actual={}", data.slice_to(cnt));
+
+    assert!(conn.read(&mut buf::wrap_mut(&mut data)).unwrap().would_block());
+}
@@ -0,0 +1,77 @@
+use mio::*;
+use mio::net::*;
+use mio::net::tcp::*;
+use mio::event::*;
+use super::{accept, localhost};
+use std::io::timer::sleep;
+use std::time::Duration;
+
+const CLIENT: Token = Token(0);
+
+type TestEventLoop = EventLoop<uint, ()>;
+
+struct ZeroCopyHandler {
+    client: TcpSocket,
+    _server: TcpSocket,
+    completions: Vec<ZeroCopyCompletion>,
+}
+
+impl Handler<uint, ()> for ZeroCopyHandler {
+    fn error_queue(&mut self, event_loop: &mut TestEventLoop, token: Token) {
+        assert_eq!(CLIENT, token);
+
+        loop {
+            match self.client.zerocopy_completion().unwrap() {
+                NonBlock::Ready(completion) => self.completions.push(completion),
+                NonBlock::WouldBlock => break
+            }
+        }
+
+        if !self.completions.is_empty() {
+            event_loop.shutdown();
+        }
+    }
+
+    fn timeout(&mut self, _event_loop: &mut TestEventLoop, _timeout: uint) {
+        panic!("no zero copy completion was reaped");
+    }
+}
+
+#[test]
+pub fn test_zerocopy_completion() {
+    debug!("Starting TEST_ZEROCOPY_COMPLETION");
+    let mut event_loop = EventLoop::new().unwrap();
+
+    let addr = SockAddr::parse(localhost().as_slice())
+        .expect("could not parse InetAddr");
+
+    let server = TcpSocket::v4().unwrap();
+    server.set_reuseaddr(true).unwrap();
+
+    let mut server = server.bind(&addr).unwrap().listen(256).unwrap();
+
+    let client = TcpSocket::v4().unwrap();
+    client.set_zerocopy(true).unwrap();
+    client.connect(&addr).unwrap();
+
+    // Let the connection complete
+    sleep(Duration::milliseconds(50));
+
+    let accepted = accept(&mut server);
+
+    // The completion is only reported through the error queue, nothing can
+    // be read from the socket
+    let data = Vec::from_elem(64 * 1024, 0u8);
+    assert!(!client.send_zerocopy(&mut buf::wrap(data.as_slice())).unwrap().would_block());
+
+    event_loop.register_opt(&client, CLIENT, READABLE, LEVEL).unwrap();
+    event_loop.timeout_ms(0, 5_000).unwrap();
+
+    let handler = event_loop.run(ZeroCopyHandler {
+        client: client,
+        _server: accepted,
+        completions: Vec::new(),
+    }).ok().expect("failed to execute event loop");
+
+    assert_eq!(0, handler.completions[0].first);
+}